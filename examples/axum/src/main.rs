@@ -34,9 +34,9 @@ where
     let new_job = storage.push(input).await;
 
     match new_job {
-        Ok(()) => (
+        Ok(id) => (
             StatusCode::CREATED,
-            "Job was successfully added".to_string(),
+            format!("Job [{id}] was successfully added"),
         ),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
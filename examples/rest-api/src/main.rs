@@ -82,13 +82,13 @@ struct JobId {
 async fn push_job<J, S>(job: web::Json<J>, storage: web::Data<S>) -> HttpResponse
 where
     J: Job + Serialize + DeserializeOwned + 'static,
-    S: Storage<Output = J>,
+    S: Storage<Output = J> + Send,
 {
     let storage = &*storage.into_inner();
     let mut storage = storage.clone();
     let res = storage.push(job.into_inner()).await;
     match res {
-        Ok(()) => HttpResponse::Ok().body("Job added to queue".to_string()),
+        Ok(id) => HttpResponse::Ok().body(format!("Job [{id}] added to queue")),
         Err(e) => HttpResponse::InternalServerError().body(format!("{e}")),
     }
 }
@@ -138,7 +138,22 @@ where
     }
 }
 
+async fn get_job_logs<J, S>(job: web::Path<JobId>, storage: web::Data<S>) -> HttpResponse
+where
+    J: Job + Serialize + DeserializeOwned + 'static,
+    S: Storage<Output = J> + JobStreamExt<J>,
+{
+    let storage = &*storage.into_inner();
+    let mut storage = storage.clone();
+    let logs = storage.job_logs(job.job_id.to_string()).await;
+    match logs {
+        Ok(logs) => HttpResponse::Ok().json(logs),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{e}")),
+    }
+}
+
 trait StorageRest<J>: Storage<Output = J> {
+    #[allow(dead_code)]
     fn name(&self) -> String;
 }
 
@@ -185,7 +200,8 @@ impl StorageApiBuilder {
                     .route("", web::get().to(get_jobs::<J, S>)) // Fetch jobs in queue
                     .route("/workers", web::get().to(get_workers::<J, S>)) // Fetch jobs in queue
                     .route("/job", web::put().to(push_job::<J, S>)) // Allow add jobs via api
-                    .route("/job/{job_id}", web::get().to(get_job::<J, S>)), // Allow fetch specific job
+                    .route("/job/{job_id}", web::get().to(get_job::<J, S>)) // Allow fetch specific job
+                    .route("/job/{job_id}/logs", web::get().to(get_job_logs::<J, S>)), // Fetch the logs of a job
             ),
             list: self.list,
         }
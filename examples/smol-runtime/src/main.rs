@@ -14,7 +14,7 @@ use timer::SmolTimer;
 use tracing::debug;
 
 #[derive(Default, Debug, Clone)]
-struct Reminder(DateTime<Utc>);
+struct Reminder(#[allow(dead_code)] DateTime<Utc>);
 
 impl From<DateTime<Utc>> for Reminder {
     fn from(t: DateTime<Utc>) -> Self {
@@ -37,7 +37,7 @@ struct SmolExecutor;
 impl Executor for SmolExecutor {
     type JoinHandle = ();
     fn spawn(&self, fut: impl Future<Output = ()> + Send + 'static) -> Self::JoinHandle {
-        smol::spawn(async { fut.await }).detach()
+        smol::spawn(fut).detach()
     }
 }
 
@@ -54,6 +54,7 @@ fn main() -> Result<()> {
 
         let schedule = Schedule::from_str("1/1 * * * * *").unwrap();
         let worker = WorkerBuilder::new("daily-cron-worker")
+            .timer(SmolTimer)
             .stream(CronStream::new(schedule, SmolTimer).to_stream())
             .layer(TraceLayer::new())
             .build(job_fn(send_reminder));
@@ -26,6 +26,7 @@ impl Timer for SmolTimer {
     }
 }
 
+#[allow(dead_code)]
 struct SmolTimeout<T: Future> {
     inner: Pin<Box<smol_timeout::Timeout<T>>>,
 }
@@ -16,6 +16,7 @@ use tokio::time::sleep;
 use email_service::Email;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct InvalidEmailError {
     email: String,
 }
@@ -1,26 +1,51 @@
-use std::{error::Error, fmt::Debug, marker::PhantomData};
+use std::{error::Error, fmt::Debug, marker::PhantomData, sync::Arc};
 
-use futures::Stream;
+use futures::{future::BoxFuture, Stream};
 use tower::{
     layer::util::{Identity, Stack},
     Layer, Service, ServiceBuilder,
 };
 
 use crate::{
+    error::JobError,
     job::Job,
     job_fn::{job_fn, JobFn},
     request::JobRequest,
-    worker::{ready::ReadyWorker, Worker, WorkerRef},
+    utils::{timer::TokioTimer, Timer},
+    worker::{
+        ready::{ReadyWorker, Reenqueue},
+        timeout::JobTimeout,
+        Worker, WorkerListener, WorkerListeners, WorkerRef,
+    },
 };
 
 /// An abstract that allows building a [`Worker`].
 /// Usually the output is [`ReadyWorker`] but you can implement your own via [`WorkerFactory`]
-#[derive(Debug)]
 pub struct WorkerBuilder<Job, Source, Middleware> {
     pub(crate) name: String,
     pub(crate) job: PhantomData<Job>,
     pub(crate) layer: ServiceBuilder<Middleware>,
     pub(crate) source: Source,
+    pub(crate) beats: Vec<BoxFuture<'static, ()>>,
+    pub(crate) reenqueue: Option<Reenqueue>,
+    pub(crate) concurrency: Option<usize>,
+    pub(crate) listeners: WorkerListeners,
+    pub(crate) timer: Arc<dyn Timer + Send + Sync>,
+}
+
+impl<Job, Source, Middleware> Debug for WorkerBuilder<Job, Source, Middleware> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerBuilder")
+            .field("name", &self.name)
+            .field("job", &std::any::type_name::<Job>())
+            .field("layer", &std::any::type_name::<Middleware>())
+            .field("source", &std::any::type_name::<Source>())
+            .field("beats", &self.beats.len())
+            .field("reenqueue", &self.reenqueue.is_some())
+            .field("concurrency", &self.concurrency)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
 }
 
 impl WorkerBuilder<(), (), Identity> {
@@ -32,6 +57,11 @@ impl WorkerBuilder<(), (), Identity> {
             layer: ServiceBuilder::new(),
             source: (),
             name: name.into(),
+            beats: Vec::new(),
+            reenqueue: None,
+            concurrency: None,
+            listeners: WorkerListeners::default(),
+            timer: Arc::new(TokioTimer),
         }
     }
 }
@@ -47,6 +77,11 @@ impl<J, S, M> WorkerBuilder<J, S, M> {
             layer: self.layer,
             source: stream,
             name: self.name,
+            beats: self.beats,
+            reenqueue: self.reenqueue,
+            concurrency: self.concurrency,
+            listeners: self.listeners,
+            timer: self.timer,
         }
     }
 
@@ -66,11 +101,39 @@ impl<J, S, M> WorkerBuilder<J, S, M> {
             layer: self.layer,
             source: stream(WorkerRef::new(self.name.clone())),
             name: self.name,
+            beats: self.beats,
+            reenqueue: self.reenqueue,
+            concurrency: self.concurrency,
+            listeners: self.listeners,
+            timer: self.timer,
         }
     }
 }
 
 impl<Job, Stream, Serv> WorkerBuilder<Job, Stream, Serv> {
+    /// Limits the number of jobs this worker runs at the same time.
+    /// Once the limit is reached, the source stream is not polled until a job completes,
+    /// so jobs are left in the source instead of waiting in memory.
+    ///
+    /// By default the number of running jobs is unbounded
+    pub fn concurrency(mut self, max: usize) -> Self {
+        self.concurrency = Some(max.max(1));
+        self
+    }
+
+    /// Sets the [`Timer`] that enforces job timeouts and paces the beats of the worker.
+    /// Defaults to [`TokioTimer`]
+    pub fn timer<T: Timer + Send + Sync + 'static>(mut self, timer: T) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Attach a [`WorkerListener`] that is notified of the worker's events
+    pub fn listener<L: WorkerListener + 'static>(self, listener: L) -> Self {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
     /// Allows of decorating the service that consumes jobs.
     /// Allows adding multiple [`tower`] middleware
     pub fn middleware<NewService>(
@@ -84,6 +147,11 @@ impl<Job, Stream, Serv> WorkerBuilder<Job, Stream, Serv> {
             layer: middleware,
             name: self.name,
             source: self.source,
+            beats: self.beats,
+            reenqueue: self.reenqueue,
+            concurrency: self.concurrency,
+            listeners: self.listeners,
+            timer: self.timer,
         }
     }
     /// Shorthand for decoration. Allows adding a single layer [tower] middleware
@@ -96,31 +164,41 @@ impl<Job, Stream, Serv> WorkerBuilder<Job, Stream, Serv> {
             source: self.source,
             layer: self.layer.layer(layer),
             name: self.name,
+            beats: self.beats,
+            reenqueue: self.reenqueue,
+            concurrency: self.concurrency,
+            listeners: self.listeners,
+            timer: self.timer,
         }
     }
 }
 
-impl<J, S, M, Ser, E, Request> WorkerFactory<J, Ser> for WorkerBuilder<J, S, M>
+impl<J, S, M, Ser, E> WorkerFactory<J, Ser> for WorkerBuilder<J, S, M>
 where
-    S: Stream<Item = Result<Option<Request>, E>> + Send + 'static + Unpin,
+    S: Stream<Item = Result<Option<JobRequest<J>>, E>> + Send + 'static + Unpin,
     J: Job + Send + 'static,
-    M: Layer<Ser>,
-    <M as Layer<Ser>>::Service: Service<Request> + Send + 'static,
+    M: Layer<JobTimeout<Ser>>,
+    <M as Layer<JobTimeout<Ser>>>::Service: Service<JobRequest<J>> + Send + 'static,
     E: Sync + Send + 'static + Error,
-    Request: Send,
-    <<M as Layer<Ser>>::Service as Service<Request>>::Future: std::marker::Send,
-    Ser: Service<Request>,
-    <Ser as Service<Request>>::Error: Debug,
-    <<M as Layer<Ser>>::Service as Service<Request>>::Error: std::fmt::Debug,
-    <<M as Layer<Ser>>::Service as Service<Request>>::Future: 'static,
+    <<M as Layer<JobTimeout<Ser>>>::Service as Service<JobRequest<J>>>::Future: std::marker::Send,
+    Ser: Service<JobRequest<J>>,
+    <Ser as Service<JobRequest<J>>>::Error: Debug + From<JobError>,
+    <<M as Layer<JobTimeout<Ser>>>::Service as Service<JobRequest<J>>>::Error: std::fmt::Debug,
+    <<M as Layer<JobTimeout<Ser>>>::Service as Service<JobRequest<J>>>::Future: 'static,
 {
-    type Worker = ReadyWorker<S, <M as Layer<Ser>>::Service>;
-    /// Convert a worker builder to a worker ready to consume jobs
-    fn build(self, service: Ser) -> ReadyWorker<S, <M as Layer<Ser>>::Service> {
+    type Worker = ReadyWorker<S, <M as Layer<JobTimeout<Ser>>>::Service>;
+    /// Convert a worker builder to a worker ready to consume jobs.
+    /// The timeout of every job is enforced around `service`, see [`JobTimeout`]
+    fn build(self, service: Ser) -> ReadyWorker<S, <M as Layer<JobTimeout<Ser>>>::Service> {
+        let service = JobTimeout::with_shared_timer(service, self.timer);
         ReadyWorker {
             name: self.name,
             stream: self.source,
             service: self.layer.service(service),
+            beats: self.beats,
+            reenqueue: self.reenqueue,
+            concurrency: self.concurrency,
+            listeners: self.listeners,
         }
     }
 }
@@ -141,7 +219,6 @@ pub trait WorkerFactory<J, S> {
 }
 
 /// Helper trait for building new Workers from [`WorkerBuilder`]
-
 pub trait WorkerFactoryFn<J, F> {
     /// The worker build
     type Worker: Worker<J>;
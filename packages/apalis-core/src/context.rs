@@ -1,9 +1,19 @@
 use crate::request::JobState;
 
 use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use http::Extensions;
 use serde::{Deserialize, Serialize};
-use std::{any::Any, marker::Send};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    marker::Send,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+pub use tokio_util::sync::CancellationToken;
+use tower::BoxError;
+use tracing::{warn, Level};
 
 /// The context for a job is represented here
 /// Used to provide a context when a job is defined through the [Job] trait
@@ -15,9 +25,23 @@ pub struct JobContext {
     pub(crate) attempts: i32,
     pub(crate) max_attempts: i32,
     pub(crate) last_error: Option<String>,
+    #[serde(default)]
+    pub(crate) last_failure: Option<JobFailure>,
     pub(crate) lock_at: Option<DateTime<Utc>>,
     pub(crate) lock_by: Option<String>,
     pub(crate) done_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) timeout: Option<Duration>,
+    #[serde(default)]
+    pub(crate) priority: i32,
+    #[serde(default)]
+    pub(crate) progress: Option<JobProgress>,
+    #[serde(skip)]
+    pub(crate) cancellation: CancellationToken,
+    #[serde(skip)]
+    pub(crate) progress_reporter: ProgressReporter,
+    #[serde(skip)]
+    pub(crate) logger: JobLogger,
     #[serde(skip)]
     pub(crate) data: Data,
 }
@@ -44,7 +68,14 @@ impl JobContext {
             attempts: 0,
             max_attempts: 25,
             last_error: None,
+            last_failure: None,
             lock_by: None,
+            timeout: None,
+            priority: 0,
+            progress: None,
+            cancellation: CancellationToken::new(),
+            progress_reporter: ProgressReporter::default(),
+            logger: JobLogger::default(),
             data: Data::default(),
         }
     }
@@ -94,6 +125,26 @@ impl JobContext {
         self.max_attempts
     }
 
+    /// Get the timeout provided when the job was pushed, if any
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set how long a single attempt of the job may run
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Get the priority of the job. Jobs with a higher priority are served first. Default 0
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Set the priority of the job
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
     /// Get the id for a job
     pub fn id(&self) -> String {
         self.id.clone()
@@ -168,4 +219,322 @@ impl JobContext {
     pub fn set_last_error(&mut self, error: String) {
         self.last_error = Some(error);
     }
+
+    /// Get the details of the last failed attempt, if any
+    pub fn last_failure(&self) -> &Option<JobFailure> {
+        &self.last_failure
+    }
+
+    /// Set the details of the last failed attempt
+    pub fn set_last_failure(&mut self, failure: Option<JobFailure>) {
+        self.last_failure = failure;
+    }
+
+    /// Get the token tripped once the running job is cancelled with [Storage::cancel].
+    ///
+    /// Cancellation is cooperative: the worker trips the token on its next keep-alive,
+    /// and long running jobs should check it or race it against their work.
+    /// A job that fails after the token is tripped is not retried.
+    ///
+    /// ```
+    /// # use apalis_core::context::JobContext;
+    /// let ctx = JobContext::new(1.to_string());
+    /// assert!(!ctx.cancellation_token().is_cancelled());
+    /// ```
+    ///
+    /// [Storage::cancel]: crate::storage::Storage::cancel
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Get the progress last reported by the job, if any
+    pub fn progress(&self) -> &Option<JobProgress> {
+        &self.progress
+    }
+
+    /// Set the progress of the job
+    pub fn set_progress(&mut self, progress: Option<JobProgress>) {
+        self.progress = progress;
+    }
+
+    /// Get the handle the running job reports its progress through.
+    ///
+    /// Reports are only written to the storage when the job is consumed from one,
+    /// see [ProgressReporter] for how they are throttled.
+    ///
+    /// ```
+    /// # use apalis_core::context::JobContext;
+    /// # async fn import(ctx: JobContext) {
+    /// for batch in 0..10u8 {
+    ///     // ... import the batch
+    ///     ctx.progress_reporter()
+    ///         .report((batch + 1) * 10, Some(format!("imported batch {batch}")))
+    ///         .await;
+    /// }
+    /// # }
+    /// ```
+    pub fn progress_reporter(&self) -> &ProgressReporter {
+        &self.progress_reporter
+    }
+
+    /// Set the handle the running job reports its progress through
+    pub fn set_progress_reporter(&mut self, reporter: ProgressReporter) {
+        self.progress_reporter = reporter;
+    }
+
+    /// Get the logger of the running job.
+    ///
+    /// Lines are always emitted as [tracing] events. When the job is consumed from a storage
+    /// that captures logs, they are also stored with the job, see [JobLogger].
+    ///
+    /// ```
+    /// # use apalis_core::context::JobContext;
+    /// # async fn charge(ctx: JobContext) {
+    /// ctx.logger().info("charging card");
+    /// ctx.logger().warn("payment gateway is slow, retrying");
+    /// # }
+    /// ```
+    pub fn logger(&self) -> &JobLogger {
+        &self.logger
+    }
+
+    /// Set the logger of the running job
+    pub fn set_logger(&mut self, logger: JobLogger) {
+        self.logger = logger;
+    }
+}
+
+/// A line logged by a job through its [JobLogger]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobLogLine {
+    /// The level of the line, such as `INFO`
+    pub level: String,
+    /// The logged message
+    pub message: String,
+    /// When the line was logged
+    pub logged_at: DateTime<Utc>,
+}
+
+/// Logs lines from a running job, see [JobContext::logger].
+///
+/// A capturing logger keeps up to `max_lines` of the lines logged during the current attempt,
+/// dropping the oldest ones, until they are taken to be stored with the job.
+#[derive(Debug, Clone, Default)]
+pub struct JobLogger {
+    capture: Option<Arc<Mutex<LogBuffer>>>,
+}
+
+#[derive(Debug)]
+struct LogBuffer {
+    lines: VecDeque<JobLogLine>,
+    max_lines: usize,
+}
+
+impl JobLogger {
+    /// Build a logger that keeps up to `max_lines` of the lines it logs
+    pub fn capturing(max_lines: usize) -> Self {
+        JobLogger {
+            capture: Some(Arc::new(Mutex::new(LogBuffer {
+                lines: VecDeque::new(),
+                max_lines,
+            }))),
+        }
+    }
+
+    /// Log `message` at `level`
+    pub fn log<M: Into<String>>(&self, level: Level, message: M) {
+        let message = message.into();
+        match level {
+            Level::ERROR => tracing::error!("{message}"),
+            Level::WARN => tracing::warn!("{message}"),
+            Level::INFO => tracing::info!("{message}"),
+            Level::DEBUG => tracing::debug!("{message}"),
+            Level::TRACE => tracing::trace!("{message}"),
+        }
+        if let Some(capture) = &self.capture {
+            let mut buffer = capture.lock().unwrap();
+            if buffer.lines.len() >= buffer.max_lines {
+                buffer.lines.pop_front();
+            }
+            if buffer.max_lines > 0 {
+                buffer.lines.push_back(JobLogLine {
+                    level: level.to_string(),
+                    message,
+                    logged_at: Utc::now(),
+                });
+            }
+        }
+    }
+
+    /// Log `message` at [Level::ERROR]
+    pub fn error<M: Into<String>>(&self, message: M) {
+        self.log(Level::ERROR, message)
+    }
+
+    /// Log `message` at [Level::WARN]
+    pub fn warn<M: Into<String>>(&self, message: M) {
+        self.log(Level::WARN, message)
+    }
+
+    /// Log `message` at [Level::INFO]
+    pub fn info<M: Into<String>>(&self, message: M) {
+        self.log(Level::INFO, message)
+    }
+
+    /// Log `message` at [Level::DEBUG]
+    pub fn debug<M: Into<String>>(&self, message: M) {
+        self.log(Level::DEBUG, message)
+    }
+
+    /// Take the captured lines, oldest first
+    pub fn take(&self) -> Vec<JobLogLine> {
+        match &self.capture {
+            Some(capture) => capture.lock().unwrap().lines.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// How far a running job has gone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobProgress {
+    /// The share of the job that is done, from 0 to 100
+    pub percent: u8,
+    /// What the job is busy with, if it said so
+    pub message: Option<String>,
+    /// When the progress was reported
+    pub updated_at: DateTime<Utc>,
+}
+
+impl JobProgress {
+    /// Describe a job that is `percent` done, capped at 100
+    pub fn new(percent: u8, message: Option<String>) -> Self {
+        JobProgress {
+            percent: percent.min(100),
+            message,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+type ProgressWriter =
+    Arc<dyn Fn(JobProgress) -> BoxFuture<'static, Result<(), BoxError>> + Send + Sync>;
+
+/// Reports the progress of a running job, see [JobContext::progress_reporter].
+///
+/// Every report is kept, but it is only written if `interval` has passed since the last
+/// write, so that jobs can report from tight loops. A report at 100% is always written,
+/// and the last report is written once the job finishes. Failed writes are logged and skipped.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    writer: Option<ProgressWriter>,
+    interval: Duration,
+    state: Arc<Mutex<ProgressState>>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    latest: Option<JobProgress>,
+    written_at: Option<Instant>,
+    pending: bool,
+}
+
+impl std::fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("interval", &self.interval)
+            .field("latest", &self.latest())
+            .finish()
+    }
+}
+
+impl ProgressReporter {
+    /// Build a reporter that hands reports to `writer`, at most once every `interval`
+    pub fn new<F>(interval: Duration, writer: F) -> Self
+    where
+        F: Fn(JobProgress) -> BoxFuture<'static, Result<(), BoxError>> + Send + Sync + 'static,
+    {
+        ProgressReporter {
+            writer: Some(Arc::new(writer)),
+            interval,
+            state: Default::default(),
+        }
+    }
+
+    /// Report that the job is `percent` done, optionally saying what it is busy with
+    pub async fn report(&self, percent: u8, message: Option<String>) {
+        let progress = JobProgress::new(percent, message);
+        let due = {
+            let mut state = self.state.lock().unwrap();
+            let due = progress.percent == 100
+                || state
+                    .written_at
+                    .map_or(true, |at| at.elapsed() >= self.interval);
+            state.latest = Some(progress.clone());
+            state.pending = !due;
+            if due {
+                state.written_at = Some(Instant::now());
+            }
+            due
+        };
+        if due {
+            self.write(progress).await;
+        }
+    }
+
+    /// Get the progress last reported, if any
+    pub fn latest(&self) -> Option<JobProgress> {
+        self.state.lock().unwrap().latest.clone()
+    }
+
+    /// Write the last report if it was held back by the interval
+    pub async fn flush(&self) {
+        let progress = {
+            let mut state = self.state.lock().unwrap();
+            if !state.pending {
+                return;
+            }
+            state.pending = false;
+            state.written_at = Some(Instant::now());
+            state.latest.clone()
+        };
+        if let Some(progress) = progress {
+            self.write(progress).await;
+        }
+    }
+
+    async fn write(&self, progress: JobProgress) {
+        if let Some(writer) = &self.writer {
+            if let Err(e) = writer(progress).await {
+                warn!("Failed to write job progress: {e}");
+            }
+        }
+    }
+}
+
+/// The details of a failed attempt of a job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobFailure {
+    /// The attempt that failed, starting at 1
+    pub attempt: i32,
+    /// The error the attempt failed with
+    pub message: String,
+    /// The errors that caused it, from the closest to the root cause
+    pub chain: Vec<String>,
+    /// When the attempt failed
+    pub failed_at: DateTime<Utc>,
+}
+
+impl JobFailure {
+    /// Describe a failure of `attempt` with `error` and the chain of its sources
+    pub fn new(attempt: i32, error: &(dyn std::error::Error + 'static)) -> Self {
+        JobFailure {
+            attempt,
+            message: error.to_string(),
+            chain: std::iter::successors(error.source(), |e| e.source())
+                .map(ToString::to_string)
+                .collect(),
+            failed_at: Utc::now(),
+        }
+    }
 }
@@ -27,6 +27,20 @@ pub enum JobError {
     #[error("Error communicating with storage: {0}")]
     Storage(StorageError),
 
+    /// The job did not complete within its timeout.
+    #[error("Job timed out after {0:?}")]
+    TimedOut(std::time::Duration),
+
+    /// The job stopped because it was cancelled, see [JobContext::cancellation_token].
+    ///
+    /// [JobContext::cancellation_token]: crate::context::JobContext::cancellation_token
+    #[error("Job was cancelled")]
+    Cancelled,
+
+    /// The job stream does not implement a feature, eg. job logs
+    #[error("The job stream does not support {0}")]
+    Unsupported(&'static str),
+
     /// A generic IO error
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
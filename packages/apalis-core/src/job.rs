@@ -1,18 +1,62 @@
-use std::{collections::HashMap, fmt::Debug, time::Duration};
+use std::{collections::HashMap, fmt::Debug, str::FromStr, time::Duration};
 
 use crate::{
+    context::JobLogLine,
     error::{JobError, JobStreamError},
     request::{JobRequest, JobState},
 };
 use chrono::{DateTime, Utc};
 use futures::{future::BoxFuture, stream::BoxStream};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 /// Represents a result for a [Job].
 pub type JobFuture<I> = BoxFuture<'static, I>;
 /// Represents a stream for [Job].
 pub type JobStreamResult<T> = BoxStream<'static, Result<Option<JobRequest<T>>, JobStreamError>>;
 
+/// A unique identifier for a job in a storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct JobId(Uuid);
+
+impl JobId {
+    /// Generate a new random [JobId]
+    pub fn new() -> Self {
+        JobId(Uuid::new_v4())
+    }
+
+    /// Get the underlying [Uuid]
+    pub fn inner(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl Default for JobId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Uuid> for JobId {
+    fn from(id: Uuid) -> Self {
+        JobId(id)
+    }
+}
+
+impl FromStr for JobId {
+    type Err = uuid::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(JobId(Uuid::from_str(s)?))
+    }
+}
+
+impl std::fmt::Display for JobId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
 #[derive(Debug)]
 /// Represents a wrapper for a job produced from streams
 pub struct JobRequestWrapper<T>(pub Result<Option<JobRequest<T>>, JobStreamError>);
@@ -29,6 +73,141 @@ pub struct JobRequestWrapper<T>(pub Result<Option<JobRequest<T>>, JobStreamError
 pub trait Job: Sized + Send + Unpin + Sync {
     /// Represents the name for job.
     const NAME: &'static str;
+
+    /// How long a single attempt of the job may run before it is failed.
+    /// A timeout provided when pushing the job takes precedence. Defaults to no timeout
+    const TIMEOUT: Option<Duration> = None;
+
+    /// How long the job waits in storage before it is attempted again after a failure.
+    /// Defaults to [Backoff::none]
+    const BACKOFF: Backoff = Backoff::none();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackoffStrategy {
+    Fixed,
+    Linear,
+    Exponential,
+}
+
+/// How long a failed job waits before it is attempted again, see [Job::BACKOFF].
+///
+/// Storages schedule jobs to the second, so delays are rounded up to whole seconds
+/// and a delay, or a maximum, that is neither zero nor at least a second is rejected.
+///
+/// # Example
+/// ```rust,ignore
+/// impl Job for Email {
+///     const NAME: &'static str = "apalis::Email";
+///     const BACKOFF: Backoff = Backoff::exponential(Duration::from_secs(1))
+///         .with_max(Duration::from_secs(600))
+///         .with_jitter();
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    strategy: BackoffStrategy,
+    delay: Duration,
+    max: Option<Duration>,
+    jitter: bool,
+}
+
+impl Backoff {
+    /// Attempt the job again right away
+    pub const fn none() -> Self {
+        Self::fixed(Duration::ZERO)
+    }
+
+    /// Wait `delay` after every failed attempt
+    ///
+    /// # Panics
+    ///
+    /// If `delay` is shorter than a second, but not zero
+    pub const fn fixed(delay: Duration) -> Self {
+        assert!(
+            delay.is_zero() || delay.as_secs() > 0,
+            "a backoff delay must be zero or at least a second"
+        );
+        Backoff {
+            strategy: BackoffStrategy::Fixed,
+            delay,
+            max: None,
+            jitter: false,
+        }
+    }
+
+    /// Wait `delay` after the first failed attempt, and `delay` longer after each one that follows
+    pub const fn linear(delay: Duration) -> Self {
+        Backoff {
+            strategy: BackoffStrategy::Linear,
+            ..Self::fixed(delay)
+        }
+    }
+
+    /// Wait `delay` after the first failed attempt, and twice as long after each one that follows
+    pub const fn exponential(delay: Duration) -> Self {
+        Backoff {
+            strategy: BackoffStrategy::Exponential,
+            ..Self::fixed(delay)
+        }
+    }
+
+    /// Never wait longer than `max`
+    ///
+    /// # Panics
+    ///
+    /// If `max` is shorter than a second, but not zero
+    pub const fn with_max(self, max: Duration) -> Self {
+        assert!(
+            max.is_zero() || max.as_secs() > 0,
+            "a backoff maximum must be zero or at least a second"
+        );
+        Backoff {
+            max: Some(max),
+            ..self
+        }
+    }
+
+    /// Wait a random duration between half and all of the delay,
+    /// so that jobs failing together are not all attempted again at once
+    pub const fn with_jitter(self) -> Self {
+        Backoff {
+            jitter: true,
+            ..self
+        }
+    }
+
+    /// How long to wait once the job has failed `attempts` times
+    pub fn delay(&self, attempts: i32) -> Duration {
+        let attempts = attempts.max(1) as u32;
+        let delay = match self.strategy {
+            BackoffStrategy::Fixed => self.delay,
+            BackoffStrategy::Linear => self.delay.saturating_mul(attempts),
+            BackoffStrategy::Exponential => {
+                self.delay.saturating_mul(2u32.saturating_pow(attempts - 1))
+            }
+        };
+        let delay = match self.max {
+            Some(max) => delay.min(max),
+            None => delay,
+        };
+        let delay = if self.jitter {
+            delay / 2 + (delay / 2).mul_f64(fastrand::f64())
+        } else {
+            delay
+        };
+        if delay.subsec_nanos() == 0 {
+            delay
+        } else {
+            Duration::from_secs(delay.as_secs().saturating_add(1))
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::none()
+    }
 }
 
 /// Represents a Stream of jobs being consumed by a Worker
@@ -106,4 +285,46 @@ where
         status: &JobState,
         page: i32,
     ) -> Result<Vec<JobRequest<Job>>, JobError>;
+
+    /// Fetch the lines logged by a job, oldest first
+    ///
+    /// The default implementation fails with [JobError::Unsupported]
+    async fn job_logs(&mut self, _job_id: String) -> Result<Vec<JobLogLine>, JobError> {
+        Err(JobError::Unsupported("job logs"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        let second = Duration::from_secs(1);
+        assert_eq!(Backoff::none().delay(3), Duration::ZERO);
+        assert_eq!(Backoff::fixed(second).delay(3), second);
+        assert_eq!(Backoff::linear(second).delay(3), second * 3);
+        assert_eq!(Backoff::exponential(second).delay(1), second);
+        assert_eq!(Backoff::exponential(second).delay(4), second * 8);
+        let capped = Backoff::exponential(second).with_max(second * 5);
+        assert_eq!(capped.delay(10), second * 5);
+        assert_eq!(capped.delay(i32::MAX), second * 5);
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_within_delay() {
+        let backoff = Backoff::fixed(Duration::from_secs(10)).with_jitter();
+        for _ in 0..100 {
+            let delay = backoff.delay(1);
+            assert!(delay >= Duration::from_secs(5));
+            assert!(delay <= Duration::from_secs(10));
+            assert_eq!(delay.subsec_nanos(), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "a backoff delay must be zero or at least a second")]
+    fn test_backoff_rejects_sub_second_delays() {
+        Backoff::fixed(Duration::from_millis(500));
+    }
 }
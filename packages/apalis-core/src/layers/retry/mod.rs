@@ -10,6 +10,11 @@ type Req<T> = JobRequest<T>;
 type Err = JobError;
 
 /// Retries a job instantly until `max_attempts`
+///
+/// The attempts are only kept in memory by the worker running the job. For jobs consumed from
+/// a storage, prefer [`Job::BACKOFF`], which persists each attempt before the job is retried.
+///
+/// [`Job::BACKOFF`]: crate::job::Job::BACKOFF
 #[derive(Clone, Debug)]
 pub struct DefaultRetryPolicy;
 
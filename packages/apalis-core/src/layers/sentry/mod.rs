@@ -27,7 +27,7 @@ pub struct SentryJobLayer;
 impl SentryJobLayer {
     /// Creates a new Layer that only logs Job details.
     pub fn new() -> Self {
-        Self::default()
+        Self
     }
 }
 
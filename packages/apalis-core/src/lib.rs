@@ -83,6 +83,10 @@ pub mod mock {
                 service,
                 stream,
                 name: "test-worker".to_string(),
+                beats: Vec::new(),
+                reenqueue: None,
+                concurrency: None,
+                listeners: Default::default(),
             },
         )
     }
@@ -1,11 +1,16 @@
 use std::{
     fmt::{self, Debug, Formatter},
+    sync::{Arc, RwLock},
     time::Duration,
 };
 
-use futures::{Future, FutureExt};
+use futures::{
+    future::{self, BoxFuture, Either},
+    Future, FutureExt,
+};
 use graceful_shutdown::Shutdown;
 use log::info;
+use tokio::sync::watch;
 use tower::Service;
 use tracing::warn;
 
@@ -13,15 +18,20 @@ use crate::{
     executor::{Executor, TokioExecutor},
     job::Job,
     request::JobRequest,
-    worker::{Worker, WorkerContext},
+    utils::{timer::TokioTimer, Timer},
+    worker::{Worker, WorkerContext, WorkerError, WorkerEvent, WorkerListener, WorkerListeners},
 };
 
 /// A monitor for coordinating and managing a collection of workers.
 pub struct Monitor<E: Executor> {
     shutdown: Shutdown,
+    terminate: Shutdown,
     worker_handles: Vec<(String, E::JoinHandle)>,
     timeout: Option<Duration>,
     executor: E,
+    listeners: WorkerListeners,
+    controls: WorkerControls,
+    timer: Arc<dyn Timer + Send + Sync>,
 }
 
 impl<E: Executor> Debug for Monitor<E> {
@@ -38,11 +48,12 @@ impl<E: Executor> Debug for Monitor<E> {
             )
             .field("timeout", &self.timeout)
             .field("executor", &std::any::type_name::<E>())
+            .field("listeners", &self.listeners.len())
             .finish()
     }
 }
 
-impl<E: Executor + Send + 'static> Monitor<E> {
+impl<E: Executor + Send + Sync + 'static> Monitor<E> {
     /// Registers a worker with the monitor.
     ///
     /// # Arguments
@@ -64,18 +75,19 @@ impl<E: Executor + Send + 'static> Monitor<E> {
     where
         <Serv as Service<JobRequest<J>>>::Future: std::marker::Send,
     {
-        let shutdown = self.shutdown.clone();
         let name = worker.name();
-        let handle = self.executor.spawn(
-            self.shutdown.graceful(
-                worker
-                    .start(WorkerContext {
-                        shutdown,
-                        executor: self.executor.clone(),
-                    })
-                    .map(|_| ()),
-            ),
+        let control = self.controls.add(&name);
+        let (ctx, stopped) = worker_context(
+            &self.shutdown,
+            &self.terminate,
+            &self.executor,
+            &self.listeners,
+            &control,
         );
+        let run = run_worker(worker.start(ctx), stopped).map(|_| ());
+        let handle = self
+            .executor
+            .spawn(self.shutdown.graceful(self.terminate.graceful(run)));
         self.worker_handles.push((name, handle));
         self
     }
@@ -113,6 +125,113 @@ impl<E: Executor + Send + 'static> Monitor<E> {
         self
     }
 
+    /// Registers a worker that is restarted according to `policy` whenever it stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - When and how often the worker is restarted.
+    /// * `factory` - A function that builds a fresh worker instance for every (re)start.
+    ///
+    /// # Returns
+    ///
+    /// The monitor instance, with the supervised worker added to the collection.
+    ///
+    /// # Remarks
+    ///
+    /// Restarts and give-ups are logged and emitted as [`WorkerEvent`]s to the monitor's listeners.
+    /// Workers are never restarted once the monitor is shutting down.
+    pub fn register_with_restart<
+        Strm,
+        Serv: Service<JobRequest<J>>,
+        J: Job + 'static,
+        W: Worker<J, Service = Serv, Source = Strm> + Send + 'static,
+        Call: Fn() -> W + Send + 'static,
+    >(
+        mut self,
+        policy: RestartPolicy,
+        factory: Call,
+    ) -> Self
+    where
+        <Serv as Service<JobRequest<J>>>::Future: std::marker::Send,
+    {
+        let mut first = Some(factory());
+        let name = first.as_ref().map(Worker::name).unwrap_or_default();
+        let shutdown = self.shutdown.clone();
+        let terminate = self.terminate.clone();
+        let executor = self.executor.clone();
+        let listeners = self.listeners.clone();
+        let control = self.controls.add(&name);
+        let timer = self.timer.clone();
+        let worker_name = name.clone();
+        let supervisor = async move {
+            let mut restarts = 0;
+            loop {
+                let worker = first.take().unwrap_or_else(&factory);
+                let (ctx, stopped) =
+                    worker_context(&shutdown, &terminate, &executor, &listeners, &control);
+                let res = run_worker(worker.start(ctx), stopped).await;
+                if shutdown.is_shutting_down() || control.stop.is_shutting_down() {
+                    break;
+                }
+                if let Err(e) = &res {
+                    warn!("Worker {worker_name} failed: {e}");
+                    listeners.emit(&worker_name, WorkerEvent::Error(e.to_string()));
+                }
+                if !policy.should_restart(&res) {
+                    break;
+                }
+                if policy.max_restarts.map_or(false, |max| restarts >= max) {
+                    warn!("Worker {worker_name} stopped after {restarts} restarts, giving up");
+                    listeners.emit(&worker_name, WorkerEvent::GaveUp { restarts });
+                    break;
+                }
+                restarts += 1;
+                let delay = policy.backoff(restarts);
+                info!("Restarting worker {worker_name} in {delay:?} (restart {restarts})");
+                listeners.emit(
+                    &worker_name,
+                    WorkerEvent::Restart {
+                        attempt: restarts,
+                        delay,
+                    },
+                );
+                let wait = control.stop.cancel_on_shutdown(timer.sleep(delay));
+                if shutdown.cancel_on_shutdown(wait).await.flatten().is_none() {
+                    break;
+                }
+            }
+        };
+        let handle = self
+            .executor
+            .spawn(self.shutdown.graceful(self.terminate.graceful(supervisor)));
+        self.worker_handles.push((name, handle));
+        self
+    }
+
+    /// Returns a [`MonitorHandle`] that controls the workers of this monitor while it runs.
+    ///
+    /// Workers registered after the handle is created are controlled by it too.
+    pub fn handle(&self) -> MonitorHandle {
+        MonitorHandle {
+            shutdown: self.shutdown.clone(),
+            controls: self.controls.clone(),
+        }
+    }
+
+    /// Attach a [`WorkerListener`] that is notified of the events of every worker in this monitor
+    pub fn listener<L: WorkerListener + 'static>(self, listener: L) -> Self {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// Sets the [`Timer`] that paces the restarts of supervised workers. Defaults to [`TokioTimer`].
+    ///
+    /// Workers take the timer of the monitor when they are registered, so set it first.
+    pub fn timer<T: Timer + Send + Sync + 'static>(mut self, timer: T) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
     /// Sets a timeout duration for the monitor's shutdown process.
     ///
     /// # Arguments
@@ -137,7 +256,6 @@ impl<E: Executor + Send + 'static> Monitor<E> {
     /// # Errors
     ///
     /// If the monitor fails to shutdown gracefully, an `std::io::Error` will be returned.
-
     pub async fn run_with_signal<S: Future<Output = std::io::Result<()>>>(
         self,
         signal: S,
@@ -159,16 +277,16 @@ impl<E: Executor + Send + 'static> Monitor<E> {
     /// If a timeout has been set using the `shutdown_timeout` method, the monitor
     /// will wait for all workers to complete up to the timeout duration before exiting.
     /// If the timeout is reached and workers have not completed, the monitor will log a warning
-    /// message and exit forcefully.
+    /// message and exit forcefully. Before exiting, workers are given up to the same timeout
+    /// to hand back the jobs they still have in flight.
     pub async fn run(self) -> std::io::Result<()> {
-        // let _res: Vec<(String, bool)> = self
-        //     .worker_handles
-        //     .into_iter()
-        //     .map(|h| (h.0, h.1.is_finished()))
-        //     .collect();
         if let Some(timeout) = self.timeout {
             if self.shutdown.with_timeout(timeout).await {
                 warn!("Shutdown timeout reached. Exiting forcefully");
+                self.terminate.shutdown();
+                if self.terminate.with_timeout(timeout).await {
+                    warn!("Workers did not hand back their jobs in time");
+                }
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::TimedOut,
                     "Shutdown timeout reached. Exiting forcefully",
@@ -197,9 +315,13 @@ impl Monitor<TokioExecutor> {
     pub fn new() -> Self {
         Self {
             shutdown: Shutdown::new(),
+            terminate: Shutdown::new(),
             worker_handles: Vec::new(),
             timeout: None,
             executor: TokioExecutor,
+            listeners: WorkerListeners::default(),
+            controls: WorkerControls::default(),
+            timer: Arc::new(TokioTimer),
         }
     }
 
@@ -208,11 +330,245 @@ impl Monitor<TokioExecutor> {
     pub fn executor<E: Executor>(self, executor: E) -> Monitor<E> {
         Monitor {
             shutdown: self.shutdown,
+            terminate: self.terminate,
             worker_handles: Vec::new(),
             timeout: self.timeout,
             executor,
+            listeners: self.listeners,
+            controls: self.controls,
+            timer: self.timer,
+        }
+    }
+}
+
+/// Builds the context for a single run of a worker, along with a future that shuts the run
+/// down once it is stopped. Each worker can be stopped on its own, but they all stop with the monitor.
+fn worker_context<E: Executor>(
+    monitor: &Shutdown,
+    terminate: &Shutdown,
+    executor: &E,
+    listeners: &WorkerListeners,
+    control: &WorkerControl,
+) -> (WorkerContext<E>, BoxFuture<'static, ()>) {
+    let shutdown = Shutdown::new();
+    let stop = shutdown.clone();
+    let stopped = control
+        .stop
+        .cancel_on_shutdown(shutdown.cancel_on_shutdown(future::pending::<()>()));
+    let stopped = monitor
+        .cancel_on_shutdown(stopped)
+        .map(move |_| stop.shutdown())
+        .boxed();
+    let ctx = WorkerContext {
+        shutdown,
+        terminate: terminate.clone(),
+        executor: executor.clone(),
+        listeners: listeners.clone(),
+        pause: control.pause.subscribe(),
+    };
+    (ctx, stopped)
+}
+
+/// Drives a run of a worker to completion, shutting it down if `stopped` resolves first.
+/// Stops are only watched for as long as the run lasts.
+async fn run_worker<F>(run: F, stopped: BoxFuture<'static, ()>) -> Result<(), WorkerError>
+where
+    F: Future<Output = Result<(), WorkerError>> + Unpin,
+{
+    match future::select(run, stopped).await {
+        Either::Left((res, _)) => res,
+        Either::Right(((), run)) => run.await,
+    }
+}
+
+/// Controls a registered worker across all of its runs
+#[derive(Clone)]
+struct WorkerControl {
+    name: String,
+    pause: watch::Sender<bool>,
+    stop: Shutdown,
+}
+
+/// The controls of every worker registered with a monitor, shared with its handles
+#[derive(Clone, Default)]
+struct WorkerControls(Arc<RwLock<Vec<WorkerControl>>>);
+
+impl WorkerControls {
+    fn add(&self, name: &str) -> WorkerControl {
+        let control = WorkerControl {
+            name: name.to_string(),
+            pause: watch::channel(false).0,
+            stop: Shutdown::new(),
+        };
+        self.0.write().unwrap().push(control.clone());
+        control
+    }
+
+    /// Applies `f` to every worker named `name`, returning false if there is none
+    fn apply(&self, name: &str, f: impl Fn(&WorkerControl)) -> bool {
+        let controls = self.0.read().unwrap();
+        let mut found = false;
+        for control in controls.iter().filter(|control| control.name == name) {
+            f(control);
+            found = true;
+        }
+        found
+    }
+}
+
+/// A cloneable handle to control the workers of a running [`Monitor`].
+///
+/// Workers are looked up by name. When several workers share a name, all of them are affected.
+///
+/// ```rust,ignore
+/// let monitor = Monitor::new().register(worker);
+/// let handle = monitor.handle();
+/// tokio::spawn(monitor.run());
+/// // Stop fetching new jobs, running jobs carry on
+/// handle.pause("email-worker");
+/// ```
+#[derive(Clone)]
+pub struct MonitorHandle {
+    shutdown: Shutdown,
+    controls: WorkerControls,
+}
+
+impl Debug for MonitorHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MonitorHandle")
+            .field("shutdown", &"[Graceful shutdown listener]")
+            .field("workers", &self.workers())
+            .finish()
+    }
+}
+
+impl MonitorHandle {
+    /// The names of the workers registered with the monitor
+    pub fn workers(&self) -> Vec<String> {
+        let controls = self.controls.0.read().unwrap();
+        controls
+            .iter()
+            .map(|control| control.name.clone())
+            .collect()
+    }
+
+    /// Returns whether the worker is paused, or `None` if no worker is registered with `name`
+    pub fn is_paused(&self, name: &str) -> Option<bool> {
+        let controls = self.controls.0.read().unwrap();
+        controls
+            .iter()
+            .find(|control| control.name == name)
+            .map(|control| *control.pause.borrow())
+    }
+
+    /// Pauses the worker: it stops fetching new jobs while its running jobs complete.
+    /// A paused worker keeps sending keep-alives to its storage.
+    ///
+    /// Returns false if no worker is registered with `name`
+    pub fn pause(&self, name: &str) -> bool {
+        self.controls.apply(name, |control| {
+            control.pause.send_modify(|paused| *paused = true)
+        })
+    }
+
+    /// Resumes fetching jobs on a paused worker.
+    ///
+    /// Returns false if no worker is registered with `name`
+    pub fn resume(&self, name: &str) -> bool {
+        self.controls.apply(name, |control| {
+            control.pause.send_modify(|paused| *paused = false)
+        })
+    }
+
+    /// Gracefully shuts down the worker, without restarting it.
+    /// Other workers of the monitor are not affected.
+    ///
+    /// Returns false if no worker is registered with `name`
+    pub fn stop(&self, name: &str) -> bool {
+        self.controls.apply(name, |control| control.stop.shutdown())
+    }
+
+    /// Gracefully shuts down the monitor and all its workers
+    pub fn shutdown(&self) {
+        self.shutdown.shutdown();
+    }
+}
+
+/// When a supervised worker is restarted after it stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartStrategy {
+    /// Never restart the worker
+    Never,
+    /// Restart the worker whenever it stops
+    Always,
+    /// Restart the worker only when it stops with an error
+    OnError,
+}
+
+/// Controls how [`Monitor::register_with_restart`] restarts a worker
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    strategy: RestartStrategy,
+    max_restarts: Option<usize>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::new(RestartStrategy::OnError)
+    }
+}
+
+impl RestartPolicy {
+    /// Build a policy with the given strategy, unlimited restarts and a backoff from 1s up to 60s
+    pub fn new(strategy: RestartStrategy) -> Self {
+        RestartPolicy {
+            strategy,
+            max_restarts: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Give up after the worker has been restarted `max` times
+    pub fn with_max_restarts(mut self, max: usize) -> Self {
+        self.max_restarts = Some(max);
+        self
+    }
+
+    /// Wait `initial` before the first restart, doubling the wait for each
+    /// further restart up to `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// The strategy of this policy
+    pub fn strategy(&self) -> RestartStrategy {
+        self.strategy
+    }
+
+    /// The maximum number of restarts, if any
+    pub fn max_restarts(&self) -> Option<usize> {
+        self.max_restarts
+    }
+
+    fn should_restart(&self, res: &Result<(), WorkerError>) -> bool {
+        match self.strategy {
+            RestartStrategy::Never => false,
+            RestartStrategy::Always => true,
+            RestartStrategy::OnError => res.is_err(),
         }
     }
+
+    fn backoff(&self, restarts: usize) -> Duration {
+        let exponent = u32::try_from(restarts.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_backoff)
+    }
 }
 
 #[cfg(test)]
@@ -222,10 +578,21 @@ mod tests {
         task::{Context, Poll},
     };
 
-    use crate::{context::JobContext, job_fn::job_fn, worker::WorkerError};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use crate::{
+        builder::{WorkerBuilder, WorkerFactoryFn},
+        context::JobContext,
+        error::JobStreamError,
+        job_fn::{job_fn, JobFn},
+        worker::{ready::ReadyWorker, timeout::JobTimeout, WorkerError},
+    };
 
     use super::*;
-    use futures::Stream;
+    use futures::{stream, stream::BoxStream, Stream, StreamExt};
     use tokio::time::sleep;
     use tower::ServiceBuilder;
 
@@ -250,7 +617,7 @@ mod tests {
             "test-worker".to_string()
         }
 
-        async fn start<E: Executor + Send>(
+        async fn start<E: Executor + Send + Sync + 'static>(
             self,
             _ctx: WorkerContext<E>,
         ) -> Result<(), WorkerError> {
@@ -323,4 +690,283 @@ mod tests {
         });
         assert_eq!(monitor.worker_handles.len(), 5);
     }
+
+    struct FailingWorker {
+        starts: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Worker<TestJob> for FailingWorker {
+        type Service = JobFn<fn(TestJob, JobContext) -> future::Ready<()>>;
+        type Source = TestSource;
+
+        fn name(&self) -> String {
+            "failing-worker".to_string()
+        }
+
+        async fn start<E: Executor + Send + Sync + 'static>(
+            self,
+            _ctx: WorkerContext<E>,
+        ) -> Result<(), WorkerError> {
+            self.starts.fetch_add(1, Ordering::SeqCst);
+            Err(WorkerError::StartError("database unavailable".to_string()))
+        }
+    }
+
+    struct EventCollector(Arc<Mutex<Vec<String>>>);
+
+    impl WorkerListener for EventCollector {
+        fn on_event(&self, worker_id: &str, event: &WorkerEvent) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{worker_id}: {event:?}"));
+        }
+    }
+
+    async fn run_for(monitor: Monitor<TokioExecutor>, duration: Duration) {
+        monitor
+            .run_with_signal(async {
+                sleep(duration).await;
+                Ok(())
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_monitor_restarts_failed_worker_until_max_restarts() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let counter = starts.clone();
+        let policy = RestartPolicy::new(RestartStrategy::OnError)
+            .with_max_restarts(2)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(20));
+        let monitor = Monitor::new()
+            .listener(EventCollector(events.clone()))
+            .register_with_restart(policy, move || FailingWorker {
+                starts: counter.clone(),
+            });
+        run_for(monitor, Duration::from_millis(200)).await;
+
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events.last().map(String::as_str),
+            Some("failing-worker: GaveUp { restarts: 2 }")
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| event.contains("Restart {"))
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_monitor_never_restarts_with_never_strategy() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counter = starts.clone();
+        let policy = RestartPolicy::new(RestartStrategy::Never)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(10));
+        let monitor = Monitor::new().register_with_restart(policy, move || FailingWorker {
+            starts: counter.clone(),
+        });
+        run_for(monitor, Duration::from_millis(100)).await;
+
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_monitor_always_restarts_stopped_worker() {
+        let policy = RestartPolicy::new(RestartStrategy::Always)
+            .with_max_restarts(1)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(10));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let monitor = Monitor::new()
+            .listener(EventCollector(events.clone()))
+            .register_with_restart(policy, || TestWorker {
+                _service: ServiceBuilder::new().service(job_fn(test_service)),
+            });
+        run_for(monitor, Duration::from_millis(400)).await;
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].starts_with("test-worker: Restart { attempt: 1"));
+    }
+
+    #[derive(Clone)]
+    struct CountingExecutor(Arc<AtomicUsize>);
+
+    impl Executor for CountingExecutor {
+        type JoinHandle = ();
+        fn spawn(&self, fut: impl Future<Output = ()> + Send + 'static) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(fut);
+        }
+    }
+
+    struct CountingTimer(Arc<AtomicUsize>);
+
+    impl Timer for CountingTimer {
+        fn sleep(&self, duration: Duration) -> Pin<Box<dyn crate::utils::Sleep>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            TokioTimer.sleep(duration)
+        }
+
+        fn sleep_until(&self, deadline: std::time::Instant) -> Pin<Box<dyn crate::utils::Sleep>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            TokioTimer.sleep_until(deadline)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_monitor_restarts_through_timer_without_spawning_tasks() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let spawns = Arc::new(AtomicUsize::new(0));
+        let sleeps = Arc::new(AtomicUsize::new(0));
+        let counter = starts.clone();
+        let policy = RestartPolicy::new(RestartStrategy::Always)
+            .with_max_restarts(3)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(10));
+        let monitor = Monitor::new()
+            .executor(CountingExecutor(spawns.clone()))
+            .timer(CountingTimer(sleeps.clone()))
+            .register_with_restart(policy, move || FailingWorker {
+                starts: counter.clone(),
+            });
+        monitor
+            .run_with_signal(async {
+                sleep(Duration::from_millis(200)).await;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(starts.load(Ordering::SeqCst), 4);
+        assert_eq!(sleeps.load(Ordering::SeqCst), 3);
+        // Only the supervisor is spawned, runs are not watched by tasks of their own
+        assert_eq!(spawns.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_restart_policy_backoff_is_capped() {
+        let policy = RestartPolicy::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+    }
+
+    type CountingWorker = ReadyWorker<
+        BoxStream<'static, Result<Option<JobRequest<TestJob>>, JobStreamError>>,
+        JobTimeout<JobFn<fn(TestJob, JobContext) -> future::Ready<()>>>,
+    >;
+
+    struct JobCounter(Arc<AtomicUsize>);
+
+    impl WorkerListener for JobCounter {
+        fn on_event(&self, _worker_id: &str, event: &WorkerEvent) {
+            if let WorkerEvent::JobSucceeded { .. } = event {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// A worker fetching a job every 10ms and counting the jobs it processed
+    fn counting_worker(name: &str, processed: Arc<AtomicUsize>) -> CountingWorker {
+        let jobs = stream::unfold((), |_| async {
+            sleep(Duration::from_millis(10)).await;
+            Some((Ok(Some(JobRequest::new(TestJob {}))), ()))
+        })
+        .boxed();
+        let service: fn(TestJob, JobContext) -> future::Ready<()> = |_, _| future::ready(());
+        WorkerBuilder::new(name)
+            .listener(JobCounter(processed))
+            .stream(jobs)
+            .build_fn(service)
+    }
+
+    #[tokio::test]
+    async fn test_monitor_handle_pauses_and_resumes_worker() {
+        let processed = Arc::new(AtomicUsize::new(0));
+        let monitor = Monitor::new().register(counting_worker("counter", processed.clone()));
+        let handle = monitor.handle();
+        let run = tokio::spawn(monitor.run());
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(handle.pause("counter"));
+        assert_eq!(handle.is_paused("counter"), Some(true));
+        // Let the job fetched before pausing complete
+        sleep(Duration::from_millis(50)).await;
+        let paused_at = processed.load(Ordering::SeqCst);
+        assert!(paused_at > 0);
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(processed.load(Ordering::SeqCst), paused_at);
+
+        assert!(handle.resume("counter"));
+        sleep(Duration::from_millis(100)).await;
+        assert!(processed.load(Ordering::SeqCst) > paused_at);
+
+        handle.shutdown();
+        run.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_monitor_handle_stops_single_worker() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let running = Arc::new(AtomicUsize::new(0));
+        let monitor = Monitor::new()
+            .listener(EventCollector(events.clone()))
+            .register(counting_worker("stopped", stopped.clone()))
+            .register_with_restart(RestartPolicy::new(RestartStrategy::Always), {
+                let running = running.clone();
+                move || counting_worker("running", running.clone())
+            });
+        let handle = monitor.handle();
+        assert_eq!(handle.workers(), ["stopped", "running"]);
+        assert!(!handle.stop("unknown"));
+        let run = tokio::spawn(monitor.run());
+
+        sleep(Duration::from_millis(50)).await;
+        assert!(handle.stop("stopped"));
+        assert!(handle.stop("running"));
+        sleep(Duration::from_millis(100)).await;
+        handle.shutdown();
+        run.await.unwrap().unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events.contains(&"stopped: Stop".to_string()));
+        assert!(events.contains(&"running: Stop".to_string()));
+        assert!(!events.iter().any(|event| event.contains("Restart")));
+    }
+
+    #[tokio::test]
+    async fn test_monitor_handle_stop_leaves_other_workers_running() {
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let running = Arc::new(AtomicUsize::new(0));
+        let monitor = Monitor::new()
+            .register(counting_worker("stopped", stopped.clone()))
+            .register(counting_worker("running", running.clone()));
+        let handle = monitor.handle();
+        let run = tokio::spawn(monitor.run());
+
+        sleep(Duration::from_millis(50)).await;
+        handle.stop("stopped");
+        sleep(Duration::from_millis(50)).await;
+        let (stopped_at, running_at) = (
+            stopped.load(Ordering::SeqCst),
+            running.load(Ordering::SeqCst),
+        );
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(stopped.load(Ordering::SeqCst), stopped_at);
+        assert!(running.load(Ordering::SeqCst) > running_at);
+        assert!(!run.is_finished());
+
+        handle.shutdown();
+        run.await.unwrap().unwrap();
+    }
 }
@@ -7,11 +7,21 @@ use crate::context::JobContext;
 
 /// Represents the state of a [JobRequest]
 #[derive(
-    EnumString, Serialize, Deserialize, Debug, Clone, AsRefStr, Hash, PartialEq, std::cmp::Eq,
+    EnumString,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    AsRefStr,
+    Hash,
+    PartialEq,
+    std::cmp::Eq,
+    Default,
 )]
 pub enum JobState {
     /// Job is pending
     #[serde(alias = "Latest")]
+    #[default]
     Pending,
     /// Job is running
     Running,
@@ -23,12 +33,12 @@ pub enum JobState {
     Failed,
     /// Job has been killed
     Killed,
-}
-
-impl Default for JobState {
-    fn default() -> Self {
-        JobState::Pending
-    }
+    /// Job is waiting for its parent jobs to be done
+    Blocked,
+    /// Job was cancelled before it finished, see [Storage::cancel]
+    ///
+    /// [Storage::cancel]: crate::storage::Storage::cancel
+    Cancelled,
 }
 
 /// Represents a job which can be serialized and executed
@@ -1,9 +1,24 @@
-use std::any::Any;
+use std::{any::Any, time::Duration};
 
+use serde::{Deserialize, Serialize};
 use tower::BoxError;
 
 use crate::error::JobError;
 
+/// Represents the outcome a job requests from its storage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum JobResult {
+    /// The job completed and should be acknowledged
+    Success,
+    /// The job should be put back into the queue and attempted again
+    Retry,
+    /// The job should never be attempted again
+    Kill,
+    /// The job should be attempted again after the provided duration
+    Reschedule(Duration),
+}
+
 /// Helper for Job Responses
 pub trait IntoResponse {
     /// The final result of the job
@@ -25,6 +40,13 @@ impl IntoResponse for bool {
     }
 }
 
+impl IntoResponse for JobResult {
+    type Result = std::result::Result<Self, JobError>;
+    fn into_response(self) -> std::result::Result<Self, JobError> {
+        Ok(self)
+    }
+}
+
 impl<T: Any, E: Into<BoxError> + Send + Sync> IntoResponse for std::result::Result<T, E> {
     type Result = Result<T, JobError>;
     fn into_response(self) -> Result<T, JobError> {
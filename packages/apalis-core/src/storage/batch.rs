@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A unique identifier for a batch of jobs in a storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BatchId(Uuid);
+
+impl BatchId {
+    /// Generate a new random [BatchId]
+    pub fn new() -> Self {
+        BatchId(Uuid::new_v4())
+    }
+
+    /// Get the underlying [Uuid]
+    pub fn inner(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl Default for BatchId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Uuid> for BatchId {
+    fn from(id: Uuid) -> Self {
+        BatchId(id)
+    }
+}
+
+impl FromStr for BatchId {
+    type Err = uuid::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(BatchId(Uuid::from_str(s)?))
+    }
+}
+
+impl std::fmt::Display for BatchId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// The jobs pushed once every job of a closed batch has finished.
+///
+/// Only one of them is ever pushed: `on_success` if every job was done,
+/// `on_failure` if any job was killed.
+#[derive(Debug, Clone)]
+pub struct BatchCallbacks<T> {
+    on_success: Option<T>,
+    on_failure: Option<T>,
+}
+
+impl<T> Default for BatchCallbacks<T> {
+    fn default() -> Self {
+        BatchCallbacks {
+            on_success: None,
+            on_failure: None,
+        }
+    }
+}
+
+impl<T> BatchCallbacks<T> {
+    /// Build callbacks that push nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push `job` once every job of the batch is done
+    pub fn with_on_success(mut self, job: T) -> Self {
+        self.on_success = Some(job);
+        self
+    }
+
+    /// Push `job` once every job of the batch has finished, if any of them was killed
+    pub fn with_on_failure(mut self, job: T) -> Self {
+        self.on_failure = Some(job);
+        self
+    }
+
+    /// Get the job pushed when the batch succeeds, if any
+    pub fn on_success(&self) -> Option<&T> {
+        self.on_success.as_ref()
+    }
+
+    /// Get the job pushed when the batch fails, if any
+    pub fn on_failure(&self) -> Option<&T> {
+        self.on_failure.as_ref()
+    }
+}
+
+/// How far the jobs of a batch have gone
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchProgress {
+    /// The number of jobs pushed into the batch
+    pub total: i64,
+    /// The number of jobs that are done
+    pub done: i64,
+    /// The number of jobs that were killed
+    pub failed: i64,
+    /// Whether the batch is closed, so no more jobs can be pushed into it
+    pub closed: bool,
+}
+
+impl BatchProgress {
+    /// The number of jobs that have not finished yet
+    pub fn pending(&self) -> i64 {
+        self.total - self.done - self.failed
+    }
+
+    /// Whether the batch is closed and every job in it has finished
+    pub fn is_finished(&self) -> bool {
+        self.closed && self.pending() == 0
+    }
+}
@@ -1,45 +1,315 @@
-use futures::StreamExt;
-use std::{marker::PhantomData, time::Duration};
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use std::{
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tower::layer::util::Stack;
+use tracing::warn;
 
-use crate::{builder::WorkerBuilder, job::JobStreamResult, worker::WorkerRef};
+use crate::{
+    builder::WorkerBuilder,
+    job::JobStreamResult,
+    utils::Timer,
+    worker::{ready::Reenqueue, WorkerEvent, WorkerListeners, WorkerRef},
+};
 
-use super::{layers::KeepAliveLayer, Storage};
+use super::{
+    layers::{AckLayer, RunningJobs},
+    Storage, StorageWorkerPulse,
+};
+
+/// Configuration for a [Worker] that consumes a [Storage]
+///
+/// [Worker]: crate::worker::Worker
+#[derive(Debug, Clone)]
+pub struct StorageWorkerConfig {
+    poll_interval: Duration,
+    keep_alive: Duration,
+    buffer_size: usize,
+    orphan_threshold: Duration,
+    enqueue_scheduled: Option<(Duration, i32)>,
+    reenqueue_orphaned: Option<(Duration, i32)>,
+    result_retention: Option<Duration>,
+    progress_interval: Duration,
+    max_log_lines: Option<usize>,
+}
+
+impl Default for StorageWorkerConfig {
+    fn default() -> Self {
+        StorageWorkerConfig {
+            poll_interval: Duration::from_millis(10),
+            keep_alive: Duration::from_secs(30),
+            buffer_size: 1,
+            orphan_threshold: Duration::from_secs(300),
+            enqueue_scheduled: Some((Duration::from_secs(1), 10)),
+            reenqueue_orphaned: Some((Duration::from_secs(60), 10)),
+            result_retention: None,
+            progress_interval: Duration::from_secs(1),
+            max_log_lines: None,
+        }
+    }
+}
+
+impl StorageWorkerConfig {
+    /// Build the default config
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often the storage is polled for new jobs. Defaults to 10ms
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// How often the worker notifies the storage that it is still alive. Defaults to 30s
+    ///
+    /// This should be well below the orphan threshold, otherwise running jobs
+    /// will be re-enqueued by other workers. Running jobs cancelled with [Storage::cancel]
+    /// are also only stopped on the next keep-alive.
+    pub fn with_keep_alive(mut self, period: Duration) -> Self {
+        self.keep_alive = period;
+        self
+    }
+
+    /// The maximum number of jobs claimed from the storage in a single round-trip. Defaults to 1
+    ///
+    /// Claimed jobs are handed to the worker one by one, so they may wait for a free slot
+    /// when the worker's concurrency is limited. Jobs still waiting when the worker stops
+    /// are handed back to the storage.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// How long a worker can go without a keep-alive before its running jobs are
+    /// considered orphaned and re-enqueued. Defaults to 5 minutes
+    pub fn with_orphan_threshold(mut self, threshold: Duration) -> Self {
+        self.orphan_threshold = threshold;
+        self
+    }
+
+    /// Every `interval`, move up to `count` scheduled jobs that are due into the active queue
+    pub fn with_enqueue_scheduled(mut self, interval: Duration, count: i32) -> Self {
+        self.enqueue_scheduled = Some((interval, count));
+        self
+    }
+
+    /// Every `interval`, re-enqueue up to `count` jobs held by workers that are no longer alive
+    pub fn with_reenqueue_orphaned(mut self, interval: Duration, count: i32) -> Self {
+        self.reenqueue_orphaned = Some((interval, count));
+        self
+    }
+
+    /// Never emit [StorageWorkerPulse::EnqueueScheduled] from this worker
+    pub fn without_enqueue_scheduled(mut self) -> Self {
+        self.enqueue_scheduled = None;
+        self
+    }
+
+    /// Never emit [StorageWorkerPulse::RenqueueOrpharned] from this worker
+    pub fn without_reenqueue_orphaned(mut self) -> Self {
+        self.reenqueue_orphaned = None;
+        self
+    }
+
+    /// Save the value returned by each successful job, so that it can be read back with
+    /// [Storage::fetch_result] for `retention` after the job is done. Results are not saved
+    /// by default
+    pub fn with_result_retention(mut self, retention: Duration) -> Self {
+        self.result_retention = Some(retention);
+        self
+    }
+
+    /// Write the progress reported by each job at most once every `interval`. Defaults to 1s
+    pub fn with_progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Store the lines each job logs through [JobContext::logger], keeping the last
+    /// `max_lines` of them per job. Logs are not stored by default
+    ///
+    /// [JobContext::logger]: crate::context::JobContext::logger
+    pub fn with_job_logs(mut self, max_lines: usize) -> Self {
+        self.max_log_lines = Some(max_lines);
+        self
+    }
+
+    fn pulses(&self) -> Vec<(StorageWorkerPulse, Duration)> {
+        let mut pulses = Vec::new();
+        if let Some((interval, count)) = self.enqueue_scheduled {
+            pulses.push((StorageWorkerPulse::EnqueueScheduled { count }, interval));
+        }
+        if let Some((interval, count)) = self.reenqueue_orphaned {
+            let pulse = StorageWorkerPulse::RenqueueOrpharned {
+                count,
+                timeout_worker: self.orphan_threshold,
+            };
+            pulses.push((pulse, interval));
+        }
+        pulses
+    }
+}
 
 /// A helper trait to help build a [Worker] that consumes a [Storage]
-pub trait WithStorage<NS, ST: Storage<Output = Self::Job>> {
+pub trait WithStorage<NS, ST: Storage<Output = Self::Job>>: Sized {
     /// The job to consume
     type Job;
     /// The [Stream] to produce jobs
     type Stream;
     /// The builder method to produce a [WorkerBuilder] that will consume jobs
-    fn with_storage(self, storage: ST) -> WorkerBuilder<Self::Job, Self::Stream, NS>;
+    fn with_storage(self, storage: ST) -> WorkerBuilder<Self::Job, Self::Stream, NS> {
+        self.with_storage_config(storage, StorageWorkerConfig::default())
+    }
+
+    /// The builder method to produce a [WorkerBuilder] that will consume jobs
+    /// using the provided [StorageWorkerConfig]
+    fn with_storage_config(
+        self,
+        storage: ST,
+        config: StorageWorkerConfig,
+    ) -> WorkerBuilder<Self::Job, Self::Stream, NS>;
 }
 
-impl<J: 'static, M, ST> WithStorage<Stack<KeepAliveLayer<ST, J>, M>, ST>
+impl<J: 'static, M: 'static, ST> WithStorage<Stack<AckLayer<ST, J>, M>, ST>
     for WorkerBuilder<(), (), M>
 where
-    ST: Storage<Output = J>,
+    ST: Storage<Output = J> + Sync + 'static,
 {
     type Job = J;
     type Stream = JobStreamResult<J>;
-    fn with_storage(
+    fn with_storage_config(
         self,
         storage: ST,
-    ) -> WorkerBuilder<J, Self::Stream, Stack<KeepAliveLayer<ST, J>, M>> {
-        let layer = self.layer.layer(KeepAliveLayer::new(
-            WorkerRef::new(self.name.clone()),
+        config: StorageWorkerConfig,
+    ) -> WorkerBuilder<J, Self::Stream, Stack<AckLayer<ST, J>, M>> {
+        let worker = WorkerRef::new(self.name.clone());
+        let mut ack =
+            AckLayer::new(worker, storage.clone()).with_progress_interval(config.progress_interval);
+        if let Some(retention) = config.result_retention {
+            ack = ack.with_result_retention(retention);
+        }
+        if let Some(max_lines) = config.max_log_lines {
+            ack = ack.with_job_logs(max_lines);
+        }
+        let running = ack.running_jobs();
+        let layer = self.layer.layer(ack);
+        let mut beats = self.beats;
+        beats.push(keep_alive::<ST, M>(
             storage.clone(),
-            Duration::from_secs(30),
+            self.name.clone(),
+            config.keep_alive,
+            self.timer.clone(),
+            self.listeners.clone(),
+            running,
         ));
+        for (pulse, period) in config.pulses() {
+            beats.push(heartbeat(
+                storage.clone(),
+                pulse,
+                period,
+                self.timer.clone(),
+            ));
+        }
+        let reenqueue = reenqueue(storage.clone(), self.name.clone());
+        // Jobs are locked by the worker, so it has to be registered before the first claim
+        let register = register::<ST, M>(storage.clone(), self.name.clone());
         let mut storage = storage;
+        let source = storage.consume(self.name.clone(), config.poll_interval, config.buffer_size);
         WorkerBuilder {
             job: PhantomData,
             layer,
-            source: storage
-                .consume(self.name.clone(), Duration::from_millis(10))
-                .boxed(),
+            source: register.map(|_| source).flatten_stream().boxed(),
             name: self.name,
+            beats,
+            reenqueue: Some(reenqueue),
+            concurrency: self.concurrency,
+            listeners: self.listeners,
+            timer: self.timer,
         }
     }
 }
+
+/// Registers the worker with the storage before it starts consuming
+async fn register<ST: Storage, M>(mut storage: ST, worker_id: String) {
+    if let Err(e) = storage.keep_alive::<M>(worker_id.clone()).await {
+        warn!("Failed to register {worker_id}: {e}");
+    }
+}
+
+/// Notifies the storage that the worker is alive every `period`, then trips the tokens of
+/// the running jobs that were cancelled. Failures are retried with an exponential backoff
+/// capped at `period`.
+fn keep_alive<ST: Storage + Sync + 'static, M: 'static>(
+    mut storage: ST,
+    worker_id: String,
+    period: Duration,
+    timer: Arc<dyn Timer + Send + Sync>,
+    listeners: WorkerListeners,
+    running: RunningJobs,
+) -> BoxFuture<'static, ()> {
+    async move {
+        let mut failures = 0;
+        loop {
+            let wait = match storage.keep_alive::<M>(worker_id.clone()).await {
+                Ok(()) => {
+                    failures = 0;
+                    if !running.is_empty() {
+                        match storage.fetch_cancelled(worker_id.clone()).await {
+                            Ok(cancelled) => running.cancel(&cancelled),
+                            Err(e) => warn!("Failed to fetch cancelled jobs for {worker_id}: {e}"),
+                        }
+                    }
+                    period
+                }
+                Err(e) => {
+                    failures += 1;
+                    warn!("Failed to send keep-alive for {worker_id} ({failures} in a row): {e}");
+                    listeners.emit(
+                        &worker_id,
+                        WorkerEvent::Error(format!("Failed to send keep-alive: {e}")),
+                    );
+                    Duration::from_millis(100)
+                        .saturating_mul(2u32.saturating_pow(failures - 1))
+                        .min(period)
+                }
+            };
+            timer.sleep(wait).await;
+        }
+    }
+    .boxed()
+}
+
+/// Emits `pulse` to the storage every `period`
+fn heartbeat<ST: Storage + 'static>(
+    mut storage: ST,
+    pulse: StorageWorkerPulse,
+    period: Duration,
+    timer: Arc<dyn Timer + Send + Sync>,
+) -> BoxFuture<'static, ()> {
+    async move {
+        let mut next = Instant::now();
+        loop {
+            timer.sleep_until(next).await;
+            if let Err(e) = storage.heartbeat(pulse.clone()).await {
+                warn!("Failed to emit heartbeat {pulse:?}: {e}");
+            }
+            next += period;
+        }
+    }
+    .boxed()
+}
+
+/// Hands the jobs still held by the worker back to the storage once it has stopped
+fn reenqueue<ST: Storage + 'static>(mut storage: ST, worker_id: String) -> Reenqueue {
+    Box::new(move || {
+        async move {
+            if let Err(e) = storage.reenqueue_active(worker_id.clone()).await {
+                warn!("Failed to reenqueue active jobs for {worker_id}: {e}");
+            }
+        }
+        .boxed()
+    })
+}
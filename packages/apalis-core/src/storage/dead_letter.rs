@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{job::JobId, request::JobRequest};
+
+use super::{Storage, StorageResult};
+
+/// Selects the killed jobs requeued by [DeadLetter::requeue_dead_matching]
+#[derive(Debug, Clone, Default)]
+pub struct DeadLetterFilter {
+    error_contains: Option<String>,
+    killed_after: Option<DateTime<Utc>>,
+    killed_before: Option<DateTime<Utc>>,
+}
+
+impl DeadLetterFilter {
+    /// Build a filter matching every killed job
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match jobs whose last error contains `text`
+    pub fn with_error_containing<S: Into<String>>(mut self, text: S) -> Self {
+        self.error_contains = Some(text.into());
+        self
+    }
+
+    /// Only match jobs killed at or after `at`
+    pub fn with_killed_after(mut self, at: DateTime<Utc>) -> Self {
+        self.killed_after = Some(at);
+        self
+    }
+
+    /// Only match jobs killed before `at`
+    pub fn with_killed_before(mut self, at: DateTime<Utc>) -> Self {
+        self.killed_before = Some(at);
+        self
+    }
+
+    /// Get the text the last error has to contain, if any
+    pub fn error_contains(&self) -> Option<&str> {
+        self.error_contains.as_deref()
+    }
+
+    /// Get the earliest kill time matched, if any
+    pub fn killed_after(&self) -> Option<&DateTime<Utc>> {
+        self.killed_after.as_ref()
+    }
+
+    /// Get the kill time every matched job precedes, if any
+    pub fn killed_before(&self) -> Option<&DateTime<Utc>> {
+        self.killed_before.as_ref()
+    }
+}
+
+/// Manages the jobs killed by a [Storage], so that they can be inspected and replayed
+/// once the cause of their failure is fixed.
+///
+/// A requeued job is pending again with its attempts reset. It keeps its last error,
+/// but no longer holds its unique key nor counts towards its batch.
+#[async_trait::async_trait]
+pub trait DeadLetter: Storage {
+    /// List killed jobs, the most recently killed first, ten per page starting at 1.
+    ///
+    /// Why each job was killed is kept in [JobContext::last_error] and [JobContext::last_failure]
+    ///
+    /// [JobContext::last_error]: crate::context::JobContext::last_error
+    /// [JobContext::last_failure]: crate::context::JobContext::last_failure
+    async fn list_dead(&self, page: i32) -> StorageResult<Vec<JobRequest<Self::Output>>>;
+
+    /// Requeue the killed jobs in `job_ids`, returning how many were requeued.
+    /// Jobs that are not killed are left untouched
+    async fn requeue_dead(&mut self, job_ids: &[JobId]) -> StorageResult<u64>;
+
+    /// Requeue every killed job matching `filter`, returning how many were requeued
+    async fn requeue_dead_matching(&mut self, filter: &DeadLetterFilter) -> StorageResult<u64>;
+
+    /// Delete the jobs killed more than `age` ago, returning how many were deleted
+    async fn purge_dead(&mut self, age: Duration) -> StorageResult<u64>;
+}
@@ -1,6 +1,9 @@
 use thiserror::Error;
 
-use crate::error::{BoxDynError, JobError};
+use crate::{
+    error::{BoxDynError, JobError},
+    job::JobId,
+};
 
 /// Represents a storage emitted by a worker
 #[derive(Debug, Error)]
@@ -18,6 +21,12 @@ pub enum StorageError {
     /// Serialization/Deserialization Error
     #[error("Serialization/Deserialization Error")]
     SerDe(#[source] BoxDynError),
+    /// A job with the same id exists, or a job pushed with the same unique key is still pending or running
+    #[error("The job id or unique key is held by job {0}")]
+    Duplicate(JobId),
+    /// The storage does not implement a feature, eg. batches
+    #[error("The storage does not support {0}")]
+    Unsupported(&'static str),
 }
 
 impl From<serde_json::Error> for StorageError {
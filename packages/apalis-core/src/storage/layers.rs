@@ -1,78 +1,337 @@
-use std::marker::PhantomData;
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
 
-use futures::Future;
+use chrono::Utc;
+use futures::{future::BoxFuture, FutureExt};
+use serde::Serialize;
 use std::time::Duration;
-use tokio::time::interval;
-use tower::{layer::util::Identity, Layer, Service};
+use tower::{BoxError, Layer, Service};
+use tracing::warn;
 
-use crate::{request::JobRequest, worker::WorkerRef};
+use crate::{
+    context::{CancellationToken, JobFailure, JobLogger, ProgressReporter},
+    error::JobError,
+    job::Job,
+    request::JobRequest,
+    response::JobResult,
+    worker::WorkerRef,
+};
 
-use super::Storage;
+use super::{Storage, StorageError, StorageResult};
 
-/// A `tower::layer::Layer` that wraps a service to periodically send a "keep-alive" message
-/// to the source to notify it that the worker is still alive. This layer keeps a reference to
-/// the worker and its name and uses it to send the "keep-alive" message.
-pub struct KeepAliveLayer<T, Req> {
+/// A `tower::layer::Layer` that reports the outcome of every job back to its [`Storage`].
+///
+/// Jobs that complete successfully are acknowledged, unless they return a [`JobResult`] in which
+/// case the matching storage call is made. Jobs that fail have their attempts and `last_error`
+/// persisted, and are then retried until they reach `max_attempts`, after which they are killed.
+/// A job is rescheduled rather than retried right away when its [`Job::BACKOFF`] asks it to wait.
+///
+/// An attempt that exceeds the job's timeout fails with [`JobError::TimedOut`], see [`JobTimeout`],
+/// and is retried like any other failure.
+///
+/// The values returned by successful jobs are only saved if a retention is set,
+/// see [`AckLayer::with_result_retention`].
+///
+/// A job that fails after its [`JobContext::cancellation_token`] was tripped is aborted
+/// rather than retried, see [`Storage::abort`].
+///
+/// The progress reported through [`JobContext::progress_reporter`] is written to the storage
+/// at most once per interval, see [`AckLayer::with_progress_interval`].
+///
+/// The lines logged through [`JobContext::logger`] are stored with the job once it stops,
+/// see [`AckLayer::with_job_logs`].
+///
+/// [`JobTimeout`]: crate::worker::timeout::JobTimeout
+/// [`JobContext::cancellation_token`]: crate::context::JobContext::cancellation_token
+/// [`JobContext::progress_reporter`]: crate::context::JobContext::progress_reporter
+/// [`JobContext::logger`]: crate::context::JobContext::logger
+pub struct AckLayer<T, Req> {
     worker: WorkerRef,
     storage: T,
-    period: Duration,
+    result_retention: Option<Duration>,
+    progress_interval: Duration,
+    max_log_lines: Option<usize>,
+    running: RunningJobs,
     req_type: PhantomData<Req>,
 }
 
-impl<T, Req> std::fmt::Debug for KeepAliveLayer<T, Req> {
+/// The cancellation tokens of the jobs a worker is running.
+/// Clones share the same jobs, so the worker's keep-alive can trip them
+#[derive(Clone, Default)]
+pub(crate) struct RunningJobs(Arc<Mutex<HashMap<String, CancellationToken>>>);
+
+impl RunningJobs {
+    fn insert(&self, job_id: String, token: CancellationToken) {
+        self.0.lock().unwrap().insert(job_id, token);
+    }
+
+    fn remove(&self, job_id: &str) {
+        self.0.lock().unwrap().remove(job_id);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// Trips the tokens of the jobs in `job_ids` that are still running
+    pub(crate) fn cancel(&self, job_ids: &[String]) {
+        let running = self.0.lock().unwrap();
+        for token in job_ids.iter().filter_map(|job_id| running.get(job_id)) {
+            token.cancel();
+        }
+    }
+}
+
+impl<T, Req> std::fmt::Debug for AckLayer<T, Req> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("KeepAliveLayer")
+        f.debug_struct("AckLayer")
             .field("worker_name", &self.worker.name())
-            .field("period", &self.period)
             .finish()
     }
 }
 
-impl<T, Req> KeepAliveLayer<T, Req> {
-    /// Creates a new [`PeriodicLayer`] with the provided `make_request` closure
-    /// and `period`.
-    ///
-    /// `make_request` returns a request to be called on the inner service.
-    /// `period` gives with interval with which to send the request from `make_request`.
-    pub fn new(worker: WorkerRef, storage: T, period: Duration) -> Self {
-        KeepAliveLayer {
+impl<T, Req> AckLayer<T, Req> {
+    /// Creates a new [`AckLayer`] that reports results for `worker` to `storage`.
+    pub fn new(worker: WorkerRef, storage: T) -> Self {
+        AckLayer {
             worker,
             storage,
-            period,
+            result_retention: None,
+            progress_interval: Duration::from_secs(1),
+            max_log_lines: None,
+            running: RunningJobs::default(),
+            req_type: PhantomData,
+        }
+    }
+
+    /// Save the value returned by each successful job for `retention`,
+    /// see [`Storage::fetch_result`].
+    pub fn with_result_retention(mut self, retention: Duration) -> Self {
+        self.result_retention = Some(retention);
+        self
+    }
+
+    /// Write the progress reported by a job at most once every `interval`. Defaults to 1s
+    pub fn with_progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Store the lines logged by each job, keeping up to `max_lines` per job,
+    /// see [`Storage::append_logs`].
+    pub fn with_job_logs(mut self, max_lines: usize) -> Self {
+        self.max_log_lines = Some(max_lines);
+        self
+    }
+
+    pub(crate) fn running_jobs(&self) -> RunningJobs {
+        self.running.clone()
+    }
+}
+
+impl<S, T: Clone, Req> Layer<S> for AckLayer<T, Req> {
+    type Service = AckService<S, T, Req>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AckService {
+            inner,
+            worker: self.worker.clone(),
+            storage: self.storage.clone(),
+            result_retention: self.result_retention,
+            progress_interval: self.progress_interval,
+            max_log_lines: self.max_log_lines,
+            running: self.running.clone(),
             req_type: PhantomData,
         }
     }
 }
 
-impl<S, T, F, Request> Layer<S> for KeepAliveLayer<T, Request>
+/// The service produced by [`AckLayer`].
+pub struct AckService<S, T, Req> {
+    inner: S,
+    worker: WorkerRef,
+    storage: T,
+    result_retention: Option<Duration>,
+    progress_interval: Duration,
+    max_log_lines: Option<usize>,
+    running: RunningJobs,
+    req_type: PhantomData<Req>,
+}
+
+impl<S, T, Req> std::fmt::Debug for AckService<S, T, Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AckService")
+            .field("worker_name", &self.worker.name())
+            .field("service", &std::any::type_name::<S>())
+            .finish()
+    }
+}
+
+impl<S, T, Req> Service<JobRequest<Req>> for AckService<S, T, Req>
 where
-    S: Service<JobRequest<Request>, Future = F> + Send + 'static,
-    F: Future<Output = Result<S::Response, S::Error>> + Send + 'static,
-    Request: Send,
-    T: Storage<Output = Request> + Send + 'static,
+    S: Service<JobRequest<Req>>,
+    S::Future: Send + 'static,
+    S::Response: Any + Send + Serialize,
+    S::Error: Display + Send + From<JobError> + Any,
+    T: Storage<Output = Req> + Send + Sync + 'static,
+    Req: Job + 'static,
 {
-    type Service = S;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
 
-    fn layer(&self, inner: S) -> Self::Service {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: JobRequest<Req>) -> Self::Future {
         let mut storage = self.storage.clone();
-        let worker_ref = self.worker.clone();
-        let period = self.period;
-        let make_worker = {
-            let period = period;
-            async move {
-                let mut interval = interval(period);
-
-                loop {
-                    storage
-                        .keep_alive::<S>(worker_ref.name().to_string())
-                        .await
-                        .unwrap();
-                    let _ = interval.tick().await;
+        let worker_id = self.worker.name().to_string();
+        let job_id = req.id();
+        let result_retention = self.result_retention;
+        let token = req.context().cancellation_token().clone();
+        let running = self.running.clone();
+        running.insert(job_id.clone(), token.clone());
+        let progress = progress_reporter(&self.storage, job_id.clone(), self.progress_interval);
+        req.context_mut().set_progress_reporter(progress.clone());
+        let max_log_lines = self.max_log_lines;
+        let logger = max_log_lines.map(JobLogger::capturing).unwrap_or_default();
+        req.context_mut().set_logger(logger.clone());
+        let fut = self.inner.call(req);
+        async move {
+            let res = fut.await;
+            running.remove(&job_id);
+            progress.flush().await;
+            if let Some(max_lines) = max_log_lines {
+                let lines = logger.take();
+                if !lines.is_empty() {
+                    if let Err(e) = storage.append_logs(job_id.clone(), &lines, max_lines).await {
+                        warn!("Failed to store the logs of job {job_id}: {e}");
+                    }
+                }
+            }
+            let report = match &res {
+                Err(_) if token.is_cancelled() => storage.abort(worker_id, job_id.clone()).await,
+                Ok(res) => match (res as &dyn Any).downcast_ref::<JobResult>() {
+                    Some(JobResult::Retry) => {
+                        retry_job(&mut storage, worker_id, job_id.clone(), None).await
+                    }
+                    Some(JobResult::Kill) => storage.kill(worker_id, job_id.clone()).await,
+                    Some(JobResult::Reschedule(wait)) => {
+                        reschedule_job(&mut storage, worker_id, job_id.clone(), *wait).await
+                    }
+                    Some(JobResult::Success) => storage.ack(worker_id, job_id.clone()).await,
+                    None => {
+                        if let Some(retention) = result_retention {
+                            let saved = match serde_json::to_value(res) {
+                                Ok(result) => {
+                                    storage.save_result(job_id.clone(), result, retention).await
+                                }
+                                Err(e) => Err(e.into()),
+                            };
+                            if let Err(e) = saved {
+                                warn!("Failed to save the result of job {job_id}: {e}");
+                            }
+                        }
+                        storage.ack(worker_id, job_id.clone()).await
+                    }
+                },
+                Err(e) => {
+                    let failure = describe_failure(e);
+                    retry_job(&mut storage, worker_id, job_id.clone(), Some(failure)).await
                 }
+            };
+            if let Err(e) = report {
+                warn!("Failed to report the result of job {job_id} to storage: {e}");
             }
-        };
-        tokio::spawn(make_worker);
+            res
+        }
+        .boxed()
+    }
+}
 
-        Layer::<S>::layer(&Identity::new(), inner)
+/// Builds the reporter that writes the progress of `job_id` to `storage`
+fn progress_reporter<T>(storage: &T, job_id: String, interval: Duration) -> ProgressReporter
+where
+    T: Storage + Sync + 'static,
+{
+    let storage = storage.clone();
+    ProgressReporter::new(interval, move |progress| {
+        let mut storage = storage.clone();
+        let job_id = job_id.clone();
+        async move {
+            storage
+                .update_progress(job_id, &progress)
+                .await
+                .map_err(Into::into)
+        }
+        .boxed()
+    })
+}
+
+/// Describes a failed attempt, following the chain of sources of [`JobError`]s and [`BoxError`]s.
+/// The attempt is filled in once the job is fetched.
+fn describe_failure<E: Display + Any>(error: &E) -> JobFailure {
+    let any = error as &dyn Any;
+    if let Some(error) = any.downcast_ref::<JobError>() {
+        return JobFailure::new(0, error);
+    }
+    if let Some(error) = any.downcast_ref::<BoxError>() {
+        return JobFailure::new(0, error.as_ref());
+    }
+    JobFailure {
+        attempt: 0,
+        message: error.to_string(),
+        chain: Vec::new(),
+        failed_at: Utc::now(),
     }
 }
+
+/// Persists an attempt and either retries, reschedules after the job's backoff, or kills the job.
+async fn retry_job<T: Storage>(
+    storage: &mut T,
+    worker_id: String,
+    job_id: String,
+    failure: Option<JobFailure>,
+) -> StorageResult<()> {
+    let mut job = storage
+        .fetch_by_id(job_id.clone())
+        .await?
+        .ok_or(StorageError::NotFound)?;
+    job.record_attempt();
+    if let Some(mut failure) = failure {
+        failure.attempt = job.attempts();
+        job.set_last_error(failure.message.clone());
+        job.set_last_failure(Some(failure));
+    }
+    storage.update_by_id(job_id.clone(), &job).await?;
+    if job.attempts() >= job.max_attempts() {
+        return storage.kill(worker_id, job_id).await;
+    }
+    let wait = T::Output::BACKOFF.delay(job.attempts());
+    if wait.is_zero() {
+        storage.retry(worker_id, job_id).await
+    } else {
+        storage.reschedule(worker_id, &job, wait).await
+    }
+}
+
+/// Puts the job back into storage to be run after `wait`.
+async fn reschedule_job<T: Storage>(
+    storage: &mut T,
+    worker_id: String,
+    job_id: String,
+    wait: Duration,
+) -> StorageResult<()> {
+    let job = storage
+        .fetch_by_id(job_id)
+        .await?
+        .ok_or(StorageError::NotFound)?;
+    storage.reschedule(worker_id, &job, wait).await
+}
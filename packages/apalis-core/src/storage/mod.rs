@@ -1,5 +1,7 @@
+mod batch;
 /// Allows for building workers that consume a [Storage]
 pub mod builder;
+mod dead_letter;
 mod error;
 mod layers;
 use std::time::Duration;
@@ -7,13 +9,17 @@ use std::time::Duration;
 use chrono::{DateTime, Utc};
 
 use crate::{
+    context::{JobLogLine, JobProgress},
     job::JobStream,
-    job::{Job, JobStreamResult},
+    job::{Job, JobId, JobStreamResult},
     request::JobRequest,
 };
 
+pub use self::batch::{BatchCallbacks, BatchId, BatchProgress};
+pub use self::dead_letter::{DeadLetter, DeadLetterFilter};
 #[cfg(feature = "storage")]
 pub use self::error::StorageError;
+pub use self::layers::{AckLayer, AckService};
 
 /// Represents a Storage Result
 pub type StorageResult<I> = Result<I, StorageError>;
@@ -22,17 +28,23 @@ pub type StorageResult<I> = Result<I, StorageError>;
 ///
 /// [Builder]: crate::builder::WorkerBuilder
 #[async_trait::async_trait]
-pub trait Storage: Clone {
+pub trait Storage: Clone + Send {
     /// The type of job that can be persisted
     type Output: Job;
 
-    /// Pushes a job to a storage
-    ///
-    /// TODO: return id
-    async fn push(&mut self, job: Self::Output) -> StorageResult<()>;
+    /// Pushes a job to a storage, returning its id
+    async fn push(&mut self, job: Self::Output) -> StorageResult<JobId> {
+        self.push_with(job, PushOptions::new()).await
+    }
+
+    /// Push a job into the scheduled set, returning its id
+    async fn schedule(&mut self, job: Self::Output, on: DateTime<Utc>) -> StorageResult<JobId> {
+        self.push_with(job, PushOptions::new().with_run_at(on))
+            .await
+    }
 
-    /// Push a job into the scheduled set
-    async fn schedule(&mut self, job: Self::Output, on: DateTime<Utc>) -> StorageResult<()>;
+    /// Pushes a job to a storage with the provided [PushOptions], returning its id
+    async fn push_with(&mut self, job: Self::Output, options: PushOptions) -> StorageResult<JobId>;
 
     /// Return the number of pending jobs from the queue
     async fn len(&self) -> StorageResult<i64>;
@@ -40,21 +52,60 @@ pub trait Storage: Clone {
     /// Fetch a job given an id
     async fn fetch_by_id(&self, job_id: String) -> StorageResult<Option<JobRequest<Self::Output>>>;
 
-    /// Get the stream of jobs
-    fn consume(&mut self, worker_id: String, interval: Duration) -> JobStreamResult<Self::Output>;
+    /// Get the stream of jobs, polling every `interval` for up to `buffer_size` jobs
+    fn consume(
+        &mut self,
+        worker_id: String,
+        interval: Duration,
+        buffer_size: usize,
+    ) -> JobStreamResult<Self::Output>;
 
     /// Acknowledge a job which returns [JobResult::Success]
+    ///
+    /// [JobResult::Success]: crate::response::JobResult::Success
     async fn ack(&mut self, worker_id: String, job_id: String) -> StorageResult<()>;
 
     /// Retry a job which returns [JobResult::Retry]
+    ///
+    /// [JobResult::Retry]: crate::response::JobResult::Retry
     async fn retry(&mut self, worker_id: String, job_id: String) -> StorageResult<()>;
 
     /// Called by a Worker to keep the storage alive and prevent jobs from being deemed as orphaned
     async fn keep_alive<Service>(&mut self, worker_id: String) -> StorageResult<()>;
 
     /// Kill a job that returns [JobResult::Kill]
+    ///
+    /// [JobResult::Kill]: crate::response::JobResult::Kill
     async fn kill(&mut self, worker_id: String, job_id: String) -> StorageResult<()>;
 
+    /// Cancel a job. A job that has not started yet is taken out of the queue and
+    /// [JobState::Cancelled] right away, along with the jobs waiting on it.
+    /// A running job is only flagged, its worker then trips [JobContext::cancellation_token].
+    ///
+    /// Cancelling a job that does not exist or has finished fails with [StorageError::NotFound]
+    ///
+    /// The default implementation fails with [StorageError::Unsupported], as does
+    /// [Storage::abort], while [Storage::fetch_cancelled] finds no flagged job.
+    ///
+    /// [JobState::Cancelled]: crate::request::JobState::Cancelled
+    /// [JobContext::cancellation_token]: crate::context::JobContext::cancellation_token
+    async fn cancel(&mut self, _job_id: String) -> StorageResult<()> {
+        Err(StorageError::Unsupported("cancellation"))
+    }
+
+    /// Get the ids of the jobs running on `worker_id` that were flagged by [Storage::cancel]
+    async fn fetch_cancelled(&self, _worker_id: String) -> StorageResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Mark a running job that stopped after being flagged by [Storage::cancel] as
+    /// [JobState::Cancelled], along with the jobs waiting on it
+    ///
+    /// [JobState::Cancelled]: crate::request::JobState::Cancelled
+    async fn abort(&mut self, _worker_id: String, _job_id: String) -> StorageResult<()> {
+        Err(StorageError::Unsupported("cancellation"))
+    }
+
     /// Update a job details
     async fn update_by_id(
         &self,
@@ -62,19 +113,98 @@ pub trait Storage: Clone {
         job: &JobRequest<Self::Output>,
     ) -> StorageResult<()>;
 
+    /// Record the progress reported by a running job, see [JobContext::progress_reporter]
+    ///
+    /// The default implementation fails with [StorageError::Unsupported]
+    ///
+    /// [JobContext::progress_reporter]: crate::context::JobContext::progress_reporter
+    async fn update_progress(
+        &mut self,
+        _job_id: String,
+        _progress: &JobProgress,
+    ) -> StorageResult<()> {
+        Err(StorageError::Unsupported("job progress"))
+    }
+
+    /// Append the lines logged by a job during an attempt, keeping only the last `max_lines`
+    /// lines of the job. They are read back with [JobStreamExt::job_logs]
+    ///
+    /// The default implementation fails with [StorageError::Unsupported]
+    ///
+    /// [JobStreamExt::job_logs]: crate::job::JobStreamExt::job_logs
+    async fn append_logs(
+        &mut self,
+        _job_id: String,
+        _lines: &[JobLogLine],
+        _max_lines: usize,
+    ) -> StorageResult<()> {
+        Err(StorageError::Unsupported("job logs"))
+    }
+
     /// Used for scheduling jobs
     async fn heartbeat(&mut self, pulse: StorageWorkerPulse) -> StorageResult<bool>;
 
-    /// Kill a job that returns [JobResult::Reschedule]
-    /// [`JobResult::Reschedule`]: crate::response::JobResult
+    /// Reschedule a job running on `worker_id` that returns [JobResult::Reschedule]
+    ///
+    /// [JobResult::Reschedule]: crate::response::JobResult::Reschedule
     async fn reschedule(
         &mut self,
+        worker_id: String,
         job: &JobRequest<Self::Output>,
         wait: Duration,
     ) -> StorageResult<()>;
 
+    /// Opens a new batch of jobs. Jobs are pushed into it with [PushOptions::with_batch]
+    /// until it is closed, then one of `callbacks` is pushed once they have all finished
+    ///
+    /// The default implementation fails with [StorageError::Unsupported], as do
+    /// [Storage::close_batch] and [Storage::batch_progress]
+    async fn open_batch(
+        &mut self,
+        _callbacks: BatchCallbacks<Self::Output>,
+    ) -> StorageResult<BatchId> {
+        Err(StorageError::Unsupported("batches"))
+    }
+
+    /// Closes a batch, so no more jobs can be pushed into it
+    async fn close_batch(&mut self, _batch_id: &BatchId) -> StorageResult<()> {
+        Err(StorageError::Unsupported("batches"))
+    }
+
+    /// Get the progress of a batch, if it exists
+    async fn batch_progress(&self, _batch_id: &BatchId) -> StorageResult<Option<BatchProgress>> {
+        Err(StorageError::Unsupported("batches"))
+    }
+
+    /// Save the value returned by a job, so that it can be read back with
+    /// [Storage::fetch_result] until `retention` has passed
+    ///
+    /// The default implementation fails with [StorageError::Unsupported], as does
+    /// [Storage::fetch_result]
+    async fn save_result(
+        &mut self,
+        _job_id: String,
+        _result: serde_json::Value,
+        _retention: Duration,
+    ) -> StorageResult<()> {
+        Err(StorageError::Unsupported("job results"))
+    }
+
+    /// Fetch the value returned by a job, if it was saved and has not expired yet
+    async fn fetch_result(&self, _job_id: String) -> StorageResult<Option<serde_json::Value>> {
+        Err(StorageError::Unsupported("job results"))
+    }
+
     /// Used to recover jobs when a Worker shuts down.
-    async fn reenqueue_active(&mut self, _job_ids: Vec<String>) -> StorageResult<()> {
+    ///
+    /// Every job that is still running and locked by `worker_id` is put back into the queue
+    /// so that another [Worker] may consume them. This covers jobs whose shutdown timed out
+    /// as well as jobs that were claimed but never started.
+    ///
+    /// The default implementation only logs a warning, leaving the jobs locked by `worker_id`
+    /// until they are picked up as orphans.
+    async fn reenqueue_active(&mut self, worker_id: String) -> StorageResult<()> {
+        tracing::warn!("The storage cannot re-enqueue the active jobs of worker {worker_id}");
         Ok(())
     }
 
@@ -85,6 +215,174 @@ pub trait Storage: Clone {
     }
 }
 
+/// Options that control how a job is pushed to a [Storage]
+#[derive(Debug, Clone)]
+pub struct PushOptions {
+    id: Option<JobId>,
+    max_attempts: i32,
+    run_at: Option<DateTime<Utc>>,
+    timeout: Option<Duration>,
+    priority: i32,
+    unique_key: Option<String>,
+    unique_policy: UniquePolicy,
+    unique_ttl: Option<Duration>,
+    parents: Vec<JobId>,
+    batch: Option<BatchId>,
+}
+
+impl Default for PushOptions {
+    fn default() -> Self {
+        PushOptions {
+            id: None,
+            max_attempts: 25,
+            run_at: None,
+            timeout: None,
+            priority: 0,
+            unique_key: None,
+            unique_policy: UniquePolicy::default(),
+            unique_ttl: None,
+            parents: Vec::new(),
+            batch: None,
+        }
+    }
+}
+
+impl PushOptions {
+    /// Build the default options, equivalent to [Storage::push]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the provided id instead of generating one
+    pub fn with_id(mut self, id: JobId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Set the number of attempts before the job is killed. Defaults to 25
+    pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the earliest time the job can run. Defaults to now
+    pub fn with_run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = Some(run_at);
+        self
+    }
+
+    /// Set how long a single attempt of the job may run before it is failed.
+    /// Defaults to [Job::TIMEOUT]
+    ///
+    /// [Job::TIMEOUT]: crate::job::Job::TIMEOUT
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the priority of the job. Jobs with a higher priority are consumed first,
+    /// jobs with the same priority in the order of their run time. Defaults to 0
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Only push the job if no other job of the same type holding `key` is pending or running.
+    /// `policy` decides what happens when the key is held.
+    ///
+    /// The key is released once the job is done or killed, or after [PushOptions::with_unique_ttl]
+    pub fn with_unique_key<K: Into<String>>(mut self, key: K, policy: UniquePolicy) -> Self {
+        self.unique_key = Some(key.into());
+        self.unique_policy = policy;
+        self
+    }
+
+    /// Release the unique key after `ttl` even if the job has not finished yet
+    pub fn with_unique_ttl(mut self, ttl: Duration) -> Self {
+        self.unique_ttl = Some(ttl);
+        self
+    }
+
+    /// Only run the job once every job in `parents` is done. Until then the job is
+    /// [JobState::Blocked]. If a parent is killed or cancelled, the job is killed or cancelled too.
+    ///
+    /// [JobState::Blocked]: crate::request::JobState::Blocked
+    pub fn with_parents<I: IntoIterator<Item = JobId>>(mut self, parents: I) -> Self {
+        self.parents.extend(parents);
+        self
+    }
+
+    /// Push the job into an open batch, see [Storage::open_batch].
+    /// Pushing into a batch that is closed or does not exist fails with [StorageError::NotFound]
+    pub fn with_batch(mut self, batch_id: BatchId) -> Self {
+        self.batch = Some(batch_id);
+        self
+    }
+
+    /// Get the provided id, if any
+    pub fn id(&self) -> Option<&JobId> {
+        self.id.as_ref()
+    }
+
+    /// Get the max attempts
+    pub fn max_attempts(&self) -> i32 {
+        self.max_attempts
+    }
+
+    /// Get the provided run time, if any
+    pub fn run_at(&self) -> Option<&DateTime<Utc>> {
+        self.run_at.as_ref()
+    }
+
+    /// Get the provided timeout, if any
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Get the priority
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Get the unique key, if any
+    pub fn unique_key(&self) -> Option<&str> {
+        self.unique_key.as_deref()
+    }
+
+    /// Get the policy applied when the unique key is held
+    pub fn unique_policy(&self) -> UniquePolicy {
+        self.unique_policy
+    }
+
+    /// Get how long the unique key is held at most, if limited
+    pub fn unique_ttl(&self) -> Option<Duration> {
+        self.unique_ttl
+    }
+
+    /// Get the jobs that have to be done before this job runs
+    pub fn parents(&self) -> &[JobId] {
+        &self.parents
+    }
+
+    /// Get the batch the job is pushed into, if any
+    pub fn batch(&self) -> Option<&BatchId> {
+        self.batch.as_ref()
+    }
+}
+
+/// What to do when a job is pushed with a unique key that is already held
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UniquePolicy {
+    /// Fail the push with [StorageError::Duplicate]
+    #[default]
+    Reject,
+    /// Replace the job holding the key if it has not started yet,
+    /// otherwise fail the push with [StorageError::Duplicate]
+    Replace,
+    /// Skip the push and return the id of the job holding the key
+    Ignore,
+}
+
 /// Each [Worker] sends heartbeat messages to storage
 #[non_exhaustive]
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -99,6 +397,8 @@ pub enum StorageWorkerPulse {
     RenqueueOrpharned {
         /// the count of orphaned jobs
         count: i32,
+        /// how long a worker can go without a keep-alive before its jobs are orphaned
+        timeout_worker: Duration,
     },
 }
 
@@ -110,6 +410,6 @@ where
 
     /// Consume the stream of jobs from Storage
     fn stream(&mut self, worker_id: String, interval: Duration) -> JobStreamResult<S::Output> {
-        self.consume(worker_id, interval)
+        self.consume(worker_id, interval, 1)
     }
 }
@@ -25,6 +25,7 @@ impl Timer for TokioTimer {
     }
 }
 
+#[allow(dead_code)]
 struct TokioTimeout<T> {
     inner: Pin<Box<tokio::time::Timeout<T>>>,
 }
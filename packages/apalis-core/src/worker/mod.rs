@@ -1,10 +1,15 @@
 /// Represents a worker that is ready to consume jobs
 pub mod ready;
+/// Enforces the timeout of every job run by a worker
+pub mod timeout;
 use async_trait::async_trait;
+use futures::{Future, FutureExt};
 use graceful_shutdown::Shutdown;
 use std::fmt;
 use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
 use thiserror::Error;
+use tokio::sync::watch;
 
 use crate::executor::Executor;
 
@@ -25,6 +30,87 @@ impl WorkerRef {
     }
 }
 
+/// Commonly used worker types
+pub mod prelude {
+    pub use super::{WorkerContext, WorkerError, WorkerEvent, WorkerListener, WorkerRef};
+}
+
+/// Events emitted by a worker while it is running
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum WorkerEvent {
+    /// The worker started consuming jobs
+    Start,
+    /// The worker fetched a job from its source and started processing it
+    JobFetched {
+        /// The id of the fetched job
+        job_id: String,
+    },
+    /// A job completed successfully
+    JobSucceeded {
+        /// The id of the job
+        job_id: String,
+    },
+    /// A job returned an error
+    JobFailed {
+        /// The id of the job
+        job_id: String,
+        /// The error returned by the job
+        error: String,
+    },
+    /// The source had no jobs available. Emitted once until a job is fetched again
+    Idle,
+    /// An error occurred outside of a job, eg. while sending a keep-alive to the storage
+    Error(String),
+    /// The worker stopped consuming jobs and all its running jobs have completed
+    Stop,
+    /// The worker stopped and is restarted by its monitor after `delay`
+    Restart {
+        /// How many times the worker has been restarted, including this one
+        attempt: usize,
+        /// How long the monitor waits before restarting the worker
+        delay: std::time::Duration,
+    },
+    /// The worker stopped and its monitor gave up restarting it
+    GaveUp {
+        /// How many times the worker was restarted before giving up
+        restarts: usize,
+    },
+}
+
+/// Receives the [WorkerEvent]s of the workers it is attached to
+pub trait WorkerListener: Send + Sync {
+    /// Called for every event emitted by the worker identified by `worker_id`
+    fn on_event(&self, worker_id: &str, event: &WorkerEvent);
+}
+
+/// The listeners attached to a worker.
+/// Clones share the same listeners so they can be attached at any point while building.
+#[derive(Clone, Default)]
+pub(crate) struct WorkerListeners(Arc<RwLock<Vec<Arc<dyn WorkerListener>>>>);
+
+impl WorkerListeners {
+    pub(crate) fn push(&self, listener: Arc<dyn WorkerListener>) {
+        self.0.write().unwrap().push(listener);
+    }
+
+    /// Attaches the listeners of `other` too, eg. the listeners of the monitor running the worker
+    pub(crate) fn extend(&self, other: &WorkerListeners) {
+        let others = other.0.read().unwrap().clone();
+        self.0.write().unwrap().extend(others);
+    }
+
+    pub(crate) fn emit(&self, worker_id: &str, event: WorkerEvent) {
+        for listener in self.0.read().unwrap().iter() {
+            listener.on_event(worker_id, &event);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+}
+
 /// Possible errors that can occur when starting a worker.
 #[derive(Error, Debug)]
 pub enum WorkerError {
@@ -37,6 +123,9 @@ pub enum WorkerError {
     /// An error occurred while trying to start the worker.
     #[error("Failed to start worker: {0}")]
     StartError(String),
+    /// The job stream ended because of an error.
+    #[error("Job stream failed: {0}")]
+    StreamError(String),
 }
 /// The `Worker` trait represents a type that can execute jobs. It is used
 /// to define workers that can be managed by the `Monitor`.
@@ -60,27 +149,96 @@ pub trait Worker<Job>: Sized {
     /// This method should run indefinitely or until it returns an error.
     /// If an error occurs, it should return a `WorkerError` describing
     /// the reason for the failure.
-    async fn start<E: Executor + Send>(self, ctx: WorkerContext<E>) -> Result<(), WorkerError>;
+    async fn start<E: Executor + Send + Sync + 'static>(
+        self,
+        ctx: WorkerContext<E>,
+    ) -> Result<(), WorkerError>;
 }
 
 /// Stores the Workers context
+///
+/// Every job consumed by a [`ready::ReadyWorker`] carries a clone of its worker's context
+/// in the [`JobContext`] data, so handlers can spawn follow-up work or stop the worker:
+///
+/// ```rust,ignore
+/// async fn handle(job: Email, ctx: JobContext) {
+///     let worker = ctx.data_opt::<WorkerContext<TokioExecutor>>().unwrap();
+///     worker.spawn(async { /* follow-up work */ });
+/// }
+/// ```
+///
+/// [`JobContext`]: crate::context::JobContext
+#[derive(Clone)]
 pub struct WorkerContext<E: Executor> {
+    /// This worker's own shutdown, initiated when the [`Monitor`] shuts down
+    ///
+    /// [`Monitor`]: crate::monitor::Monitor
     pub(crate) shutdown: Shutdown,
+    /// Signalled when a graceful shutdown times out and workers must stop waiting for their jobs
+    pub(crate) terminate: Shutdown,
     pub(crate) executor: E,
+    /// The listeners attached to the [`Monitor`] running this worker
+    ///
+    /// [`Monitor`]: crate::monitor::Monitor
+    pub(crate) listeners: WorkerListeners,
+    /// Whether the worker should stop fetching jobs, set through a [`MonitorHandle`]
+    ///
+    /// [`MonitorHandle`]: crate::monitor::MonitorHandle
+    pub(crate) pause: watch::Receiver<bool>,
 }
 
 impl<E: Executor> fmt::Debug for WorkerContext<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("WorkerContext")
             .field("shutdown", &["Shutdown handle"])
+            .field("is_shutting_down", &self.shutdown.is_shutting_down())
             .finish()
     }
 }
 
 impl<E: Executor + Send + 'static> WorkerContext<E> {
-    /// Allows spawning of futures that will be gracefully shutdown by the worker
-    pub fn spawn() {}
+    /// Allows spawning of futures that will be gracefully shutdown by the worker.
+    ///
+    /// The worker does not finish shutting down until the future completes,
+    /// unless the shutdown times out in which case the future is dropped.
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) -> E::JoinHandle {
+        self.executor.spawn(
+            self.shutdown
+                .graceful(self.terminate.cancel_on_shutdown(future))
+                .map(|_| ()),
+        )
+    }
 
-    /// Calling this function triggers shutting down the worker
-    pub fn shutdown(&self) {}
+    /// Calling this function triggers shutting down the worker.
+    ///
+    /// The worker stops consuming jobs and exits once its running jobs and spawned
+    /// futures have completed. Other workers registered on the same monitor are not affected.
+    pub fn shutdown(&self) {
+        self.shutdown.shutdown();
+    }
+
+    /// Returns true if the worker has started shutting down
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_shutting_down()
+    }
+
+    /// Returns true if the worker is paused and should not fetch new jobs
+    pub fn is_paused(&self) -> bool {
+        *self.pause.borrow()
+    }
+
+    /// Waits until the worker is not paused.
+    /// Returns false if the worker started shutting down while paused.
+    pub(crate) async fn resumed(&self) -> bool {
+        let mut pause = self.pause.clone();
+        while *pause.borrow_and_update() {
+            match self.shutdown.cancel_on_shutdown(pause.changed()).await {
+                Some(Ok(())) => {}
+                // Nobody can resume the worker anymore
+                Some(Err(_)) => return true,
+                None => return false,
+            }
+        }
+        true
+    }
 }
@@ -1,22 +1,37 @@
-use std::{error::Error, fmt::Debug};
+use std::{
+    error::Error,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use futures::{Future, Stream, StreamExt};
-use tokio::sync::mpsc::channel;
+use futures::{future::BoxFuture, Future, FutureExt, Stream, StreamExt};
+use tokio::sync::Semaphore;
 use tower::{Service, ServiceExt};
 use tracing::info;
 use tracing::warn;
 
 use crate::executor::Executor;
 use crate::job::Job;
+use crate::request::JobRequest;
 
-use super::{Worker, WorkerContext};
+use super::{Worker, WorkerContext, WorkerError, WorkerEvent, WorkerListeners};
 use std::fmt::Formatter;
 
+/// Hands back the jobs a worker still holds once it has stopped
+pub(crate) type Reenqueue = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
 /// A worker that is ready to consume jobs
 pub struct ReadyWorker<Stream, Service> {
     pub(crate) name: String,
     pub(crate) stream: Stream,
     pub(crate) service: Service,
+    pub(crate) beats: Vec<BoxFuture<'static, ()>>,
+    pub(crate) reenqueue: Option<Reenqueue>,
+    pub(crate) concurrency: Option<usize>,
+    pub(crate) listeners: WorkerListeners,
 }
 
 impl<Stream, Service> Debug for ReadyWorker<Stream, Service> {
@@ -25,21 +40,24 @@ impl<Stream, Service> Debug for ReadyWorker<Stream, Service> {
             .field("name", &self.name)
             .field("stream", &std::any::type_name::<Stream>())
             .field("service", &std::any::type_name::<Service>())
+            .field("beats", &self.beats.len())
+            .field("reenqueue", &self.reenqueue.is_some())
+            .field("concurrency", &self.concurrency)
+            .field("listeners", &self.listeners.len())
             .finish()
     }
 }
 
 #[async_trait::async_trait]
 impl<
-        Strm: Unpin + Send + Stream<Item = Result<Option<Req>, E>> + 'static,
-        Serv: Service<Req, Future = Fut> + Send + 'static,
-        J: Job + 'static,
+        Strm: Unpin + Send + Stream<Item = Result<Option<JobRequest<J>>, E>> + 'static,
+        Serv: Service<JobRequest<J>, Future = Fut> + Send + 'static,
+        J: Job + Send + 'static,
         E: 'static + Send + Error + Sync,
-        Req: Send,
-        Fut: Future + Send + 'static,
+        Fut: Future<Output = Result<Serv::Response, Serv::Error>> + Send + 'static,
     > Worker<J> for ReadyWorker<Strm, Serv>
 where
-    <Serv as Service<Req>>::Error: Debug,
+    <Serv as Service<JobRequest<J>>>::Error: Debug,
 {
     type Service = Serv;
     type Source = Strm;
@@ -47,41 +65,279 @@ where
     fn name(&self) -> String {
         self.name.to_string()
     }
-    async fn start<Exec: Executor + Send>(
+    async fn start<Exec: Executor + Send + Sync + 'static>(
         self,
         ctx: WorkerContext<Exec>,
-    ) -> Result<(), super::WorkerError> {
+    ) -> Result<(), WorkerError> {
+        let listeners = self.listeners;
+        listeners.extend(&ctx.listeners);
+        listeners.emit(&self.name, WorkerEvent::Start);
+        for beat in self.beats {
+            ctx.executor
+                .spawn(ctx.shutdown.cancel_on_shutdown(beat).map(|_| ()));
+        }
         let mut service = self.service;
         let mut stream = ctx.shutdown.graceful_stream(self.stream);
-        let (send, mut recv) = channel::<()>(1);
+        let inflight = Arc::new(AtomicUsize::new(0));
+
+        let semaphore = self.concurrency.map(|max| Arc::new(Semaphore::new(max)));
+        let mut last_error = None;
+        let mut idle = false;
 
-        while let Some(res) = tokio::select! {
-            res = stream.next() => res,
-            _ = ctx.shutdown.clone() => None
-        } {
-            let send_clone = send.clone();
+        loop {
+            // A paused worker stops fetching jobs, while its beats and running jobs carry on
+            if !ctx.resumed().await {
+                break;
+            }
+            // Wait for a free slot before pulling the next job from the source
+            let permit = match &semaphore {
+                Some(semaphore) => {
+                    match ctx
+                        .shutdown
+                        .cancel_on_shutdown(semaphore.clone().acquire_owned())
+                        .await
+                    {
+                        Some(Ok(permit)) => Some(permit),
+                        _ => break,
+                    }
+                }
+                None => None,
+            };
+            let res = match tokio::select! {
+                res = stream.next() => res,
+                _ = ctx.shutdown.clone() => None
+            } {
+                Some(res) => res,
+                None => break,
+            };
             match res {
-                Ok(Some(item)) => {
+                Ok(Some(mut item)) => {
+                    last_error = None;
+                    idle = false;
+                    let job_id = item.id();
+                    listeners.emit(
+                        &self.name,
+                        WorkerEvent::JobFetched {
+                            job_id: job_id.clone(),
+                        },
+                    );
+                    inflight.fetch_add(1, Ordering::Relaxed);
+                    item.context_mut().insert(ctx.clone());
                     let svc = service.ready().await.unwrap();
                     let fut = svc.call(item);
-                    ctx.executor.spawn(ctx.shutdown.graceful(async move {
-                        fut.await;
-                    }));
-                    drop(send_clone);
+                    let inflight = inflight.clone();
+                    let listeners = listeners.clone();
+                    let name = self.name.clone();
+                    ctx.spawn(async move {
+                        let event = match fut.await {
+                            Ok(_) => WorkerEvent::JobSucceeded {
+                                job_id: job_id.clone(),
+                            },
+                            Err(e) => WorkerEvent::JobFailed {
+                                job_id: job_id.clone(),
+                                error: format!("{e:?}"),
+                            },
+                        };
+                        inflight.fetch_sub(1, Ordering::Relaxed);
+                        drop(permit);
+                        listeners.emit(&name, event);
+                    });
                 }
                 Err(e) => {
                     warn!("Error processing stream {e}");
-                    drop(send_clone);
+                    listeners.emit(&self.name, WorkerEvent::Error(e.to_string()));
+                    last_error = Some(e.to_string());
                 }
-                _ => {
-                    drop(send_clone);
+                Ok(None) => {
+                    last_error = None;
+                    if !idle {
+                        idle = true;
+                        listeners.emit(&self.name, WorkerEvent::Idle);
+                    }
                 }
             }
         }
-        drop(send);
         info!("Shutting down {} worker", self.name);
-        let _ = recv.recv().await;
-        info!("Shutdown {} worker successfully", self.name);
-        Ok(())
+        // A stream that ends right after an error, rather than on shutdown, has failed
+        let failure = match last_error {
+            Some(e) if !ctx.is_shutting_down() => Some(WorkerError::StreamError(e)),
+            _ => None,
+        };
+        // The stream may also have ended on its own, so make sure beats stop too
+        ctx.shutdown.shutdown();
+        // Wait for running jobs and spawned futures. Jobs cancelled by a shutdown
+        // timeout are still counted
+        let _ = ctx.terminate.cancel_on_shutdown(ctx.shutdown.clone()).await;
+        let unfinished = inflight.load(Ordering::Relaxed);
+        if unfinished > 0 {
+            warn!(
+                "Shutdown of {} worker timed out with {} jobs in flight",
+                self.name, unfinished
+            );
+        } else {
+            info!("Shutdown {} worker successfully", self.name);
+        }
+        // Nothing runs anymore, so any job still held was either cancelled or never started
+        if let Some(reenqueue) = self.reenqueue {
+            reenqueue().await;
+        }
+        listeners.emit(&self.name, WorkerEvent::Stop);
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicBool, Mutex},
+        time::Duration,
+    };
+
+    use futures::stream;
+    use graceful_shutdown::Shutdown;
+    use tokio::sync::watch;
+    use tower::service_fn;
+
+    use super::*;
+    use crate::{
+        builder::{WorkerBuilder, WorkerFactory, WorkerFactoryFn},
+        context::JobContext,
+        error::{JobError, JobStreamError},
+        executor::TokioExecutor,
+        worker::WorkerListener,
+    };
+
+    struct TestJob;
+
+    impl Job for TestJob {
+        const NAME: &'static str = "TestJob";
+    }
+
+    fn context() -> WorkerContext<TokioExecutor> {
+        WorkerContext {
+            shutdown: Shutdown::new(),
+            terminate: Shutdown::new(),
+            executor: TokioExecutor,
+            listeners: Default::default(),
+            pause: watch::channel(false).1,
+        }
+    }
+
+    fn one_job() -> impl Stream<Item = Result<Option<JobRequest<TestJob>>, JobStreamError>> + Unpin
+    {
+        stream::iter(vec![Ok(Some(JobRequest::new(TestJob)))]).chain(stream::pending())
+    }
+
+    #[tokio::test]
+    async fn test_handler_can_shutdown_its_worker() {
+        let worker = WorkerBuilder::new("test-worker")
+            .stream(one_job())
+            .build_fn(|_: TestJob, ctx: JobContext| async move {
+                let worker = ctx.data_opt::<WorkerContext<TokioExecutor>>().unwrap();
+                worker.shutdown();
+            });
+
+        tokio::time::timeout(Duration::from_secs(1), worker.start(context()))
+            .await
+            .expect("worker did not shut down")
+            .expect("worker failed");
+    }
+
+    #[tokio::test]
+    async fn test_worker_shutdown_waits_for_spawned_futures() {
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        let worker = WorkerBuilder::new("test-worker")
+            .stream(one_job())
+            .build_fn(move |_: TestJob, ctx: JobContext| {
+                let flag = flag.clone();
+                async move {
+                    let worker = ctx.data_opt::<WorkerContext<TokioExecutor>>().unwrap();
+                    worker.spawn(async move {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        flag.store(true, Ordering::SeqCst);
+                    });
+                    worker.shutdown();
+                }
+            });
+
+        worker.start(context()).await.expect("worker failed");
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    struct EventCollector(Arc<Mutex<Vec<String>>>);
+
+    impl WorkerListener for EventCollector {
+        fn on_event(&self, _worker_id: &str, event: &WorkerEvent) {
+            let event = format!("{event:?}");
+            let name = event.split([' ', '(']).next().unwrap().to_string();
+            self.0.lock().unwrap().push(name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_emits_lifecycle_events() {
+        let worker_events = Arc::new(Mutex::new(Vec::new()));
+        let monitor_events = Arc::new(Mutex::new(Vec::new()));
+        let jobs = stream::iter(vec![
+            Ok(None),
+            Ok(None),
+            Ok(Some(JobRequest::new(TestJob))),
+            Ok::<_, JobStreamError>(Some(JobRequest::new(TestJob))),
+        ]);
+        let worker = WorkerBuilder::new("test-worker")
+            .listener(EventCollector(worker_events.clone()))
+            .stream(jobs)
+            .build(service_fn(|_: JobRequest<TestJob>| async {
+                static CALLS: AtomicUsize = AtomicUsize::new(0);
+                match CALLS.fetch_add(1, Ordering::SeqCst) {
+                    0 => Ok(()),
+                    _ => Err(JobError::WorkerCrashed),
+                }
+            }));
+        let ctx = context();
+        ctx.listeners
+            .push(Arc::new(EventCollector(monitor_events.clone())));
+
+        worker.start(ctx).await.expect("worker failed");
+
+        let mut events = worker_events.lock().unwrap().clone();
+        assert_eq!(events[..4], ["Start", "Idle", "JobFetched", "JobFetched"]);
+        events[4..6].sort();
+        assert_eq!(events[4..], ["JobFailed", "JobSucceeded", "Stop"]);
+        assert_eq!(monitor_events.lock().unwrap().len(), 7);
+    }
+
+    struct FailureCollector(Arc<Mutex<Vec<String>>>);
+
+    impl WorkerListener for FailureCollector {
+        fn on_event(&self, _worker_id: &str, event: &WorkerEvent) {
+            if let WorkerEvent::JobFailed { error, .. } = event {
+                self.0.lock().unwrap().push(error.clone());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_enforces_job_timeout() {
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let mut job = JobRequest::new(TestJob);
+        job.context_mut()
+            .set_timeout(Some(Duration::from_millis(50)));
+        let worker = WorkerBuilder::new("test-worker")
+            .listener(FailureCollector(failures.clone()))
+            .stream(stream::iter(vec![Ok::<_, JobStreamError>(Some(job))]))
+            .build_fn(|_: TestJob, _: JobContext| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            });
+
+        tokio::time::timeout(Duration::from_secs(1), worker.start(context()))
+            .await
+            .expect("job timeout was not enforced")
+            .expect("worker failed");
+        assert_eq!(*failures.lock().unwrap(), ["TimedOut(50ms)".to_string()]);
     }
 }
@@ -0,0 +1,118 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pin_project_lite::pin_project;
+use tower::Service;
+
+use crate::{
+    error::JobError,
+    job::Job,
+    request::JobRequest,
+    utils::{Sleep, Timer},
+};
+
+/// Limits every attempt of a job to its timeout, see [`Job::TIMEOUT`] and
+/// [`JobContext::timeout`]. An attempt that times out is dropped and fails
+/// with [`JobError::TimedOut`].
+///
+/// Workers built with a [`WorkerBuilder`] wrap their service in it, so the timeout
+/// is enforced closest to the handler, inside every layer of the worker.
+///
+/// [`JobContext::timeout`]: crate::context::JobContext::timeout
+/// [`WorkerBuilder`]: crate::builder::WorkerBuilder
+#[derive(Clone)]
+pub struct JobTimeout<S> {
+    inner: S,
+    timer: Arc<dyn Timer + Send + Sync>,
+}
+
+impl<S> fmt::Debug for JobTimeout<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobTimeout")
+            .field("inner", &std::any::type_name::<S>())
+            .finish()
+    }
+}
+
+impl<S> JobTimeout<S> {
+    /// Wrap `inner`, measuring the timeouts with `timer`
+    pub fn new<T: Timer + Send + Sync + 'static>(inner: S, timer: T) -> Self {
+        Self::with_shared_timer(inner, Arc::new(timer))
+    }
+
+    pub(crate) fn with_shared_timer(inner: S, timer: Arc<dyn Timer + Send + Sync>) -> Self {
+        JobTimeout { inner, timer }
+    }
+}
+
+impl<S, J> Service<JobRequest<J>> for JobTimeout<S>
+where
+    S: Service<JobRequest<J>>,
+    S::Error: From<JobError>,
+    J: Job,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = JobTimeoutFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: JobRequest<J>) -> Self::Future {
+        let timeout = req.context().timeout().or(J::TIMEOUT);
+        let deadline = timeout.map(|timeout| (self.timer.sleep(timeout), timeout));
+        JobTimeoutFuture {
+            inner: self.inner.call(req),
+            deadline,
+        }
+    }
+}
+
+pin_project! {
+    /// The future returned by [`JobTimeout`]
+    pub struct JobTimeoutFuture<F> {
+        #[pin]
+        inner: F,
+        deadline: Option<(Pin<Box<dyn Sleep>>, Duration)>,
+    }
+}
+
+impl<F, T, E> Future for JobTimeoutFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<JobError>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(res) = this.inner.poll(cx) {
+            return Poll::Ready(res);
+        }
+        match this.deadline {
+            Some((sleep, timeout)) => match sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(JobError::TimedOut(*timeout).into())),
+                Poll::Pending => Poll::Pending,
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<F> fmt::Debug for JobTimeoutFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobTimeoutFuture")
+            .field(
+                "timeout",
+                &self.deadline.as_ref().map(|(_, timeout)| timeout),
+            )
+            .finish()
+    }
+}
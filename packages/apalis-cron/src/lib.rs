@@ -28,10 +28,18 @@
 //! use apalis_core::monitor::Monitor;
 //! use apalis_core::builder::WorkerBuilder;
 //! use apalis_core::builder::WorkerFactory;
+//! use apalis_core::utils::timer::TokioTimer;
 //! use apalis_cron::CronStream;
+//! use chrono::{DateTime, Utc};
 //! #[derive(Default, Clone)]
 //! struct Reminder;
 //!
+//! impl From<DateTime<Utc>> for Reminder {
+//!     fn from(_: DateTime<Utc>) -> Self {
+//!         Reminder
+//!     }
+//! }
+//!
 //! impl Job for Reminder {
 //!     const NAME: &'static str = "reminder::DailyReminder";
 //! }
@@ -58,7 +66,7 @@
 //!         .service(job_fn(send_reminder));
 //!
 //!     let worker = WorkerBuilder::new("morning-cereal")
-//!         .stream(CronStream::new(schedule).to_stream())
+//!         .stream(CronStream::new(schedule, TokioTimer).to_stream())
 //!         .build(service);
 //!     Monitor::new()
 //!         .register(worker)
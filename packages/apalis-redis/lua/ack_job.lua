@@ -1,5 +1,16 @@
 -- KEYS[1]: this consumer's inflight set
 -- KEYS[2]: the done jobs set
+-- KEYS[3]: the unique keys hash
+-- KEYS[4]: the blocked jobs hash
+-- KEYS[5]: the blocked jobs set
+-- KEYS[6]: the active job set
+-- KEYS[7]: the scheduled jobs set
+-- KEYS[8]: the job priority hash
+-- KEYS[9]: the signal list
+-- KEYS[10]: the job data hash
+-- KEYS[11]: the hash of the batch each job belongs to
+-- KEYS[12]: the set of jobs waiting on the job
+-- KEYS[13]: the hash of the batch the job belongs to, if any
 
 -- ARGV[1]: the job ID
 -- ARGV[2]: the current time
@@ -15,6 +26,44 @@ if removed == 1 then
 
   -- Push the job on to the done jobs set
   redis.call("zadd", KEYS[2], ARGV[2], ARGV[1])
+
+  -- Release the unique key held by the job, unless it expired and was taken since
+  local unique_key = redis.call("hget", KEYS[3], ARGV[1])
+  if unique_key then
+    if redis.call("get", unique_key) == ARGV[1] then
+      redis.call("del", unique_key)
+    end
+    redis.call("hdel", KEYS[3], ARGV[1])
+  end
+
+  -- Count the job towards its batch
+  if redis.call("hexists", KEYS[11], ARGV[1]) == 1 then
+    redis.call("hincrby", KEYS[13], "done", 1)
+    finish_batch(KEYS[13], ARGV[2], KEYS[5], KEYS[6], KEYS[9], KEYS[8], KEYS[10])
+  end
+
+  -- Unblock the jobs that were only waiting on this one
+  local enqueued = false
+  for _, child in ipairs(redis.call("smembers", KEYS[12])) do
+    if redis.call("hexists", KEYS[4], child) == 1 and redis.call("hincrby", KEYS[4], child, -1) <= 0 then
+      redis.call("hdel", KEYS[4], child)
+      local run_at = tonumber(redis.call("zscore", KEYS[5], child))
+      redis.call("zrem", KEYS[5], child)
+      if run_at > tonumber(ARGV[2]) then
+        redis.call("zadd", KEYS[7], run_at, child)
+      else
+        redis.call("zadd", KEYS[6], active_score(redis.call("hget", KEYS[8], child), run_at), child)
+        enqueued = true
+      end
+    end
+  end
+  redis.call("del", KEYS[12])
+
+  if enqueued then
+    redis.call("del", KEYS[9])
+    redis.call("lpush", KEYS[9], 1)
+  end
+
   return true
 end
 
@@ -0,0 +1,21 @@
+-- KEYS[1]: the batch hash
+-- KEYS[2]: the blocked jobs set
+-- KEYS[3]: the active job set
+-- KEYS[4]: the signal list
+-- KEYS[5]: the job priority hash
+-- KEYS[6]: the job data hash
+
+-- ARGV[1]: the current time
+
+-- Returns: 1 if the batch was closed, 0 if it does not exist
+
+if redis.call("exists", KEYS[1]) == 0 then
+  return 0
+end
+
+redis.call("hset", KEYS[1], "closed", 1)
+
+-- Every job may have finished already
+finish_batch(KEYS[1], ARGV[1], KEYS[2], KEYS[3], KEYS[4], KEYS[5], KEYS[6])
+
+return 1
@@ -1,6 +1,7 @@
 -- KEYS[1]: the scheduled jobs set
--- KEYS[2]: the active job list
+-- KEYS[2]: the active job set
 -- KEYS[3]: the signal list
+-- KEYS[4]: the job priority hash
 
 -- ARGV[1]: the current timestamp
 -- ARGV[2]: the max number of jobs to schedule
@@ -8,12 +9,14 @@
 -- Returns: nil
 
 -- Get the jobs out of the scheduled set
-local job_ids = redis.call("zrangebyscore", KEYS[1], 0, ARGV[1], "LIMIT", 0, ARGV[2])
-local count = table.getn(job_ids)
+local jobs = redis.call("zrangebyscore", KEYS[1], 0, ARGV[1], "WITHSCORES", "LIMIT", 0, ARGV[2])
+local count = table.getn(jobs) / 2
 
 if count > 0 then
-  -- Push them on to the active list
-  redis.call("rpush", KEYS[2], unpack(job_ids))
+  -- Push them on to the active set, ties in priority are broken by their scheduled time
+  for i = 1, table.getn(jobs), 2 do
+    redis.call("zadd", KEYS[2], active_score(redis.call("hget", KEYS[4], jobs[i]), jobs[i + 1]), jobs[i])
+  end
 
   -- Remove the jobs from the scheduled set
   redis.call("zremrangebyrank", KEYS[1], 0, count - 1)
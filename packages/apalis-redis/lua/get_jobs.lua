@@ -1,11 +1,14 @@
 -- KEYS[1]: the active consumers set
--- KEYS[2]: the active job list
+-- KEYS[2]: the active job set
 -- KEYS[3]: this consumer's inflight set
 -- KEYS[4]: the job data hash
 -- KEYS[5]: the signal list
+-- KEYS[6]: the job priority hash
+-- KEYS[7]: the active job list used by earlier versions
 
 -- ARGV[1]: the max number of jobs to get
 -- ARGV[2]: this consumer's inflight set
+-- ARGV[3]: the current time
 
 -- Returns: the jobs
 
@@ -15,8 +18,19 @@ if not registered then
   error("consumer not registered")
 end
 
--- Get the jobs out of the active job list
-local job_ids = redis.call("lrange", KEYS[2], 0, ARGV[1] - 1)
+-- Move the jobs still waiting in the list of earlier versions into the active job set,
+-- keeping them in the order they were pushed
+local legacy = redis.call("lrange", KEYS[7], 0, -1)
+for i, job_id in ipairs(legacy) do
+  local time = tonumber(ARGV[3]) - #legacy + i
+  redis.call("zadd", KEYS[2], active_score(redis.call("hget", KEYS[6], job_id), time), job_id)
+end
+if #legacy > 0 then
+  redis.call("del", KEYS[7])
+end
+
+-- Get the jobs with the highest priority out of the active job set
+local job_ids = redis.call("zrange", KEYS[2], 0, ARGV[1] - 1)
 local count = table.getn(job_ids)
 local results = {}
 
@@ -24,8 +38,8 @@ if count > 0 then
   -- Add the jobs to the active set
   redis.call("sadd", KEYS[3], unpack(job_ids))
 
-  -- Remove the jobs from the active job list
-  redis.call("ltrim", KEYS[2], count, -1)
+  -- Remove the jobs from the active job set
+  redis.call("zremrangebyrank", KEYS[2], 0, count - 1)
 
   -- Return the job data
   results = redis.call("hmget", KEYS[4], unpack(job_ids))
@@ -1,23 +1,115 @@
--- KEYS[1]: this consumer's inflight set
--- KEYS[2]: the dead jobs set
+-- KEYS[1]: this consumer's inflight set, unused when the job has not started yet
+-- KEYS[2]: the dead jobs set, or the cancelled jobs set when cancelling
 -- KEYS[3]: the job data hash
+-- KEYS[4]: the unique keys hash
+-- KEYS[5]: the blocked jobs hash
+-- KEYS[6]: the blocked jobs set
+-- KEYS[7]: the hash of the batch each job belongs to
+-- KEYS[8]: the active job set
+-- KEYS[9]: the job priority hash
+-- KEYS[10]: the signal list
+-- KEYS[11]: the scheduled jobs set
+-- KEYS[12]: the set of running jobs flagged for cancellation
+-- KEYS[13..12+N]: the sets of jobs waiting on each job in ARGV[6..5+N]
+-- KEYS[13+N..]: the hashes of the batches in ARGV[6+N..]
 
 -- ARGV[1]: the job ID
 -- ARGV[2]: the current time
 -- ARGV[3]: the serialized job data
+-- ARGV[4]: "1" if the job has not started yet and is taken out of the queue instead
+-- ARGV[5]: the number N of jobs in ARGV[6..5+N]
+-- ARGV[6..5+N]: the job ID, then the IDs of every job waiting on it, directly or not
+-- ARGV[6+N..]: the IDs of the batches those jobs belong to
 
--- Returns: nil
+-- Returns: 1 if the job was killed or cancelled, 0 otherwise, -1 if the jobs waiting on it
+-- or their batches changed since they were listed, in which case nothing is written
 
--- Remove the job from this consumer's inflight set
-local removed = redis.call("srem", KEYS[1], ARGV[1])
+-- Map every listed job and batch to its key
+local count = tonumber(ARGV[5])
+local dependents = {}
+for i = 1, count do
+  dependents[ARGV[5 + i]] = KEYS[12 + i]
+end
+local batches = {}
+for i = 6 + count, #ARGV do
+  batches[ARGV[i]] = KEYS[i + 7]
+end
+
+-- Make sure the listed jobs and batches cover every job and batch that is touched
+for _, dependents_key in pairs(dependents) do
+  for _, child in ipairs(redis.call("smembers", dependents_key)) do
+    if not dependents[child] then
+      return -1
+    end
+  end
+end
+for job_id in pairs(dependents) do
+  local batch_id = redis.call("hget", KEYS[7], job_id)
+  if batch_id and not batches[batch_id] then
+    return -1
+  end
+end
 
-if removed == 1 then
-  -- Push the job on to the dead jobs set
+local removed = 0
+if ARGV[4] == "1" then
+  -- Take the job out of the queue, wherever it waits
+  removed = redis.call("zrem", KEYS[8], ARGV[1]) + redis.call("zrem", KEYS[11], ARGV[1])
+    + redis.call("zrem", KEYS[6], ARGV[1])
+  redis.call("hdel", KEYS[5], ARGV[1])
+else
+  -- Remove the job from this consumer's inflight set
+  removed = redis.call("srem", KEYS[1], ARGV[1])
+end
+
+if removed > 0 then
+  redis.call("srem", KEYS[12], ARGV[1])
+
+  -- Push the job on to the dead or cancelled jobs set
   redis.call("zadd", KEYS[2], ARGV[2], ARGV[1])
 
   -- Reset the job data
   redis.call("hset", KEYS[3], ARGV[1], ARGV[3])
 
+  -- Kill or cancel every job waiting on this one, directly or not, since it can never run
+  local killed = {ARGV[1]}
+  local i = 1
+  while i <= #killed do
+    local dependents_key = dependents[killed[i]]
+    for _, child in ipairs(redis.call("smembers", dependents_key)) do
+      if redis.call("hdel", KEYS[5], child) == 1 then
+        redis.call("zrem", KEYS[6], child)
+        redis.call("zadd", KEYS[2], ARGV[2], child)
+        table.insert(killed, child)
+      end
+    end
+    redis.call("del", dependents_key)
+    i = i + 1
+  end
+
+  -- Count the killed or cancelled jobs as failed in their batches
+  local finished = {}
+  for _, job_id in ipairs(killed) do
+    local batch_id = redis.call("hget", KEYS[7], job_id)
+    if batch_id then
+      redis.call("hincrby", batches[batch_id], "failed", 1)
+      finished[batch_id] = true
+    end
+  end
+  for batch_id in pairs(finished) do
+    finish_batch(batches[batch_id], ARGV[2], KEYS[6], KEYS[8], KEYS[10], KEYS[9], KEYS[3])
+  end
+
+  -- Release the unique keys held by those jobs, unless they expired and were taken since
+  for _, job_id in ipairs(killed) do
+    local unique_key = redis.call("hget", KEYS[4], job_id)
+    if unique_key then
+      if redis.call("get", unique_key) == job_id then
+        redis.call("del", unique_key)
+      end
+      redis.call("hdel", KEYS[4], job_id)
+    end
+  end
+
   return 1
 end
 
@@ -0,0 +1,31 @@
+-- KEYS[1]: the dead jobs set
+-- KEYS[2]: the job data hash
+-- KEYS[3]: the job priority hash
+-- KEYS[4]: the hash of the batch each job belongs to
+-- KEYS[5]: the job results hash
+-- KEYS[6]: the result expiry set
+-- KEYS[7..]: the logs lists of the jobs in ARGV[2..]
+
+-- ARGV[1]: the latest kill time purged
+-- ARGV[2..]: the IDs of the dead jobs killed at or before that time
+
+-- Returns: the number of jobs deleted
+
+local purged = 0
+for i = 2, #ARGV do
+  local job_id = ARGV[i]
+  local killed_at = redis.call("zscore", KEYS[1], job_id)
+  -- Skip the jobs that were requeued since they were listed
+  if killed_at and tonumber(killed_at) <= tonumber(ARGV[1]) then
+    redis.call("zrem", KEYS[1], job_id)
+    redis.call("hdel", KEYS[2], job_id)
+    redis.call("hdel", KEYS[3], job_id)
+    redis.call("hdel", KEYS[4], job_id)
+    redis.call("hdel", KEYS[5], job_id)
+    redis.call("zrem", KEYS[6], job_id)
+    redis.call("del", KEYS[i + 5])
+    purged = purged + 1
+  end
+end
+
+return purged
@@ -1,22 +1,139 @@
 -- KEYS[1]: the job data hash
--- KEYS[2]: the active job list
+-- KEYS[2]: the active job set
 -- KEYS[3]: the signal list
+-- KEYS[4]: the job priority hash
+-- KEYS[5]: the scheduled jobs set
+-- KEYS[6]: the unique key of the job, if any
+-- KEYS[7]: the unique keys hash
+-- KEYS[8]: the done jobs set
+-- KEYS[9]: the dead jobs set
+-- KEYS[10]: the blocked jobs set
+-- KEYS[11]: the blocked jobs hash, counting the parents each job still waits on
+-- KEYS[12]: the hash of the batch each job belongs to
+-- KEYS[13]: the hash of the batch the job is pushed into, if any
+-- KEYS[14]: the cancelled jobs set
+-- KEYS[15]: the set of jobs waiting on the job expected to hold the unique key
+-- KEYS[16..]: the sets of jobs waiting on each parent job, in the order of ARGV[10..]
 
 -- ARGV[1]: the job ID
 -- ARGV[2]: the serialized job data
+-- ARGV[3]: the job priority
+-- ARGV[4]: the time to run the job
+-- ARGV[5]: the current time
+-- ARGV[6]: the unique policy, empty if the job has no unique key
+-- ARGV[7]: how long the unique key is held in milliseconds, 0 until the job is done
+-- ARGV[8]: the batch ID, empty if the job is not pushed into a batch
+-- ARGV[9]: the ID of the job expected to hold the unique key, when replacing it
+-- ARGV[10..]: the IDs of the parent jobs
 
--- Returns: 1 if the job was newly enqueued, 0 if it already exists
+-- Returns: {1, job ID} if the job was newly enqueued, {0, job ID} if the job holding the
+-- unique key is kept instead, {-1, job ID} if the unique key is held,
+-- {-2, parent ID} if a parent job does not exist, {-3, batch ID} if the batch is closed or does not exist,
+-- {-4, job ID} if a job with that ID already exists,
+-- {-5, job ID} if the unique key is held by another job than ARGV[9], in which case nothing is written
+
+if redis.call("hexists", KEYS[1], ARGV[1]) == 1 then
+  return {-4, ARGV[1]}
+end
+
+if ARGV[8] ~= "" and redis.call("hget", KEYS[13], "closed") ~= "0" then
+  return {-3, ARGV[8]}
+end
+
+-- Find the parents the job has to wait on, keeping the sets of jobs waiting on them
+local waiting_on = {}
+local finished_in = nil
+for i = 10, #ARGV do
+  local parent = ARGV[i]
+  if redis.call("hexists", KEYS[1], parent) == 0 then
+    return {-2, parent}
+  end
+  if redis.call("zscore", KEYS[9], parent) then
+    finished_in = KEYS[9]
+  elseif redis.call("zscore", KEYS[14], parent) then
+    finished_in = finished_in or KEYS[14]
+  elseif not redis.call("zscore", KEYS[8], parent) then
+    table.insert(waiting_on, KEYS[i + 6])
+  end
+end
+
+if finished_in then
+  -- A parent was killed or cancelled, so the job can never run and shares its fate
+  redis.call("hset", KEYS[1], ARGV[1], ARGV[2])
+  redis.call("hset", KEYS[4], ARGV[1], ARGV[3])
+  redis.call("zadd", finished_in, ARGV[5], ARGV[1])
+  if ARGV[8] ~= "" then
+    redis.call("hset", KEYS[12], ARGV[1], ARGV[8])
+    redis.call("hincrby", KEYS[13], "total", 1)
+    redis.call("hincrby", KEYS[13], "failed", 1)
+  end
+  return {1, ARGV[1]}
+end
+
+if ARGV[6] ~= "" then
+  local holder = redis.call("get", KEYS[6])
+  if holder then
+    if ARGV[6] == "ignore" then
+      return {0, holder}
+    end
+
+    if ARGV[6] == "replace" and holder ~= ARGV[9] then
+      return {-5, holder}
+    end
+
+    -- Only a job that no worker has taken yet can be replaced.
+    -- Jobs of a batch are counted by it, and jobs waiting on it would never run,
+    -- so those are never replaced
+    local waiting = 0
+    if ARGV[6] == "replace" and redis.call("hexists", KEYS[12], holder) == 0
+      and redis.call("scard", KEYS[15]) == 0 then
+      waiting = redis.call("zrem", KEYS[2], holder) + redis.call("zrem", KEYS[5], holder)
+        + redis.call("zrem", KEYS[10], holder)
+    end
+    if waiting == 0 then
+      return {-1, holder}
+    end
+
+    redis.call("hdel", KEYS[1], holder)
+    redis.call("hdel", KEYS[4], holder)
+    redis.call("hdel", KEYS[7], holder)
+    redis.call("hdel", KEYS[11], holder)
+  end
+
+  if tonumber(ARGV[7]) > 0 then
+    redis.call("set", KEYS[6], ARGV[1], "PX", ARGV[7])
+  else
+    redis.call("set", KEYS[6], ARGV[1])
+  end
+  redis.call("hset", KEYS[7], ARGV[1], KEYS[6])
+end
 
 -- Set job data in hash
-local set = redis.call("hsetnx", KEYS[1], ARGV[1], ARGV[2])
+redis.call("hset", KEYS[1], ARGV[1], ARGV[2])
+redis.call("hset", KEYS[4], ARGV[1], ARGV[3])
+
+if ARGV[8] ~= "" then
+  redis.call("hset", KEYS[12], ARGV[1], ARGV[8])
+  redis.call("hincrby", KEYS[13], "total", 1)
+end
 
-if set == 1 then
-  -- If it was set, push the job on to the active list
-  redis.call("rpush", KEYS[2], ARGV[1])
+if #waiting_on > 0 then
+  -- Park the job until its parents are done, keeping its run time as the score
+  redis.call("zadd", KEYS[10], ARGV[4], ARGV[1])
+  redis.call("hset", KEYS[11], ARGV[1], #waiting_on)
+  for _, dependents in ipairs(waiting_on) do
+    redis.call("sadd", dependents, ARGV[1])
+  end
+elseif tonumber(ARGV[4]) > tonumber(ARGV[5]) then
+  -- Push the job on to the scheduled set
+  redis.call("zadd", KEYS[5], ARGV[4], ARGV[1])
+else
+  -- Push the job on to the active set
+  redis.call("zadd", KEYS[2], active_score(ARGV[3], ARGV[4]), ARGV[1])
 
   -- Signal that there are jobs in the queue
   redis.call("del", KEYS[3])
   redis.call("lpush", KEYS[3], 1)
 end
 
-return set
+return {1, ARGV[1]}
@@ -1,21 +1,22 @@
 -- KEYS[1]: this consumer's inflight set
--- KEYS[2]: the active jobs list
+-- KEYS[2]: the active jobs set
 -- KEYS[3]: the signal list
+-- KEYS[4]: the job priority hash
 
--- ARGV[]: the list of job IDs
+-- ARGV[1]: the current time
 
 -- Returns: nil
 
-for _,job_id in ipairs(ARGV) do
-  -- Remove the jobs from this consumer's inflight set
-  local removed = redis.call("srem", KEYS[1], job_id)
+local job_ids = redis.call("smembers", KEYS[1])
 
-  if removed == 1 then
-    -- Push the job back into the active jobs list
-    redis.call("rpush", KEYS[2], job_id)
-  end
+for _,job_id in ipairs(job_ids) do
+  -- Push the job back into the active jobs set
+  redis.call("zadd", KEYS[2], active_score(redis.call("hget", KEYS[4], job_id), ARGV[1]), job_id)
 end
 
+-- Remove the jobs from this consumer's inflight set
+redis.call("del", KEYS[1])
+
 -- Signal that there are jobs in the queue
 redis.call("del", KEYS[3])
 redis.call("lpush", KEYS[3], 1)
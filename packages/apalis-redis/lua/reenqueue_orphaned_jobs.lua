@@ -1,9 +1,11 @@
 -- KEYS[1]: the consumer set
--- KEYS[2]: the active job list
+-- KEYS[2]: the active job set
 -- KEYS[3]: the signal list
+-- KEYS[4]: the job priority hash
 
 -- ARGV[1]: the timestamp before which a consumer is considered expired
 -- ARGV[2]: the max number of jobs to process in a given run
+-- ARGV[3]: the current time
 
 -- Returns: 0 if all orphaned jobs have been rescheduled, 1 if there are more to process
 
@@ -16,9 +18,9 @@ for _,consumer in ipairs(consumers) do
   local jobs = redis.call("spop", consumer, limit)
   local count = table.getn(jobs)
 
-  -- Push any orphaned jobs on to the message list
-  if count > 0 then
-    redis.call("rpush", KEYS[2], unpack(jobs))
+  -- Push any orphaned jobs on to the active set
+  for _,job_id in ipairs(jobs) do
+    redis.call("zadd", KEYS[2], active_score(redis.call("hget", KEYS[4], job_id), ARGV[3]), job_id)
   end
 
   -- Delete the consumer if all of its jobs have been rescheduled
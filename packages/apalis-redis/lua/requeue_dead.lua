@@ -0,0 +1,29 @@
+-- KEYS[1]: the dead jobs set
+-- KEYS[2]: the job data hash
+-- KEYS[3]: the active job set
+-- KEYS[4]: the job priority hash
+-- KEYS[5]: the signal list
+-- KEYS[6]: the hash of the batch each job belongs to
+
+-- ARGV[1]: the job ID
+-- ARGV[2]: the serialized job data, reset to run again
+-- ARGV[3]: the current time
+
+-- Returns: 1 if the job was requeued, 0 if it is not killed
+
+if redis.call("zrem", KEYS[1], ARGV[1]) == 0 then
+  return 0
+end
+
+redis.call("hset", KEYS[2], ARGV[1], ARGV[2])
+
+-- The batch already counted the job as failed
+redis.call("hdel", KEYS[6], ARGV[1])
+
+redis.call("zadd", KEYS[3], active_score(redis.call("hget", KEYS[4], ARGV[1]), ARGV[3]), ARGV[1])
+
+-- Signal that there are jobs in the queue
+redis.call("del", KEYS[5])
+redis.call("lpush", KEYS[5], 1)
+
+return 1
@@ -0,0 +1,25 @@
+-- KEYS[1]: the job data hash
+-- KEYS[2]: the scheduled set
+-- KEYS[3]: the job priority hash
+-- KEYS[4]: this consumer's inflight set
+
+-- ARGV[1]: the job ID
+-- ARGV[2]: the serialized job data
+-- ARGV[3]: the time to schedule the job
+-- ARGV[4]: the job priority
+
+-- Returns: 1 if the job was taken out of this consumer's inflight set and scheduled, 0 otherwise
+
+-- Remove the job from this consumer's inflight set
+local removed = redis.call("srem", KEYS[4], ARGV[1])
+
+if removed == 1 then
+  -- Reset the job data
+  redis.call("hset", KEYS[1], ARGV[1], ARGV[2])
+  redis.call("hset", KEYS[3], ARGV[1], ARGV[4])
+
+  -- Push the job on to the scheduled set
+  redis.call("zadd", KEYS[2], ARGV[3], ARGV[1])
+end
+
+return removed
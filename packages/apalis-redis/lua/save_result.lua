@@ -0,0 +1,25 @@
+-- KEYS[1]: the job results hash
+-- KEYS[2]: the result expiry set
+-- KEYS[3]: the job data hash
+
+-- ARGV[1]: the job ID
+-- ARGV[2]: the serialized result
+-- ARGV[3]: the current time
+-- ARGV[4]: the time the result expires
+
+-- Returns: 1 if the result was saved, 0 if the job does not exist
+
+-- Results past their retention are dropped as new ones are saved
+local expired = redis.call("zrangebyscore", KEYS[2], "-inf", ARGV[3])
+for _, job_id in ipairs(expired) do
+  redis.call("hdel", KEYS[1], job_id)
+end
+redis.call("zremrangebyscore", KEYS[2], "-inf", ARGV[3])
+
+if redis.call("hexists", KEYS[3], ARGV[1]) == 0 then
+  return 0
+end
+
+redis.call("hset", KEYS[1], ARGV[1], ARGV[2])
+redis.call("zadd", KEYS[2], ARGV[4], ARGV[1])
+return 1
@@ -0,0 +1,35 @@
+-- Helpers shared by the scripts, loaded in front of each of them
+
+-- The score of a job in the active job set. Higher priorities get lower scores, ties are
+-- broken by time. Priorities are clamped to +/- 2^20 so that scores stay exact in a double
+local function active_score(priority, time)
+  local clamped = math.max(-1048576, math.min(1048576, tonumber(priority) or 0))
+  return -clamped * 4294967296 + tonumber(time)
+end
+
+
+-- Releases the callback of a closed batch once every job in it has finished,
+-- and deletes the callback that does not apply
+local function finish_batch(batch_key, now, blocked_set, active_set, signal_list, priority_hash, data_hash)
+  local batch = redis.call("hmget", batch_key, "total", "done", "failed", "closed", "finished", "on_success", "on_failure")
+  if batch[4] ~= "1" or batch[5] or tonumber(batch[2]) + tonumber(batch[3]) < tonumber(batch[1]) then
+    return
+  end
+  redis.call("hset", batch_key, "finished", now)
+
+  local callback, discarded = batch[6], batch[7]
+  if tonumber(batch[3]) > 0 then
+    callback, discarded = batch[7], batch[6]
+  end
+  if callback then
+    redis.call("zrem", blocked_set, callback)
+    redis.call("zadd", active_set, active_score(redis.call("hget", priority_hash, callback), now), callback)
+    redis.call("del", signal_list)
+    redis.call("lpush", signal_list, 1)
+  end
+  if discarded then
+    redis.call("zrem", blocked_set, discarded)
+    redis.call("hdel", data_hash, discarded)
+    redis.call("hdel", priority_hash, discarded)
+  end
+end
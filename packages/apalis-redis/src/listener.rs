@@ -1,27 +1,149 @@
-use apalis_core::worker::prelude::{WorkerEvent, WorkerListener};
+use apalis_core::{
+    executor::Executor,
+    worker::prelude::{WorkerEvent, WorkerListener},
+};
+use futures::{channel::mpsc, StreamExt};
 use redis::{aio::MultiplexedConnection, Cmd};
+use serde::{Deserialize, Serialize};
+
+/// The channel worker events are published to
+pub const CHANNEL: &str = "apalis::workers";
+
+/// The JSON payload published to [CHANNEL] for every [WorkerEvent]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerEventMessage {
+    /// The worker that emitted the event
+    pub worker_id: String,
+    /// The name of the event, eg. `JobFailed`
+    pub event: String,
+    /// The job the event is about, if any
+    pub job_id: Option<String>,
+}
+
+impl WorkerEventMessage {
+    /// Build the message for `event` emitted by `worker_id`
+    pub fn new(worker_id: &str, event: &WorkerEvent) -> Self {
+        let job_id = match event {
+            WorkerEvent::JobFetched { job_id }
+            | WorkerEvent::JobSucceeded { job_id }
+            | WorkerEvent::JobFailed { job_id, .. } => Some(job_id.clone()),
+            _ => None,
+        };
+        // The debug output of an event always starts with its name
+        let event = format!("{event:?}")
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect();
+        WorkerEventMessage {
+            worker_id: worker_id.to_string(),
+            event,
+            job_id,
+        }
+    }
+}
 
 /// A Listener that broadcasts event to Redis via pubsub
+///
+/// Every [WorkerEvent] is published to [CHANNEL] as a JSON [WorkerEventMessage].
+/// Events are published one at a time, in the order they were emitted,
+/// by a single task spawned on the executor of the listener.
+#[derive(Debug)]
 pub struct RedisPubSubListener {
-    conn: MultiplexedConnection,
+    sender: mpsc::UnboundedSender<String>,
 }
 
 impl RedisPubSubListener {
-    /// New listener from connection
-    pub fn new(conn: MultiplexedConnection) -> Self {
-        Self { conn }
+    /// New listener from connection, publishing from a task spawned on `executor`
+    ///
+    /// The task ends once the listener is dropped.
+    pub fn new<E: Executor>(mut conn: MultiplexedConnection, executor: &E) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded::<String>();
+        executor.spawn(async move {
+            while let Some(message) = receiver.next().await {
+                let res: Result<(), _> =
+                    Cmd::publish(CHANNEL, message).query_async(&mut conn).await;
+                if let Err(e) = res {
+                    log::warn!("Failed to publish worker event: {e}");
+                }
+            }
+        });
+        Self { sender }
     }
 }
 
 impl WorkerListener for RedisPubSubListener {
     fn on_event(&self, worker_id: &str, event: &WorkerEvent) {
-        let mut conn = self.conn.clone();
-        let message = format!("{worker_id}: {event:?}");
-        let fut = async move {
-            let _res: Result<(), _> = Cmd::publish("apalis::workers", message)
-                .query_async(&mut conn)
-                .await;
-        };
-        tokio::spawn(fut);
+        let message = WorkerEventMessage::new(worker_id, event);
+        match serde_json::to_string(&message) {
+            Ok(message) => {
+                // The publisher only stops once the listener is dropped
+                let _ = self.sender.unbounded_send(message);
+            }
+            Err(e) => log::warn!("Failed to serialize worker event: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apalis_core::executor::TokioExecutor;
+
+    #[tokio::test]
+    async fn test_publishes_events_as_json_in_order() {
+        let redis_url = std::env::var("REDIS_URL").expect("No REDIS_URL is specified");
+        let client = redis::Client::open(redis_url).expect("invalid REDIS_URL");
+        let mut pubsub = client
+            .get_async_connection()
+            .await
+            .expect("failed to connect DB server")
+            .into_pubsub();
+        pubsub
+            .subscribe(CHANNEL)
+            .await
+            .expect("failed to subscribe");
+
+        let conn = client
+            .get_multiplexed_async_connection()
+            .await
+            .expect("failed to connect DB server");
+        let listener = RedisPubSubListener::new(conn, &TokioExecutor);
+        let events = [
+            WorkerEvent::Start,
+            WorkerEvent::JobFetched {
+                job_id: "1".to_string(),
+            },
+            WorkerEvent::JobFailed {
+                job_id: "1".to_string(),
+                error: "boom".to_string(),
+            },
+            WorkerEvent::Stop,
+        ];
+        for event in &events {
+            listener.on_event("worker-1", event);
+        }
+
+        let mut messages = pubsub.on_message();
+        let mut received = Vec::new();
+        for _ in 0..events.len() {
+            let message = messages.next().await.expect("subscription closed");
+            let payload: String = message.get_payload().expect("invalid payload");
+            let message: WorkerEventMessage =
+                serde_json::from_str(&payload).expect("payload is not a WorkerEventMessage");
+            received.push(message);
+        }
+
+        let expected = [
+            ("Start", None),
+            ("JobFetched", Some("1")),
+            ("JobFailed", Some("1")),
+            ("Stop", None),
+        ]
+        .map(|(event, job_id)| WorkerEventMessage {
+            worker_id: "worker-1".to_string(),
+            event: event.to_string(),
+            job_id: job_id.map(ToString::to_string),
+        });
+        assert_eq!(received, expected);
     }
 }
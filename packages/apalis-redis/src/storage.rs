@@ -1,57 +1,115 @@
 use std::{marker::PhantomData, time::Duration};
 
 use apalis_core::{
+    context::{JobContext, JobLogLine, JobProgress},
     error::{JobError, JobStreamError},
-    job::{Job, JobStreamExt, JobStreamResult, JobStreamWorker},
+    job::{Job, JobId, JobStreamExt, JobStreamResult, JobStreamWorker},
     request::{JobRequest, JobState},
-    storage::{Storage, StorageError, StorageResult, StorageWorkerPulse},
+    storage::{
+        BatchCallbacks, BatchId, BatchProgress, DeadLetter, DeadLetterFilter, PushOptions, Storage,
+        StorageError, StorageResult, StorageWorkerPulse, UniquePolicy,
+    },
 };
 use async_stream::try_stream;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use futures::Stream;
 use log::*;
 use redis::{aio::MultiplexedConnection, Client, IntoConnectionInfo, RedisError, Script, Value};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::time::Instant;
 
-const ACTIVE_JOBS_LIST: &str = "{queue}:active";
+const ACTIVE_JOBS_SET: &str = "{queue}:active_set";
+const BATCH_HASH: &str = "{queue}:batch:";
+const BLOCKED_JOBS_HASH: &str = "{queue}:blocked_parents";
+const BLOCKED_JOBS_SET: &str = "{queue}:blocked";
+const CANCEL_REQUESTED_SET: &str = "{queue}:cancel_requested";
+const CANCELLED_JOBS_SET: &str = "{queue}:cancelled";
 const CONSUMERS_SET: &str = "{queue}:consumers";
 const DEAD_JOBS_SET: &str = "{queue}:dead";
+const DEPENDENTS_SET: &str = "{queue}:dependents:";
 const DONE_JOBS_SET: &str = "{queue}:done";
 const FAILED_JOBS_SET: &str = "{queue}:failed";
 const INFLIGHT_JOB_SET: &str = "{queue}:inflight";
+const JOB_BATCHES_HASH: &str = "{queue}:job_batches";
 const JOB_DATA_HASH: &str = "{queue}:data";
+const JOB_LOGS_LIST: &str = "{queue}:logs:";
+const JOB_PRIORITY_HASH: &str = "{queue}:priority";
+const JOB_RESULTS_HASH: &str = "{queue}:results";
+/// The list active jobs were kept in before priorities, drained into [ACTIVE_JOBS_SET]
+const LEGACY_ACTIVE_JOBS_LIST: &str = "{queue}:active";
+const RESULT_EXPIRY_SET: &str = "{queue}:result_expiry";
+const UNIQUE_KEY: &str = "{queue}:unique:{key}";
+const UNIQUE_KEYS_HASH: &str = "{queue}:unique_keys";
 const SCHEDULED_JOBS_SET: &str = "{queue}:scheduled";
 const SIGNAL_LIST: &str = "{queue}:signal";
 
 #[derive(Clone)]
 struct RedisQueueInfo {
-    active_jobs_list: String,
+    active_jobs_set: String,
+    batch_hash: String,
+    blocked_jobs_hash: String,
+    blocked_jobs_set: String,
+    cancel_requested_set: String,
+    cancelled_jobs_set: String,
     consumers_set: String,
     dead_jobs_set: String,
+    dependents_set: String,
     done_jobs_set: String,
     failed_jobs_set: String,
     inflight_jobs_set: String,
+    job_batches_hash: String,
     job_data_hash: String,
+    job_logs_list: String,
+    job_priority_hash: String,
+    job_results_hash: String,
+    legacy_active_jobs_list: String,
+    result_expiry_set: String,
     scheduled_jobs_set: String,
     signal_list: String,
+    unique_key: String,
+    unique_keys_hash: String,
+}
+
+/// The shape a [JobRequest] is stored in, borrowing the job
+#[derive(Serialize)]
+struct StoredJob<'a, T> {
+    job: &'a T,
+    context: JobContext,
+}
+
+/// Loads a script that uses the helpers of `lua/shared.lua`
+macro_rules! shared_script {
+    ($path:literal) => {
+        redis::Script::new(concat!(
+            include_str!("../lua/shared.lua"),
+            include_str!($path)
+        ))
+    };
 }
 
 #[derive(Clone)]
 struct RedisScript {
     ack_job: Script,
+    close_batch: Script,
     enqueue_scheduled: Script,
     get_jobs: Script,
     kill_job: Script,
+    purge_dead: Script,
     push_job: Script,
     reenqueue_active: Script,
     reenqueue_orphaned: Script,
     register_consumer: Script,
+    requeue_dead: Script,
     retry_job: Script,
-    schedule_job: Script,
+    save_result: Script,
+    reschedule_job: Script,
 }
 
 /// Represents a [Storage] that uses Redis for storage.
+///
+/// Pending jobs are kept in a sorted set, ordered by priority and then by the time
+/// they became ready to run. Priorities beyond ±2^20 are ordered as ±2^20. Jobs waiting
+/// on parent jobs are kept in a separate set until their last parent is acknowledged.
 pub struct RedisStorage<T> {
     conn: MultiplexedConnection,
     job_type: PhantomData<T>,
@@ -77,33 +135,45 @@ impl<T: Job> RedisStorage<T> {
             conn,
             job_type: PhantomData,
             queue: RedisQueueInfo {
-                active_jobs_list: ACTIVE_JOBS_LIST.replace("{queue}", name),
+                active_jobs_set: ACTIVE_JOBS_SET.replace("{queue}", name),
+                batch_hash: BATCH_HASH.replace("{queue}", name),
+                blocked_jobs_hash: BLOCKED_JOBS_HASH.replace("{queue}", name),
+                blocked_jobs_set: BLOCKED_JOBS_SET.replace("{queue}", name),
+                cancel_requested_set: CANCEL_REQUESTED_SET.replace("{queue}", name),
+                cancelled_jobs_set: CANCELLED_JOBS_SET.replace("{queue}", name),
                 consumers_set: CONSUMERS_SET.replace("{queue}", name),
                 dead_jobs_set: DEAD_JOBS_SET.replace("{queue}", name),
+                dependents_set: DEPENDENTS_SET.replace("{queue}", name),
                 done_jobs_set: DONE_JOBS_SET.replace("{queue}", name),
                 failed_jobs_set: FAILED_JOBS_SET.replace("{queue}", name),
                 inflight_jobs_set: INFLIGHT_JOB_SET.replace("{queue}", name),
+                job_batches_hash: JOB_BATCHES_HASH.replace("{queue}", name),
                 job_data_hash: JOB_DATA_HASH.replace("{queue}", name),
+                job_logs_list: JOB_LOGS_LIST.replace("{queue}", name),
+                job_priority_hash: JOB_PRIORITY_HASH.replace("{queue}", name),
+                job_results_hash: JOB_RESULTS_HASH.replace("{queue}", name),
+                legacy_active_jobs_list: LEGACY_ACTIVE_JOBS_LIST.replace("{queue}", name),
+                result_expiry_set: RESULT_EXPIRY_SET.replace("{queue}", name),
                 scheduled_jobs_set: SCHEDULED_JOBS_SET.replace("{queue}", name),
                 signal_list: SIGNAL_LIST.replace("{queue}", name),
+                unique_key: UNIQUE_KEY.replace("{queue}", name),
+                unique_keys_hash: UNIQUE_KEYS_HASH.replace("{queue}", name),
             },
             scripts: RedisScript {
-                ack_job: redis::Script::new(include_str!("../lua/ack_job.lua")),
-                push_job: redis::Script::new(include_str!("../lua/push_job.lua")),
+                ack_job: shared_script!("../lua/ack_job.lua"),
+                close_batch: shared_script!("../lua/close_batch.lua"),
+                push_job: shared_script!("../lua/push_job.lua"),
                 retry_job: redis::Script::new(include_str!("../lua/retry_job.lua")),
-                enqueue_scheduled: redis::Script::new(include_str!(
-                    "../lua/enqueue_scheduled_jobs.lua"
-                )),
-                get_jobs: redis::Script::new(include_str!("../lua/get_jobs.lua")),
+                enqueue_scheduled: shared_script!("../lua/enqueue_scheduled_jobs.lua"),
+                get_jobs: shared_script!("../lua/get_jobs.lua"),
                 register_consumer: redis::Script::new(include_str!("../lua/register_consumer.lua")),
-                kill_job: redis::Script::new(include_str!("../lua/kill_job.lua")),
-                reenqueue_active: redis::Script::new(include_str!(
-                    "../lua/reenqueue_active_jobs.lua"
-                )),
-                reenqueue_orphaned: redis::Script::new(include_str!(
-                    "../lua/reenqueue_orphaned_jobs.lua"
-                )),
-                schedule_job: redis::Script::new(include_str!("../lua/schedule_job.lua")),
+                kill_job: shared_script!("../lua/kill_job.lua"),
+                reenqueue_active: shared_script!("../lua/reenqueue_active_jobs.lua"),
+                reenqueue_orphaned: shared_script!("../lua/reenqueue_orphaned_jobs.lua"),
+                purge_dead: redis::Script::new(include_str!("../lua/purge_dead.lua")),
+                requeue_dead: shared_script!("../lua/requeue_dead.lua"),
+                save_result: redis::Script::new(include_str!("../lua/save_result.lua")),
+                reschedule_job: redis::Script::new(include_str!("../lua/reschedule_job.lua")),
             },
         }
     }
@@ -124,14 +194,17 @@ impl<T: DeserializeOwned + Send + Unpin> RedisStorage<T> {
         &self,
         worker_id: String,
         interval: Duration,
+        buffer_size: usize,
     ) -> impl Stream<Item = Result<Option<JobRequest<T>>, JobStreamError>> {
         let mut conn = self.conn.clone();
         let fetch_jobs = self.scripts.get_jobs.clone();
         let consumers_set = self.queue.consumers_set.to_string();
-        let active_jobs_list = self.queue.active_jobs_list.to_string();
+        let active_jobs_set = self.queue.active_jobs_set.to_string();
         let job_data_hash = self.queue.job_data_hash.to_string();
         let inflight_set = format!("{}:{}", self.queue.inflight_jobs_set, worker_id);
         let signal_list = self.queue.signal_list.to_string();
+        let job_priority_hash = self.queue.job_priority_hash.to_string();
+        let legacy_active_jobs_list = self.queue.legacy_active_jobs_list.to_string();
         let start = Instant::now() + Duration::from_millis(5);
         let mut interval = tokio::time::interval_at(start, interval);
         try_stream! {
@@ -139,32 +212,116 @@ impl<T: DeserializeOwned + Send + Unpin> RedisStorage<T> {
                 interval.tick().await;
                 let res: Result<Vec<Value>, JobStreamError> = fetch_jobs
                     .key(&consumers_set)
-                    .key(&active_jobs_list)
+                    .key(&active_jobs_set)
                     .key(&inflight_set)
                     .key(&job_data_hash)
                     .key(&signal_list)
-                    .arg("1") // fetch one job at a time
+                    .key(&job_priority_hash)
+                    .key(&legacy_active_jobs_list)
+                    .arg(buffer_size)
                     .arg(&inflight_set)
+                    .arg(Utc::now().timestamp())
                     .invoke_async(&mut conn)
                     .await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)));
-                let job = unwrap_job(res)?;
-                yield job
+                let jobs = unwrap_jobs(res)?;
+                if jobs.is_empty() {
+                    yield None;
+                }
+                for job in jobs {
+                    yield Some(job);
+                }
+            }
+        }
+    }
+
+    /// Kill or cancel a job and every job waiting on it, returning 1 if the job was
+    /// taken out of `inflight_set`, or out of the queue when `not_started`.
+    ///
+    /// The jobs waiting on it and their batches are listed first so that the script
+    /// is handed every key it touches, and listed again if they changed meanwhile.
+    async fn kill_job(
+        &self,
+        inflight_set: String,
+        finished_set: &str,
+        job_id: &str,
+        data: String,
+        not_started: bool,
+    ) -> StorageResult<i8> {
+        let mut conn = self.conn.clone();
+        loop {
+            let mut job_ids = vec![job_id.to_string()];
+            let mut i = 0;
+            while i < job_ids.len() {
+                let children: Vec<String> = redis::cmd("SMEMBERS")
+                    .arg(format!("{}{}", self.queue.dependents_set, job_ids[i]))
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::new(e)))?;
+                for child in children {
+                    if !job_ids.contains(&child) {
+                        job_ids.push(child);
+                    }
+                }
+                i += 1;
+            }
+            let batch_ids: Vec<Option<String>> = redis::cmd("HMGET")
+                .arg(&self.queue.job_batches_hash)
+                .arg(&job_ids)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| StorageError::Database(Box::new(e)))?;
+            let mut batch_ids: Vec<String> = batch_ids.into_iter().flatten().collect();
+            batch_ids.sort();
+            batch_ids.dedup();
+
+            let mut invocation = self.scripts.kill_job.key(&inflight_set);
+            invocation
+                .key(finished_set)
+                .key(&self.queue.job_data_hash)
+                .key(&self.queue.unique_keys_hash)
+                .key(&self.queue.blocked_jobs_hash)
+                .key(&self.queue.blocked_jobs_set)
+                .key(&self.queue.job_batches_hash)
+                .key(&self.queue.active_jobs_set)
+                .key(&self.queue.job_priority_hash)
+                .key(&self.queue.signal_list)
+                .key(&self.queue.scheduled_jobs_set)
+                .key(&self.queue.cancel_requested_set)
+                .arg(job_id)
+                .arg(Utc::now().timestamp())
+                .arg(&data)
+                .arg(if not_started { 1 } else { 0 })
+                .arg(job_ids.len())
+                .arg(&job_ids)
+                .arg(&batch_ids);
+            for id in &job_ids {
+                invocation.key(format!("{}{}", self.queue.dependents_set, id));
+            }
+            for batch_id in &batch_ids {
+                invocation.key(format!("{}{}", self.queue.batch_hash, batch_id));
+            }
+            let killed: i8 = invocation
+                .invoke_async(&mut conn)
+                .await
+                .map_err(|e| StorageError::Database(Box::new(e)))?;
+            if killed >= 0 {
+                return Ok(killed);
             }
         }
     }
 }
 
-fn unwrap_job<T>(
+fn unwrap_jobs<T>(
     data: Result<Vec<Value>, JobStreamError>,
-) -> Result<Option<JobRequest<T>>, JobStreamError>
+) -> Result<Vec<JobRequest<T>>, JobStreamError>
 where
     T: DeserializeOwned,
 {
     match data {
-        Ok(jobs) => {
-            let job = jobs.get(0);
-            Ok(deserialize_job(job))
-        }
+        Ok(jobs) => Ok(jobs
+            .iter()
+            .filter_map(|job| deserialize_job(Some(job)))
+            .collect()),
         Err(e) => Err(e),
     }
 }
@@ -176,7 +333,7 @@ where
     let job = match job {
         job @ Some(Value::Data(_)) => job,
         None => None,
-        Some(Value::Bulk(val)) => val.get(0),
+        Some(Value::Bulk(val)) => val.first(),
         _ => {
             error!(
                 "Decoding Message Failed: {:?}",
@@ -230,93 +387,205 @@ where
 {
     type Output = T;
 
-    async fn push(&mut self, job: Self::Output) -> StorageResult<()> {
+    async fn push_with(&mut self, job: Self::Output, options: PushOptions) -> StorageResult<JobId> {
         let mut conn = self.conn.clone();
+        let id = options.id().copied().unwrap_or_default();
+        let now = Utc::now();
+        let mut context = JobContext::new(id.to_string());
+        context.set_max_attempts(options.max_attempts());
+        context.set_timeout(options.timeout());
+        context.set_priority(options.priority());
+        let run_at = match options.run_at() {
+            Some(on) if *on > now => {
+                context.set_run_at(*on);
+                *on
+            }
+            Some(on) => *on,
+            None => now,
+        };
         let push_job = self.scripts.push_job.clone();
-        let job_data_hash = self.queue.job_data_hash.to_string();
-        let active_jobs_list = self.queue.active_jobs_list.to_string();
-        let signal_list = self.queue.signal_list.to_string();
-        let job = JobRequest::new(job);
-        let job_id = job.id();
-        let job = serde_json::to_string(&job)?;
-        log::debug!(
-            "Received new job with id: {} to list: {}",
-            job_id,
-            active_jobs_list
-        );
-
-        push_job
-            .key(job_data_hash)
-            .key(active_jobs_list)
-            .key(signal_list)
-            .arg(job_id)
-            .arg(job)
-            .invoke_async(&mut conn)
-            .await
-            .map_err(|e| StorageError::Database(Box::from(e)))
-    }
-
-    async fn schedule(&mut self, job: Self::Output, on: DateTime<Utc>) -> StorageResult<()> {
-        let mut conn = self.conn.clone();
-        let schedule_job = self.scripts.schedule_job.clone();
-        let job_data_hash = self.queue.job_data_hash.to_string();
-        let scheduled_jobs_set = self.queue.scheduled_jobs_set.to_string();
-        let job = JobRequest::new(job);
-        let job_id = job.id();
-        let job = serde_json::to_string(&job)?;
-        log::trace!(
-            "Scheduled new job with id: {} to list: {}",
-            job_id,
-            scheduled_jobs_set
-        );
-
-        schedule_job
-            .key(job_data_hash)
-            .key(scheduled_jobs_set)
-            .arg(job_id)
-            .arg(job)
-            .arg(on.timestamp())
-            .invoke_async(&mut conn)
-            .await
-            .map_err(|e| StorageError::Database(Box::from(e)))
+        let policy = match (options.unique_key(), options.unique_policy()) {
+            (None, _) => "",
+            (Some(_), UniquePolicy::Reject) => "reject",
+            (Some(_), UniquePolicy::Replace) => "replace",
+            (Some(_), UniquePolicy::Ignore) => "ignore",
+        };
+        let unique_key = self
+            .queue
+            .unique_key
+            .replace("{key}", options.unique_key().unwrap_or_default());
+        let unique_ttl = options
+            .unique_ttl()
+            .map_or(0, |ttl| (ttl.as_millis() as u64).max(1));
+        let job = serde_json::to_string(&JobRequest::new_with_context(job, context))?;
+        log::debug!("Received new job with id: {}", id);
+        let parents: Vec<String> = options.parents().iter().map(ToString::to_string).collect();
+        let batch_id = options.batch().map(ToString::to_string).unwrap_or_default();
+        let (pushed, holder) = loop {
+            // The job holding the unique key is replaced only if no job waits on it,
+            // so its set of waiting jobs is looked up ahead of the script
+            let expected: Option<String> = match options.unique_policy() {
+                UniquePolicy::Replace if !policy.is_empty() => redis::cmd("GET")
+                    .arg(&unique_key)
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::from(e)))?,
+                _ => None,
+            };
+            let expected = expected.unwrap_or_default();
+            let mut invocation = push_job.key(&self.queue.job_data_hash);
+            invocation
+                .key(&self.queue.active_jobs_set)
+                .key(&self.queue.signal_list)
+                .key(&self.queue.job_priority_hash)
+                .key(&self.queue.scheduled_jobs_set)
+                .key(&unique_key)
+                .key(&self.queue.unique_keys_hash)
+                .key(&self.queue.done_jobs_set)
+                .key(&self.queue.dead_jobs_set)
+                .key(&self.queue.blocked_jobs_set)
+                .key(&self.queue.blocked_jobs_hash)
+                .key(&self.queue.job_batches_hash)
+                .key(format!("{}{}", self.queue.batch_hash, batch_id))
+                .key(&self.queue.cancelled_jobs_set)
+                .key(format!("{}{}", self.queue.dependents_set, expected))
+                .arg(id.to_string())
+                .arg(&job)
+                .arg(options.priority())
+                .arg(run_at.timestamp())
+                .arg(now.timestamp())
+                .arg(policy)
+                .arg(unique_ttl)
+                .arg(&batch_id)
+                .arg(&expected)
+                .arg(&parents);
+            for parent in &parents {
+                invocation.key(format!("{}{}", self.queue.dependents_set, parent));
+            }
+            let (pushed, holder): (i8, String) = invocation
+                .invoke_async(&mut conn)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            // Look the holder up again if the unique key changed hands meanwhile
+            if pushed != -5 {
+                break (pushed, holder);
+            }
+        };
+        let holder = holder
+            .parse()
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        match pushed {
+            -1 | -4 => Err(StorageError::Duplicate(holder)),
+            -2 | -3 => Err(StorageError::NotFound),
+            _ => Ok(holder),
+        }
     }
 
-    fn consume(&mut self, worker_id: String, interval: Duration) -> JobStreamResult<T> {
-        Box::pin(self.stream_jobs(worker_id, interval))
+    fn consume(
+        &mut self,
+        worker_id: String,
+        interval: Duration,
+        buffer_size: usize,
+    ) -> JobStreamResult<T> {
+        Box::pin(self.stream_jobs(worker_id, interval, buffer_size))
     }
 
     async fn kill(&mut self, worker_id: String, job_id: String) -> StorageResult<()> {
-        let mut conn = self.conn.clone();
-        let kill_job = self.scripts.kill_job.clone();
         let current_worker_id = format!("{}:{}", self.queue.inflight_jobs_set, worker_id);
-        let job_data_hash = self.queue.job_data_hash.to_string();
-        let dead_jobs_set = self.queue.dead_jobs_set.to_string();
         let fetch_job = self.fetch_by_id(job_id.clone());
 
         let res = fetch_job.await?;
         match res {
             Some(job) => {
-                let now = Utc::now().timestamp();
                 let data = serde_json::to_string(&job)?;
-                kill_job
-                    .key(current_worker_id)
-                    .key(dead_jobs_set)
-                    .key(job_data_hash)
-                    .arg(job_id)
-                    .arg(now)
-                    .arg(data)
-                    .invoke_async(&mut conn)
-                    .await
-                    .map_err(|e| StorageError::Database(Box::from(e)))
+                self.kill_job(
+                    current_worker_id,
+                    &self.queue.dead_jobs_set,
+                    &job_id,
+                    data,
+                    false,
+                )
+                .await?;
+                Ok(())
             }
             None => Err(StorageError::NotFound),
         }
     }
 
+    async fn cancel(&mut self, job_id: String) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let mut job = self
+            .fetch_by_id(job_id.clone())
+            .await?
+            .ok_or(StorageError::NotFound)?;
+        let now = Utc::now();
+        job.set_status(JobState::Cancelled);
+        job.set_done_at(Some(now));
+        let cancelled = self
+            .kill_job(
+                self.queue.inflight_jobs_set.clone(),
+                &self.queue.cancelled_jobs_set,
+                &job_id,
+                serde_json::to_string(&job)?,
+                true,
+            )
+            .await?;
+        if cancelled == 1 {
+            return Ok(());
+        }
+        // The job is neither waiting nor finished, so a worker is running it
+        let (done, dead, cancelled): (Option<i64>, Option<i64>, Option<i64>) = redis::pipe()
+            .zscore(&self.queue.done_jobs_set, &job_id)
+            .zscore(&self.queue.dead_jobs_set, &job_id)
+            .zscore(&self.queue.cancelled_jobs_set, &job_id)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        if done.or(dead).or(cancelled).is_some() {
+            return Err(StorageError::NotFound);
+        }
+        let _: () = redis::cmd("SADD")
+            .arg(&self.queue.cancel_requested_set)
+            .arg(&job_id)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_cancelled(&self, worker_id: String) -> StorageResult<Vec<String>> {
+        let mut conn = self.conn.clone();
+        redis::cmd("SINTER")
+            .arg(format!("{}:{}", self.queue.inflight_jobs_set, worker_id))
+            .arg(&self.queue.cancel_requested_set)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))
+    }
+
+    async fn abort(&mut self, worker_id: String, job_id: String) -> StorageResult<()> {
+        let mut job = self
+            .fetch_by_id(job_id.clone())
+            .await?
+            .ok_or(StorageError::NotFound)?;
+        let now = Utc::now();
+        job.set_status(JobState::Cancelled);
+        job.set_done_at(Some(now));
+        self.kill_job(
+            format!("{}:{}", self.queue.inflight_jobs_set, worker_id),
+            &self.queue.cancelled_jobs_set,
+            &job_id,
+            serde_json::to_string(&job)?,
+            false,
+        )
+        .await?;
+        Ok(())
+    }
+
     async fn len(&self) -> StorageResult<i64> {
         let mut conn = self.conn.clone();
         let length: i64 = redis::cmd("HLEN")
-            .arg(&self.queue.job_data_hash.to_string())
+            .arg(self.queue.job_data_hash.to_string())
             .query_async(&mut conn)
             .await
             .map_err(|e| StorageError::Database(Box::new(e)))?;
@@ -326,7 +595,7 @@ where
     async fn fetch_by_id(&self, job_id: String) -> StorageResult<Option<JobRequest<Self::Output>>> {
         let mut conn = self.conn.clone();
         let data: Option<Value> = redis::cmd("HMGET")
-            .arg(&self.queue.job_data_hash.to_string())
+            .arg(self.queue.job_data_hash.to_string())
             .arg(job_id.clone())
             .query_async(&mut conn)
             .await
@@ -337,7 +606,7 @@ where
         let mut conn = self.conn.clone();
         let job = serde_json::to_string(job)?;
         let res: Result<i64, RedisError> = redis::cmd("HSET")
-            .arg(&self.queue.job_data_hash.to_string())
+            .arg(self.queue.job_data_hash.to_string())
             .arg(job_id)
             .arg(job)
             .query_async(&mut conn)
@@ -346,16 +615,78 @@ where
         Ok(())
     }
 
+    async fn update_progress(
+        &mut self,
+        job_id: String,
+        progress: &JobProgress,
+    ) -> StorageResult<()> {
+        let mut job = self
+            .fetch_by_id(job_id.clone())
+            .await?
+            .ok_or(StorageError::NotFound)?;
+        job.context_mut().set_progress(Some(progress.clone()));
+        self.update_by_id(job_id, &job).await
+    }
+
+    async fn append_logs(
+        &mut self,
+        job_id: String,
+        lines: &[JobLogLine],
+        max_lines: usize,
+    ) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let logs_list = format!("{}{}", self.queue.job_logs_list, job_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if max_lines == 0 {
+            pipe.del(&logs_list).ignore();
+        } else {
+            let lines = lines
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?;
+            pipe.rpush(&logs_list, lines)
+                .ignore()
+                .ltrim(&logs_list, -(max_lines as isize), -1)
+                .ignore();
+        }
+        pipe.query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))
+    }
+
     async fn ack(&mut self, worker_id: String, job_id: String) -> StorageResult<()> {
         let mut conn = self.conn.clone();
         let ack_job = self.scripts.ack_job.clone();
         let inflight_set = format!("{}:{}", self.queue.inflight_jobs_set, worker_id);
         let done_jobs_set = &self.queue.done_jobs_set.to_string();
+        // The batch of a job never changes, so it can be looked up ahead of the script
+        let batch_id: Option<String> = redis::cmd("HGET")
+            .arg(&self.queue.job_batches_hash)
+            .arg(&job_id)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
 
         let now = Utc::now().timestamp();
         ack_job
             .key(inflight_set)
             .key(done_jobs_set)
+            .key(&self.queue.unique_keys_hash)
+            .key(&self.queue.blocked_jobs_hash)
+            .key(&self.queue.blocked_jobs_set)
+            .key(&self.queue.active_jobs_set)
+            .key(&self.queue.scheduled_jobs_set)
+            .key(&self.queue.job_priority_hash)
+            .key(&self.queue.signal_list)
+            .key(&self.queue.job_data_hash)
+            .key(&self.queue.job_batches_hash)
+            .key(format!("{}{}", self.queue.dependents_set, job_id))
+            .key(format!(
+                "{}{}",
+                self.queue.batch_hash,
+                batch_id.unwrap_or_default()
+            ))
             .arg(job_id)
             .arg(now)
             .invoke_async(&mut conn)
@@ -377,7 +708,7 @@ where
             Some(job) => {
                 if job.attempts() >= job.max_attempts() {
                     warn!("too many retries: {:?}", job.attempts());
-                    let _res = redis::cmd("ZADD")
+                    let _res: () = redis::cmd("ZADD")
                         .arg(failed_jobs_set)
                         .arg(Utc::now().timestamp())
                         .arg(job_id.clone())
@@ -435,13 +766,14 @@ where
             StorageWorkerPulse::EnqueueScheduled { count } => {
                 let enqueue_jobs = self.scripts.enqueue_scheduled.clone();
                 let scheduled_jobs_set = self.queue.scheduled_jobs_set.to_string();
-                let active_jobs_list = self.queue.active_jobs_list.to_string();
+                let active_jobs_set = self.queue.active_jobs_set.to_string();
                 let signal_list = self.queue.signal_list.to_string();
                 let timestamp = Utc::now().timestamp();
                 let res: Result<i8, StorageError> = enqueue_jobs
                     .key(scheduled_jobs_set)
-                    .key(active_jobs_list)
+                    .key(active_jobs_set)
                     .key(signal_list)
+                    .key(&self.queue.job_priority_hash)
                     .arg(timestamp)
                     .arg(count)
                     .invoke_async(&mut conn)
@@ -458,18 +790,25 @@ where
                 }
             }
 
-            StorageWorkerPulse::RenqueueOrpharned { count } => {
+            StorageWorkerPulse::RenqueueOrpharned {
+                count,
+                timeout_worker,
+            } => {
                 let reenqueue_orphaned = self.scripts.reenqueue_orphaned.clone();
                 let consumers_set = self.queue.consumers_set.to_string();
-                let active_jobs_list = self.queue.active_jobs_list.to_string();
+                let active_jobs_set = self.queue.active_jobs_set.to_string();
                 let signal_list = self.queue.signal_list.to_string();
-                let timestamp = Utc::now() - chrono::Duration::minutes(5);
+                let timeout_worker = chrono::Duration::from_std(timeout_worker)
+                    .map_err(|e| StorageError::Database(Box::from(e)))?;
+                let timestamp = Utc::now() - timeout_worker;
                 let res: Result<i8, StorageError> = reenqueue_orphaned
                     .key(consumers_set)
-                    .key(active_jobs_list)
+                    .key(active_jobs_set)
                     .key(signal_list)
+                    .key(&self.queue.job_priority_hash)
                     .arg(timestamp.timestamp())
                     .arg(count)
+                    .arg(Utc::now().timestamp())
                     .invoke_async(&mut conn)
                     .await
                     .map_err(|e| StorageError::Database(Box::from(e)));
@@ -486,55 +825,316 @@ where
             _ => todo!(),
         }
     }
-    async fn reenqueue_active(&mut self, job_ids: Vec<String>) -> StorageResult<()> {
+    async fn reenqueue_active(&mut self, worker_id: String) -> StorageResult<()> {
         let mut conn = self.conn.clone();
         let reenqueue_active = self.scripts.reenqueue_active.clone();
-        let inflight_set = self.queue.inflight_jobs_set.to_string();
-        let active_jobs_list = self.queue.active_jobs_list.to_string();
+        let inflight_set = format!("{}:{}", self.queue.inflight_jobs_set, worker_id);
+        let active_jobs_set = self.queue.active_jobs_set.to_string();
         let signal_list = self.queue.signal_list.to_string();
 
         reenqueue_active
             .key(inflight_set)
-            .key(active_jobs_list)
+            .key(active_jobs_set)
             .key(signal_list)
-            .arg(job_ids)
+            .key(&self.queue.job_priority_hash)
+            .arg(Utc::now().timestamp())
             .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::Connection(Box::from(e)))
     }
-    async fn reschedule(&mut self, job: &JobRequest<T>, _wait: Duration) -> StorageResult<()> {
+    async fn reschedule(
+        &mut self,
+        worker_id: String,
+        job: &JobRequest<T>,
+        wait: Duration,
+    ) -> StorageResult<()> {
         let mut conn = self.conn.clone();
-        let schedule_job = self.scripts.schedule_job.clone();
+        let reschedule_job = self.scripts.reschedule_job.clone();
         let job_id = job.id();
-        let worker_id = job.lock_by().clone().unwrap_or_default();
+        let priority = job.priority();
         let job = serde_json::to_string(job)?;
         let job_data_hash = self.queue.job_data_hash.to_string();
         let scheduled_jobs_set = self.queue.scheduled_jobs_set.to_string();
-        let on = Utc::now();
+        let wait =
+            chrono::Duration::from_std(wait).map_err(|e| StorageError::Database(Box::new(e)))?;
+        let on = Utc::now() + wait;
+        // Round up, so that the job never runs before `wait` has passed
+        let on = on.timestamp() + i64::from(on.timestamp_subsec_nanos() > 0);
         let inflight_set = format!("{}:{}", self.queue.inflight_jobs_set, worker_id);
-        let failed_jobs_set = self.queue.failed_jobs_set.to_string();
-        let _cmd = redis::cmd("SREM")
-            .arg(inflight_set)
-            .arg(job_id.clone())
-            .query_async(&mut conn)
+        let _: i8 = reschedule_job
+            .key(job_data_hash)
+            .key(scheduled_jobs_set)
+            .key(&self.queue.job_priority_hash)
+            .key(inflight_set)
+            .arg(job_id)
+            .arg(job)
+            .arg(on)
+            .arg(priority)
+            .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        let _cmd = redis::cmd("ZADD")
-            .arg(failed_jobs_set)
-            .arg(Utc::now().timestamp())
-            .arg(job_id.clone())
+        Ok(())
+    }
+
+    async fn open_batch(&mut self, callbacks: BatchCallbacks<T>) -> StorageResult<BatchId> {
+        let mut conn = self.conn.clone();
+        let batch_id = BatchId::new();
+        let now = Utc::now().timestamp();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        pipe.hset_multiple(
+            format!("{}{}", self.queue.batch_hash, batch_id),
+            &[("total", 0), ("done", 0), ("failed", 0), ("closed", 0)],
+        )
+        .ignore();
+        // Callbacks wait in the blocked set until the batch finishes
+        for (field, callback) in [
+            ("on_success", callbacks.on_success()),
+            ("on_failure", callbacks.on_failure()),
+        ] {
+            let callback = match callback {
+                Some(callback) => callback,
+                None => continue,
+            };
+            let id = JobId::new().to_string();
+            let job = serde_json::to_string(&StoredJob {
+                job: callback,
+                context: JobContext::new(id.clone()),
+            })?;
+            pipe.hset(&self.queue.job_data_hash, &id, job)
+                .ignore()
+                .hset(&self.queue.job_priority_hash, &id, 0)
+                .ignore()
+                .zadd(&self.queue.blocked_jobs_set, &id, now)
+                .ignore()
+                .hset(format!("{}{}", self.queue.batch_hash, batch_id), field, &id)
+                .ignore();
+        }
+        let _: () = pipe
             .query_async(&mut conn)
             .await
-            .map_err(|e| StorageError::Database(Box::from(e)))?;
-        schedule_job
-            .key(job_data_hash)
-            .key(scheduled_jobs_set)
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        Ok(batch_id)
+    }
+
+    async fn close_batch(&mut self, batch_id: &BatchId) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let close_batch = self.scripts.close_batch.clone();
+        let closed: i8 = close_batch
+            .key(format!("{}{}", self.queue.batch_hash, batch_id))
+            .key(&self.queue.blocked_jobs_set)
+            .key(&self.queue.active_jobs_set)
+            .key(&self.queue.signal_list)
+            .key(&self.queue.job_priority_hash)
+            .key(&self.queue.job_data_hash)
+            .arg(Utc::now().timestamp())
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        match closed {
+            0 => Err(StorageError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn batch_progress(&self, batch_id: &BatchId) -> StorageResult<Option<BatchProgress>> {
+        let mut conn = self.conn.clone();
+        let (total, done, failed, closed): (Option<i64>, Option<i64>, Option<i64>, Option<i64>) =
+            redis::cmd("HMGET")
+                .arg(format!("{}{}", self.queue.batch_hash, batch_id))
+                .arg(&["total", "done", "failed", "closed"])
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| StorageError::Database(Box::new(e)))?;
+        Ok(total.map(|total| BatchProgress {
+            total,
+            done: done.unwrap_or_default(),
+            failed: failed.unwrap_or_default(),
+            closed: closed.unwrap_or_default() == 1,
+        }))
+    }
+
+    async fn save_result(
+        &mut self,
+        job_id: String,
+        result: serde_json::Value,
+        retention: Duration,
+    ) -> StorageResult<()> {
+        let mut conn = self.conn.clone();
+        let save_result = self.scripts.save_result.clone();
+        let now = Utc::now();
+        let expires_at = now
+            + chrono::Duration::from_std(retention)
+                .map_err(|e| StorageError::Database(Box::new(e)))?;
+        let saved: i8 = save_result
+            .key(&self.queue.job_results_hash)
+            .key(&self.queue.result_expiry_set)
+            .key(&self.queue.job_data_hash)
             .arg(job_id)
-            .arg(job)
-            .arg(on.timestamp())
+            .arg(serde_json::to_string(&result)?)
+            .arg(now.timestamp())
+            .arg(expires_at.timestamp())
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        match saved {
+            0 => Err(StorageError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    async fn fetch_result(&self, job_id: String) -> StorageResult<Option<serde_json::Value>> {
+        let mut conn = self.conn.clone();
+        let (result, expires_at): (Option<String>, Option<i64>) = redis::pipe()
+            .hget(&self.queue.job_results_hash, &job_id)
+            .zscore(&self.queue.result_expiry_set, &job_id)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        match (result, expires_at) {
+            (Some(result), Some(expires_at)) if expires_at > Utc::now().timestamp() => {
+                Ok(Some(serde_json::from_str(&result)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> DeadLetter for RedisStorage<T>
+where
+    T: Serialize + DeserializeOwned + Send + 'static + Unpin + Job,
+{
+    async fn list_dead(&self, page: i32) -> StorageResult<Vec<JobRequest<Self::Output>>> {
+        let mut conn = self.conn.clone();
+        let ids: Vec<String> = redis::cmd("ZREVRANGE")
+            .arg(&self.queue.dead_jobs_set)
+            .arg((page - 1) * 10)
+            .arg(page * 10 - 1)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let data: Option<Value> = redis::cmd("HMGET")
+            .arg(&self.queue.job_data_hash)
+            .arg(&ids)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        Ok(deserialize_multiple_jobs(data.as_ref()).unwrap_or_default())
+    }
+
+    async fn requeue_dead(&mut self, job_ids: &[JobId]) -> StorageResult<u64> {
+        let mut conn = self.conn.clone();
+        let requeue_dead = self.scripts.requeue_dead.clone();
+        let mut requeued = 0;
+        for job_id in job_ids {
+            let job_id = job_id.to_string();
+            let mut job = match self.fetch_by_id(job_id.clone()).await? {
+                Some(job) => job,
+                None => continue,
+            };
+            let now = Utc::now();
+            let context = job.context_mut();
+            context.set_status(JobState::Pending);
+            context.set_attempts(0);
+            context.set_run_at(now);
+            context.set_done_at(None);
+            context.set_lock_at(None);
+            context.set_lock_by(None);
+            let done: u64 = requeue_dead
+                .key(&self.queue.dead_jobs_set)
+                .key(&self.queue.job_data_hash)
+                .key(&self.queue.active_jobs_set)
+                .key(&self.queue.job_priority_hash)
+                .key(&self.queue.signal_list)
+                .key(&self.queue.job_batches_hash)
+                .arg(job_id)
+                .arg(serde_json::to_string(&job)?)
+                .arg(now.timestamp())
+                .invoke_async(&mut conn)
+                .await
+                .map_err(|e| StorageError::Database(Box::new(e)))?;
+            requeued += done;
+        }
+        Ok(requeued)
+    }
+
+    async fn requeue_dead_matching(&mut self, filter: &DeadLetterFilter) -> StorageResult<u64> {
+        let mut conn = self.conn.clone();
+        let min = filter
+            .killed_after()
+            .map(|at| at.timestamp().to_string())
+            .unwrap_or_else(|| "-inf".to_string());
+        let max = filter
+            .killed_before()
+            .map(|at| format!("({}", at.timestamp()))
+            .unwrap_or_else(|| "+inf".to_string());
+        let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(&self.queue.dead_jobs_set)
+            .arg(min)
+            .arg(max)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let data: Option<Value> = redis::cmd("HMGET")
+            .arg(&self.queue.job_data_hash)
+            .arg(&ids)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        let jobs: Vec<JobRequest<T>> = deserialize_multiple_jobs(data.as_ref()).unwrap_or_default();
+        let job_ids: Vec<JobId> = jobs
+            .iter()
+            .filter(|job| match filter.error_contains() {
+                Some(text) => job
+                    .context()
+                    .last_error()
+                    .as_ref()
+                    .map_or(false, |error| error.contains(text)),
+                None => true,
+            })
+            .filter_map(|job| job.context().id().parse().ok())
+            .collect();
+        self.requeue_dead(&job_ids).await
+    }
+
+    async fn purge_dead(&mut self, age: Duration) -> StorageResult<u64> {
+        let mut conn = self.conn.clone();
+        let purge_dead = self.scripts.purge_dead.clone();
+        let cutoff = Utc::now()
+            - chrono::Duration::from_std(age).map_err(|e| StorageError::Database(Box::new(e)))?;
+        let job_ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(&self.queue.dead_jobs_set)
+            .arg("-inf")
+            .arg(cutoff.timestamp())
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        if job_ids.is_empty() {
+            return Ok(0);
+        }
+        let mut invocation = purge_dead.key(&self.queue.dead_jobs_set);
+        invocation
+            .key(&self.queue.job_data_hash)
+            .key(&self.queue.job_priority_hash)
+            .key(&self.queue.job_batches_hash)
+            .key(&self.queue.job_results_hash)
+            .key(&self.queue.result_expiry_set)
+            .arg(cutoff.timestamp())
+            .arg(&job_ids);
+        for job_id in &job_ids {
+            invocation.key(format!("{}{}", self.queue.job_logs_list, job_id));
+        }
+        invocation
             .invoke_async(&mut conn)
             .await
-            .map_err(|e| StorageError::Database(Box::from(e)))
+            .map_err(|e| StorageError::Database(Box::new(e)))
     }
 }
 
@@ -543,6 +1143,23 @@ impl<T> JobStreamExt<T> for RedisStorage<T>
 where
     T: 'static + Job + Serialize + DeserializeOwned,
 {
+    async fn job_logs(&mut self, job_id: String) -> Result<Vec<JobLogLine>, JobError> {
+        let mut conn = self.conn.clone();
+        let lines: Vec<String> = redis::cmd("LRANGE")
+            .arg(format!("{}{}", self.queue.job_logs_list, job_id))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::new(e)))?;
+        let lines = lines
+            .iter()
+            .map(|line| serde_json::from_str(line))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(StorageError::from)?;
+        Ok(lines)
+    }
+
     async fn list_jobs(
         &mut self,
         status: &JobState,
@@ -551,10 +1168,10 @@ where
         match status {
             JobState::Pending => {
                 let mut conn = self.conn.clone();
-                let active_jobs_list = &self.queue.active_jobs_list;
+                let active_jobs_set = &self.queue.active_jobs_set;
                 let job_data_hash = &self.queue.job_data_hash;
-                let ids: Vec<String> = redis::cmd("LRANGE")
-                    .arg(active_jobs_list)
+                let ids: Vec<String> = redis::cmd("ZRANGE")
+                    .arg(active_jobs_set)
                     .arg(((page - 1) * 10).to_string())
                     .arg((page * 10).to_string())
                     .query_async(&mut conn)
@@ -679,6 +1296,52 @@ where
                 let jobs: Vec<JobRequest<T>> = deserialize_multiple_jobs(data.as_ref()).unwrap();
                 Ok(jobs)
             }
+            JobState::Cancelled => {
+                let mut conn = self.conn.clone();
+                let cancelled_jobs_set = &self.queue.cancelled_jobs_set;
+                let job_data_hash = &self.queue.job_data_hash;
+                let ids: Vec<String> = redis::cmd("ZRANGE")
+                    .arg(cancelled_jobs_set)
+                    .arg(((page - 1) * 10).to_string())
+                    .arg((page * 10).to_string())
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::new(e)))?;
+                if ids.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let data: Option<Value> = redis::cmd("HMGET")
+                    .arg(job_data_hash)
+                    .arg(&ids)
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::new(e)))?;
+                let jobs: Vec<JobRequest<T>> = deserialize_multiple_jobs(data.as_ref()).unwrap();
+                Ok(jobs)
+            }
+            JobState::Blocked => {
+                let mut conn = self.conn.clone();
+                let blocked_jobs_set = &self.queue.blocked_jobs_set;
+                let job_data_hash = &self.queue.job_data_hash;
+                let ids: Vec<String> = redis::cmd("ZRANGE")
+                    .arg(blocked_jobs_set)
+                    .arg(((page - 1) * 10).to_string())
+                    .arg((page * 10).to_string())
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::new(e)))?;
+                if ids.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let data: Option<Value> = redis::cmd("HMGET")
+                    .arg(job_data_hash)
+                    .arg(&ids)
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::new(e)))?;
+                let jobs: Vec<JobRequest<T>> = deserialize_multiple_jobs(data.as_ref()).unwrap();
+                Ok(jobs)
+            }
         }
     }
     async fn list_workers(&mut self) -> Result<Vec<JobStreamWorker>, JobError> {
@@ -709,8 +1372,15 @@ mod tests {
     use std::ops::Sub;
 
     use super::*;
+    use apalis_core::job::Backoff;
+    use apalis_core::job_fn::job_fn;
+    use apalis_core::storage::AckLayer;
+    use apalis_core::worker::WorkerRef;
+    use chrono::DateTime;
     use email_service::Email;
     use futures::StreamExt;
+    use serde::Deserialize;
+    use tower::{Layer, Service};
     use uuid::Uuid;
 
     /// migrate DB and return a storage instance.
@@ -728,7 +1398,7 @@ mod tests {
     /// rollback DB changes made by tests.
     ///
     /// You should execute this function in the end of a test
-    async fn cleanup(mut storage: RedisStorage<Email>, _worker_id: String) {
+    async fn cleanup<T>(mut storage: RedisStorage<T>, _worker_id: String) {
         let _resp: String = redis::cmd("FLUSHDB")
             .query_async(&mut storage.conn)
             .await
@@ -737,6 +1407,16 @@ mod tests {
 
     struct DummyService {}
 
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Report {
+        id: u32,
+    }
+
+    impl Job for Report {
+        const NAME: &'static str = "apalis::Report";
+        const BACKOFF: Backoff = Backoff::fixed(Duration::from_secs(60));
+    }
+
     fn example_email() -> Email {
         Email {
             subject: "Test Subject".to_string(),
@@ -749,7 +1429,7 @@ mod tests {
     where
         S: Storage<Output = T>,
     {
-        let mut stream = storage.consume(worker_id, std::time::Duration::from_secs(10));
+        let mut stream = storage.consume(worker_id, std::time::Duration::from_secs(10), 1);
         stream
             .next()
             .await
@@ -812,9 +1492,210 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_acknowledge_job() {
+    async fn test_consume_drains_legacy_active_list() {
         let mut storage = setup().await;
-        push_email(&mut storage, example_email()).await;
+        let job = JobRequest::new(example_email());
+        let job_id = job.id();
+        let mut conn = storage.conn.clone();
+        let _: () = redis::pipe()
+            .hset(
+                &storage.queue.job_data_hash,
+                &job_id,
+                serde_json::to_string(&job).expect("failed to serialize job"),
+            )
+            .rpush(&storage.queue.legacy_active_jobs_list, &job_id)
+            .query_async(&mut conn)
+            .await
+            .expect("failed to push a job to the legacy list");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.id(), job_id);
+        let legacy: bool = redis::cmd("EXISTS")
+            .arg(&storage.queue.legacy_active_jobs_list)
+            .query_async(&mut conn)
+            .await
+            .expect("failed to check the legacy list");
+        assert!(!legacy);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_push_with_clamps_extreme_priorities() {
+        let mut storage = setup().await;
+        let now = Utc::now();
+        let mut ids = Vec::new();
+        // Pushed from the lowest to the highest expected position
+        for (priority, minutes) in [(i32::MIN, 0), (0, 0), (i32::MAX - 1, 0), (i32::MAX, 1)] {
+            let options = PushOptions::new()
+                .with_priority(priority)
+                .with_run_at(now.sub(chrono::Duration::minutes(minutes)));
+            let id = storage
+                .push_with(example_email(), options)
+                .await
+                .expect("failed to push a job");
+            ids.push(id.to_string());
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+        let mut stream = storage.consume(worker_id.clone(), std::time::Duration::from_secs(10), 4);
+        let mut consumed = Vec::new();
+        for _ in 0..4 {
+            let job = stream
+                .next()
+                .await
+                .expect("stream is empty")
+                .expect("failed to poll job")
+                .expect("no job is pending");
+            consumed.push(job.context().id());
+        }
+        ids.reverse();
+        assert_eq!(consumed, ids);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_consume_prefers_higher_priority() {
+        let mut storage = setup().await;
+        let now = Utc::now();
+        let mut ids = Vec::new();
+        // Pushed from the lowest to the highest expected position
+        for (priority, minutes) in [(0, 3), (5, 1), (5, 2), (10, 0)] {
+            let options = PushOptions::new()
+                .with_priority(priority)
+                .with_run_at(now.sub(chrono::Duration::minutes(minutes)));
+            let id = storage
+                .push_with(example_email(), options)
+                .await
+                .expect("failed to push a job");
+            ids.push(id.to_string());
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut stream = storage.consume(worker_id.clone(), std::time::Duration::from_secs(10), 4);
+        let mut consumed = Vec::new();
+        for _ in 0..4 {
+            let job = stream
+                .next()
+                .await
+                .expect("stream is empty")
+                .expect("failed to poll job")
+                .expect("no job is pending");
+            consumed.push(job.context().id());
+        }
+        ids.reverse();
+        assert_eq!(consumed, ids);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_push_with_existing_id_fails() {
+        let mut storage = setup().await;
+        let id = JobId::new();
+        let pushed = storage
+            .push_with(example_email(), PushOptions::new().with_id(id))
+            .await
+            .expect("failed to push a job");
+        assert_eq!(pushed, id);
+        match storage
+            .push_with(example_email(), PushOptions::new().with_id(id))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, id),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_push_with_unique_key() {
+        let mut storage = setup().await;
+        let key = Uuid::new_v4().to_string();
+        let unique = |policy| PushOptions::new().with_unique_key(key.clone(), policy);
+
+        let first = storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+        match storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, first),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+        let ignored = storage
+            .push_with(example_email(), unique(UniquePolicy::Ignore))
+            .await
+            .expect("failed to push a job");
+        assert_eq!(ignored, first);
+
+        // The pending job is replaced
+        let replaced = storage
+            .push_with(example_email(), unique(UniquePolicy::Replace))
+            .await
+            .expect("failed to push a job");
+        assert_ne!(replaced, first);
+        assert!(storage
+            .fetch_by_id(first.to_string())
+            .await
+            .expect("failed to fetch job")
+            .is_none());
+
+        // A running job is not
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), replaced.to_string());
+        assert!(matches!(
+            storage
+                .push_with(example_email(), unique(UniquePolicy::Replace))
+                .await,
+            Err(StorageError::Duplicate(_))
+        ));
+
+        // The key is released once the job is done
+        storage
+            .ack(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to ack job");
+        storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_unique_key_is_released_after_ttl() {
+        let mut storage = setup().await;
+        let options = PushOptions::new()
+            .with_unique_key(Uuid::new_v4().to_string(), UniquePolicy::Reject)
+            .with_unique_ttl(std::time::Duration::from_millis(1));
+
+        storage
+            .push_with(example_email(), options.clone())
+            .await
+            .expect("failed to push a job");
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        storage
+            .push_with(example_email(), options)
+            .await
+            .expect("the expired key should be free");
+
+        cleanup(storage, String::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_acknowledge_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
 
         let worker_id = register_worker(&mut storage).await;
 
@@ -833,6 +1714,123 @@ mod tests {
         cleanup(storage, worker_id).await;
     }
 
+    /// Whether the job is still in the inflight set of the worker, and when it is scheduled
+    async fn inflight_and_scheduled<T>(
+        storage: &mut RedisStorage<T>,
+        worker_id: &str,
+        job_id: &str,
+    ) -> (bool, Option<i64>) {
+        redis::pipe()
+            .sismember(
+                format!("{}:{}", storage.queue.inflight_jobs_set, worker_id),
+                job_id,
+            )
+            .zscore(&storage.queue.scheduled_jobs_set, job_id)
+            .query_async(&mut storage.conn)
+            .await
+            .expect("failed to read the job sets")
+    }
+
+    #[tokio::test]
+    async fn test_reschedule_takes_job_out_of_inflight_set() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        storage
+            .reschedule(worker_id.clone(), &job, Duration::from_secs(60))
+            .await
+            .expect("failed to reschedule job");
+
+        let (inflight, scheduled) = inflight_and_scheduled(&mut storage, &worker_id, &job_id).await;
+        assert!(!inflight);
+        let scheduled = scheduled.expect("job was not scheduled");
+        assert!(scheduled >= Utc::now().timestamp() + 59);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_reschedules_failed_job_after_backoff() {
+        let redis_url = std::env::var("REDIS_URL").expect("No REDIS_URL is specified");
+        let mut storage = RedisStorage::<Report>::connect(redis_url.as_str())
+            .await
+            .expect("failed to connect DB server");
+        storage
+            .push(Report { id: 1 })
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = Uuid::new_v4().to_string();
+        storage
+            .keep_alive::<DummyService>(worker_id.clone())
+            .await
+            .expect("failed to register worker");
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone()).layer(
+            job_fn(|_: Report, _: JobContext| async { Err::<(), _>("renderer unavailable") }),
+        );
+        assert!(service.call(job).await.is_err());
+
+        // The job waits out its backoff in the scheduled set only
+        let (inflight, scheduled) = inflight_and_scheduled(&mut storage, &worker_id, &job_id).await;
+        assert!(!inflight);
+        let scheduled = scheduled.expect("job was not scheduled");
+        assert!(scheduled >= Utc::now().timestamp() + 59);
+        let cancelled = storage
+            .fetch_cancelled(worker_id.clone())
+            .await
+            .expect("failed to fetch cancelled jobs");
+        assert!(cancelled.is_empty());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_save_and_fetch_result() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        storage
+            .save_result(
+                job_id.clone(),
+                serde_json::json!("https://reports/1.pdf"),
+                Duration::from_secs(60),
+            )
+            .await
+            .expect("failed to save result");
+        let result = storage
+            .fetch_result(job_id.clone())
+            .await
+            .expect("failed to fetch result");
+        assert_eq!(result, Some(serde_json::json!("https://reports/1.pdf")));
+
+        // A result past its retention is no longer returned
+        storage
+            .save_result(job_id.clone(), serde_json::json!(null), Duration::ZERO)
+            .await
+            .expect("failed to save result");
+        let result = storage
+            .fetch_result(job_id)
+            .await
+            .expect("failed to fetch result");
+        assert!(result.is_none());
+
+        cleanup(storage, worker_id).await;
+    }
+
     #[tokio::test]
     async fn test_kill_job() {
         let mut storage = setup().await;
@@ -856,6 +1854,156 @@ mod tests {
         cleanup(storage, worker_id).await;
     }
 
+    #[tokio::test]
+    async fn test_dead_letter_requeues_and_purges_killed_jobs() {
+        let mut storage = setup().await;
+        let worker_id = register_worker(&mut storage).await;
+
+        push_email(&mut storage, example_email()).await;
+        let mut job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+        job.context_mut()
+            .set_last_error("payment gateway down".to_string());
+        storage
+            .update_by_id(job_id.clone(), &job)
+            .await
+            .expect("failed to update job");
+        storage
+            .kill(worker_id.clone(), job_id.clone())
+            .await
+            .expect("failed to kill job");
+
+        let dead = storage
+            .list_dead(1)
+            .await
+            .expect("failed to list dead jobs");
+        assert_eq!(dead.len(), 1);
+
+        let requeued = storage
+            .requeue_dead_matching(&DeadLetterFilter::new().with_error_containing("invalid card"))
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 0);
+        let requeued = storage
+            .requeue_dead_matching(&DeadLetterFilter::new().with_error_containing("gateway"))
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 1);
+        let job = get_job(&mut storage, job_id.clone()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 0);
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), job_id);
+        storage
+            .kill(worker_id.clone(), job_id.clone())
+            .await
+            .expect("failed to kill job");
+        let purged = storage
+            .purge_dead(Duration::from_secs(3600))
+            .await
+            .expect("failed to purge dead jobs");
+        assert_eq!(purged, 0);
+        let purged = storage
+            .purge_dead(Duration::ZERO)
+            .await
+            .expect("failed to purge dead jobs");
+        assert_eq!(purged, 1);
+        assert!(storage
+            .fetch_by_id(job_id)
+            .await
+            .expect("failed to fetch job by id")
+            .is_none());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_append_logs_keeps_last_lines() {
+        let mut storage = setup().await;
+        let job_id = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job")
+            .to_string();
+
+        let lines: Vec<JobLogLine> = ["connecting", "sending", "mailbox full"]
+            .into_iter()
+            .map(|message| JobLogLine {
+                level: "INFO".to_string(),
+                message: message.to_string(),
+                logged_at: Utc::now(),
+            })
+            .collect();
+        storage
+            .append_logs(job_id.clone(), &lines[..2], 2)
+            .await
+            .expect("failed to append logs");
+        storage
+            .append_logs(job_id.clone(), &lines[2..], 2)
+            .await
+            .expect("failed to append logs");
+
+        let logs = storage
+            .job_logs(job_id)
+            .await
+            .expect("failed to fetch logs");
+        assert_eq!(logs, lines[1..].to_vec());
+
+        cleanup(storage, String::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_and_running_jobs() {
+        let mut storage = setup().await;
+        let parent = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+
+        storage
+            .cancel(child.to_string())
+            .await
+            .expect("failed to cancel job");
+        let job = get_job(&mut storage, child.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Cancelled);
+        assert!(matches!(
+            storage.cancel(child.to_string()).await,
+            Err(StorageError::NotFound)
+        ));
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), parent.to_string());
+        storage
+            .cancel(parent.to_string())
+            .await
+            .expect("failed to cancel job");
+        let cancelled = storage
+            .fetch_cancelled(worker_id.clone())
+            .await
+            .expect("failed to fetch cancelled jobs");
+        assert_eq!(cancelled, vec![parent.to_string()]);
+
+        storage
+            .abort(worker_id.clone(), parent.to_string())
+            .await
+            .expect("failed to abort job");
+        let job = get_job(&mut storage, parent.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Cancelled);
+        assert!(storage
+            .fetch_cancelled(worker_id.clone())
+            .await
+            .expect("failed to fetch cancelled jobs")
+            .is_empty());
+
+        cleanup(storage, worker_id).await;
+    }
+
     #[tokio::test]
     async fn test_heartbeat_renqueueorphaned_pulse_last_seen_6min() {
         let mut storage = setup().await;
@@ -867,7 +2015,10 @@ mod tests {
 
         let job = consume_one(&mut storage, worker_id.clone()).await;
         let result = storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .expect("failed to heartbeat");
         assert!(result);
@@ -895,7 +2046,10 @@ mod tests {
 
         let job = consume_one(&mut storage, worker_id.clone()).await;
         let result = storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .expect("failed to heartbeat");
         assert!(result);
@@ -908,4 +2062,232 @@ mod tests {
 
         cleanup(storage, worker_id).await;
     }
+
+    #[tokio::test]
+    async fn test_child_job_runs_after_parents_are_done() {
+        let mut storage = setup().await;
+        let first = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let second = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(
+                example_email(),
+                PushOptions::new().with_parents([first, second]),
+            )
+            .await
+            .expect("failed to push a job");
+        let blocked = storage
+            .list_jobs(&JobState::Blocked, 1)
+            .await
+            .expect("failed to list jobs");
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].id(), child.to_string());
+
+        let worker_id = register_worker(&mut storage).await;
+        for parent in [first, second] {
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            assert_eq!(job.context().id(), parent.to_string());
+            storage
+                .ack(worker_id.clone(), job.context().id())
+                .await
+                .expect("failed to ack job");
+        }
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), child.to_string());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_unique_job_with_dependents_is_not_replaced() {
+        let mut storage = setup().await;
+        let key = Uuid::new_v4().to_string();
+        let unique = |policy| PushOptions::new().with_unique_key(key.clone(), policy);
+
+        let parent = storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+        storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+
+        // Replacing the parent would leave its child blocked forever
+        match storage
+            .push_with(example_email(), unique(UniquePolicy::Replace))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, parent),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+        assert!(storage
+            .fetch_by_id(parent.to_string())
+            .await
+            .expect("failed to fetch job")
+            .is_some());
+
+        cleanup(storage, String::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_child_job_is_killed_with_parent() {
+        let mut storage = setup().await;
+        let parent = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+        let grandchild = storage
+            .push_with(example_email(), PushOptions::new().with_parents([child]))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+
+        let killed: Vec<String> = storage
+            .list_jobs(&JobState::Killed, 1)
+            .await
+            .expect("failed to list jobs")
+            .iter()
+            .map(|job| job.id())
+            .collect();
+        for id in [parent, child, grandchild] {
+            assert!(killed.contains(&id.to_string()));
+        }
+        assert!(matches!(
+            storage
+                .push_with(
+                    example_email(),
+                    PushOptions::new().with_parents([JobId::new()])
+                )
+                .await,
+            Err(StorageError::NotFound)
+        ));
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_pushes_success_callback() {
+        let mut storage = setup().await;
+        let callbacks = BatchCallbacks::new()
+            .with_on_success(example_email())
+            .with_on_failure(example_email());
+        let batch_id = storage
+            .open_batch(callbacks)
+            .await
+            .expect("failed to open batch");
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let id = storage
+                .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+                .await
+                .expect("failed to push a job");
+            ids.push(id.to_string());
+        }
+        storage
+            .close_batch(&batch_id)
+            .await
+            .expect("failed to close batch");
+        assert!(matches!(
+            storage
+                .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+                .await,
+            Err(StorageError::NotFound)
+        ));
+
+        let worker_id = register_worker(&mut storage).await;
+        for done in 1..=2 {
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            assert!(ids.contains(&job.context().id()));
+            storage
+                .ack(worker_id.clone(), job.context().id())
+                .await
+                .expect("failed to ack job");
+            let progress = storage
+                .batch_progress(&batch_id)
+                .await
+                .expect("failed to fetch progress")
+                .expect("no batch found");
+            assert_eq!(progress.total, 2);
+            assert_eq!(progress.done, done);
+            assert_eq!(progress.pending(), 2 - done);
+        }
+
+        // Only the success callback is left
+        let blocked = storage
+            .list_jobs(&JobState::Blocked, 1)
+            .await
+            .expect("failed to list jobs");
+        assert!(blocked.is_empty());
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert!(!ids.contains(&job.context().id()));
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_pushes_failure_callback() {
+        let mut storage = setup().await;
+        let callbacks = BatchCallbacks::new().with_on_success(example_email());
+        let batch_id = storage
+            .open_batch(callbacks)
+            .await
+            .expect("failed to open batch");
+        storage
+            .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+        let progress = storage
+            .batch_progress(&batch_id)
+            .await
+            .expect("failed to fetch progress")
+            .expect("no batch found");
+        assert_eq!(progress.failed, 1);
+        assert!(!progress.is_finished());
+
+        storage
+            .close_batch(&batch_id)
+            .await
+            .expect("failed to close batch");
+        let progress = storage
+            .batch_progress(&batch_id)
+            .await
+            .expect("failed to fetch progress")
+            .expect("no batch found");
+        assert!(progress.is_finished());
+
+        // The success callback is dropped and there is no failure callback
+        for state in [JobState::Blocked, JobState::Pending] {
+            let jobs = storage
+                .list_jobs(&state, 1)
+                .await
+                .expect("failed to list jobs");
+            assert!(jobs.is_empty());
+        }
+
+        cleanup(storage, worker_id).await;
+    }
 }
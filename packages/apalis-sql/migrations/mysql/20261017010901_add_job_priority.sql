@@ -0,0 +1,3 @@
+ALTER TABLE jobs ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX PIdx ON jobs(priority DESC, run_at ASC);
@@ -0,0 +1,9 @@
+ALTER TABLE jobs ADD COLUMN unique_key varchar(255) DEFAULT NULL;
+
+ALTER TABLE jobs ADD COLUMN unique_until datetime DEFAULT NULL;
+
+-- MySQL has no partial indexes, the key only counts until the job is done or killed
+ALTER TABLE jobs ADD COLUMN unique_slot varchar(255)
+    AS (IF(status IN ('Done', 'Killed'), NULL, unique_key)) STORED;
+
+CREATE UNIQUE INDEX UIdx ON jobs(job_type, unique_slot);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS job_dependencies (
+    job_id varchar(36) NOT NULL,
+    parent_id varchar(36) NOT NULL,
+    PRIMARY KEY (job_id, parent_id),
+    FOREIGN KEY (job_id) REFERENCES jobs(id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+
+CREATE INDEX DPIdx ON job_dependencies(parent_id);
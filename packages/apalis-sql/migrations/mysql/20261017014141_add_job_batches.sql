@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS batches (
+    id varchar(36) NOT NULL,
+    job_type varchar(200) NOT NULL,
+    total BIGINT NOT NULL DEFAULT 0,
+    done BIGINT NOT NULL DEFAULT 0,
+    failed BIGINT NOT NULL DEFAULT 0,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    on_success varchar(36) DEFAULT NULL,
+    on_failure varchar(36) DEFAULT NULL,
+    finished_at datetime DEFAULT NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+
+ALTER TABLE jobs ADD COLUMN batch_id varchar(36) DEFAULT NULL;
+
+CREATE INDEX BIdx ON jobs(batch_id);
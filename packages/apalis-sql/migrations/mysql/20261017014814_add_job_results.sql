@@ -0,0 +1,5 @@
+ALTER TABLE jobs ADD COLUMN result JSON DEFAULT NULL;
+
+ALTER TABLE jobs ADD COLUMN result_expires_at datetime DEFAULT NULL;
+
+CREATE INDEX RIdx ON jobs(result_expires_at);
@@ -0,0 +1 @@
+ALTER TABLE jobs ADD COLUMN last_failure JSON DEFAULT NULL;
@@ -0,0 +1,5 @@
+ALTER TABLE jobs ADD COLUMN cancel_requested BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Cancelled jobs release their unique key, like done and killed jobs
+ALTER TABLE jobs MODIFY COLUMN unique_slot varchar(255)
+    AS (IF(status IN ('Done', 'Killed', 'Cancelled'), NULL, unique_key)) STORED;
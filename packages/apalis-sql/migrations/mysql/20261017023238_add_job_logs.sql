@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS job_logs (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    job_id varchar(36) NOT NULL,
+    level varchar(10) NOT NULL,
+    message TEXT NOT NULL,
+    logged_at datetime NOT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (job_id) REFERENCES jobs(id) ON DELETE CASCADE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+
+CREATE INDEX JLIdx ON job_logs(job_id);
@@ -0,0 +1 @@
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS timeout_ms BIGINT;
@@ -0,0 +1,32 @@
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS priority INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS PIdx ON apalis.jobs(priority DESC, run_at ASC);
+
+CREATE OR REPLACE FUNCTION apalis.get_jobs(
+    worker_id TEXT,
+    v_job_type TEXT,
+    v_job_count integer DEFAULT 5 :: integer
+) RETURNS setof apalis.jobs AS $$
+BEGIN
+    RETURN QUERY
+        WITH claimed AS (
+            UPDATE apalis.jobs
+                SET
+                    status = 'Running',
+                    lock_by = worker_id,
+                    lock_at = now()
+                WHERE id IN (
+                    SELECT   id
+                    FROM     apalis.jobs
+                    WHERE    status = 'Pending'
+                    AND      run_at < now()
+                    AND      job_type = v_job_type
+                    ORDER BY priority DESC, run_at ASC
+                    LIMIT    v_job_count
+                    FOR UPDATE SKIP LOCKED
+                )
+            RETURNING *
+        )
+        SELECT * FROM claimed ORDER BY priority DESC, run_at ASC;
+END;
+$$ LANGUAGE plpgsql volatile;
@@ -0,0 +1,6 @@
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS unique_key TEXT;
+
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS unique_until timestamptz;
+
+CREATE UNIQUE INDEX IF NOT EXISTS UIdx ON apalis.jobs(job_type, unique_key)
+    WHERE unique_key IS NOT NULL AND status NOT IN ('Done', 'Killed');
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS apalis.job_dependencies (
+    job_id TEXT NOT NULL,
+    parent_id TEXT NOT NULL,
+    PRIMARY KEY (job_id, parent_id),
+    CONSTRAINT fk_dependency_job_id FOREIGN KEY(job_id) REFERENCES apalis.jobs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS DPIdx ON apalis.job_dependencies(parent_id);
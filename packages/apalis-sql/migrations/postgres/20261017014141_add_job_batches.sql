@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS apalis.batches (
+    id TEXT NOT NULL PRIMARY KEY,
+    job_type TEXT NOT NULL,
+    total BIGINT NOT NULL DEFAULT 0,
+    done BIGINT NOT NULL DEFAULT 0,
+    failed BIGINT NOT NULL DEFAULT 0,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    on_success TEXT,
+    on_failure TEXT,
+    finished_at timestamptz
+);
+
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS batch_id TEXT;
+
+CREATE INDEX IF NOT EXISTS BIdx ON apalis.jobs(batch_id);
@@ -0,0 +1,5 @@
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS result JSONB;
+
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS result_expires_at timestamptz;
+
+CREATE INDEX IF NOT EXISTS RIdx ON apalis.jobs(result_expires_at);
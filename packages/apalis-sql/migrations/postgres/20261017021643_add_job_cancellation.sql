@@ -0,0 +1,7 @@
+ALTER TABLE apalis.jobs ADD COLUMN IF NOT EXISTS cancel_requested BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Cancelled jobs release their unique key, like done and killed jobs
+DROP INDEX IF EXISTS apalis.UIdx;
+
+CREATE UNIQUE INDEX IF NOT EXISTS UIdx ON apalis.jobs(job_type, unique_key)
+    WHERE unique_key IS NOT NULL AND status NOT IN ('Done', 'Killed', 'Cancelled');
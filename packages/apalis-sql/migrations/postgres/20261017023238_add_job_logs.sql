@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS apalis.job_logs (
+    id BIGSERIAL PRIMARY KEY,
+    job_id TEXT NOT NULL,
+    level TEXT NOT NULL,
+    message TEXT NOT NULL,
+    logged_at timestamptz NOT NULL,
+    CONSTRAINT fk_job_logs_job_id FOREIGN KEY(job_id) REFERENCES apalis.jobs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS JLIdx ON apalis.job_logs(job_id);
@@ -0,0 +1,3 @@
+ALTER TABLE Jobs ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS PIdx ON Jobs(priority DESC, run_at ASC);
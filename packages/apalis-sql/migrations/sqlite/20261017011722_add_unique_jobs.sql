@@ -0,0 +1,6 @@
+ALTER TABLE Jobs ADD COLUMN unique_key TEXT;
+
+ALTER TABLE Jobs ADD COLUMN unique_until INTEGER;
+
+CREATE UNIQUE INDEX IF NOT EXISTS UIdx ON Jobs(job_type, unique_key)
+    WHERE unique_key IS NOT NULL AND status NOT IN ('Done', 'Killed');
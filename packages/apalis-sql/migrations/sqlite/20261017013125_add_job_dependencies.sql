@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS JobDependencies (
+    job_id TEXT NOT NULL,
+    parent_id TEXT NOT NULL,
+    PRIMARY KEY (job_id, parent_id),
+    FOREIGN KEY(job_id) REFERENCES Jobs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS DPIdx ON JobDependencies(parent_id);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS Batches (
+    id TEXT NOT NULL UNIQUE,
+    job_type TEXT NOT NULL,
+    total INTEGER NOT NULL DEFAULT 0,
+    done INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    closed INTEGER NOT NULL DEFAULT 0,
+    on_success TEXT,
+    on_failure TEXT,
+    finished_at INTEGER
+);
+
+ALTER TABLE Jobs ADD COLUMN batch_id TEXT;
+
+CREATE INDEX IF NOT EXISTS BIdx ON Jobs(batch_id);
@@ -0,0 +1,5 @@
+ALTER TABLE Jobs ADD COLUMN result TEXT;
+
+ALTER TABLE Jobs ADD COLUMN result_expires_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS RIdx ON Jobs(result_expires_at);
@@ -0,0 +1,7 @@
+ALTER TABLE Jobs ADD COLUMN cancel_requested INTEGER NOT NULL DEFAULT 0;
+
+-- Cancelled jobs release their unique key, like done and killed jobs
+DROP INDEX IF EXISTS UIdx;
+
+CREATE UNIQUE INDEX IF NOT EXISTS UIdx ON Jobs(job_type, unique_key)
+    WHERE unique_key IS NOT NULL AND status NOT IN ('Done', 'Killed', 'Cancelled');
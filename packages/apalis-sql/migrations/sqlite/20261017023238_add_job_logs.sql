@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS JobLogs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    job_id TEXT NOT NULL,
+    level TEXT NOT NULL,
+    message TEXT NOT NULL,
+    logged_at INTEGER NOT NULL,
+    FOREIGN KEY(job_id) REFERENCES Jobs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS JLIdx ON JobLogs(job_id);
@@ -3,6 +3,7 @@ use chrono::{DateTime, Utc};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use sqlx::Row;
+use std::time::Duration;
 
 /// Wrapper for [JobRequest]
 pub(crate) struct SqlJobRequest<T>(JobRequest<T>);
@@ -40,6 +41,12 @@ impl<'r, T: DeserializeOwned> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Sql
         let max_attempts = row.try_get("max_attempts").unwrap_or(25);
         context.set_max_attempts(max_attempts);
 
+        let timeout_ms: Option<i64> = row.try_get("timeout_ms").unwrap_or_default();
+        context.set_timeout(timeout_ms.map(|ms| Duration::from_millis(ms as u64)));
+
+        let priority = row.try_get("priority").unwrap_or(0);
+        context.set_priority(priority);
+
         let done_at: Option<DateTime<Utc>> = row.try_get("done_at").unwrap_or_default();
         context.set_done_at(done_at);
 
@@ -49,6 +56,12 @@ impl<'r, T: DeserializeOwned> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Sql
         let last_error = row.try_get("last_error").unwrap_or_default();
         context.set_last_error(last_error);
 
+        let last_failure: Option<Value> = row.try_get("last_failure").unwrap_or_default();
+        context.set_last_failure(last_failure.and_then(|f| serde_json::from_value(f).ok()));
+
+        let progress: Option<Value> = row.try_get("progress").unwrap_or_default();
+        context.set_progress(progress.and_then(|p| serde_json::from_value(p).ok()));
+
         let status: String = row.try_get("status")?;
         context.set_status(status.parse().unwrap());
 
@@ -79,6 +92,12 @@ impl<'r, T: DeserializeOwned> sqlx::FromRow<'r, sqlx::postgres::PgRow> for SqlJo
         let max_attempts = row.try_get("max_attempts").unwrap_or(25);
         context.set_max_attempts(max_attempts);
 
+        let timeout_ms: Option<i64> = row.try_get("timeout_ms").unwrap_or_default();
+        context.set_timeout(timeout_ms.map(|ms| Duration::from_millis(ms as u64)));
+
+        let priority = row.try_get("priority").unwrap_or(0);
+        context.set_priority(priority);
+
         let done_at: Option<DateTime<Utc>> = row.try_get("done_at").unwrap_or_default();
         context.set_done_at(done_at);
 
@@ -88,6 +107,12 @@ impl<'r, T: DeserializeOwned> sqlx::FromRow<'r, sqlx::postgres::PgRow> for SqlJo
         let last_error = row.try_get("last_error").unwrap_or_default();
         context.set_last_error(last_error);
 
+        let last_failure: Option<Value> = row.try_get("last_failure").unwrap_or_default();
+        context.set_last_failure(last_failure.and_then(|f| serde_json::from_value(f).ok()));
+
+        let progress: Option<Value> = row.try_get("progress").unwrap_or_default();
+        context.set_progress(progress.and_then(|p| serde_json::from_value(p).ok()));
+
         let status: String = row.try_get("status")?;
         context.set_status(status.parse().unwrap());
 
@@ -118,6 +143,12 @@ impl<'r, T: DeserializeOwned> sqlx::FromRow<'r, sqlx::mysql::MySqlRow> for SqlJo
         let max_attempts = row.try_get("max_attempts").unwrap_or(25);
         context.set_max_attempts(max_attempts);
 
+        let timeout_ms: Option<i64> = row.try_get("timeout_ms").unwrap_or_default();
+        context.set_timeout(timeout_ms.map(|ms| Duration::from_millis(ms as u64)));
+
+        let priority = row.try_get("priority").unwrap_or(0);
+        context.set_priority(priority);
+
         let done_at: Option<DateTime<Utc>> = row.try_get("done_at").unwrap_or_default();
         context.set_done_at(done_at);
 
@@ -127,6 +158,12 @@ impl<'r, T: DeserializeOwned> sqlx::FromRow<'r, sqlx::mysql::MySqlRow> for SqlJo
         let last_error = row.try_get("last_error").unwrap_or_default();
         context.set_last_error(last_error);
 
+        let last_failure: Option<Value> = row.try_get("last_failure").unwrap_or_default();
+        context.set_last_failure(last_failure.and_then(|f| serde_json::from_value(f).ok()));
+
+        let progress: Option<Value> = row.try_get("progress").unwrap_or_default();
+        context.set_progress(progress.and_then(|p| serde_json::from_value(p).ok()));
+
         let status: String = row.try_get("status")?;
         context.set_status(status.parse().unwrap());
 
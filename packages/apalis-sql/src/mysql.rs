@@ -1,16 +1,19 @@
+use apalis_core::context::{JobLogLine, JobProgress};
 use apalis_core::error::{JobError, JobStreamError};
-use apalis_core::job::{Counts, Job, JobStreamExt, JobStreamResult, JobStreamWorker};
+use apalis_core::job::{Counts, Job, JobId, JobStreamExt, JobStreamResult, JobStreamWorker};
 use apalis_core::request::{JobRequest, JobState};
 use apalis_core::storage::StorageError;
 use apalis_core::storage::StorageWorkerPulse;
-use apalis_core::storage::{Storage, StorageResult};
+use apalis_core::storage::{
+    BatchCallbacks, BatchId, BatchProgress, DeadLetter, DeadLetterFilter, PushOptions, Storage,
+    StorageResult, UniquePolicy,
+};
 use async_stream::try_stream;
 use chrono::{DateTime, Utc};
 use futures::Stream;
 use serde::{de::DeserializeOwned, Serialize};
 
-use sqlx::types::Uuid;
-use sqlx::{MySql, MySqlPool, Pool, Row};
+use sqlx::{MySql, MySqlPool, Pool, Row, Transaction};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ops::Sub;
@@ -63,6 +66,7 @@ impl<T: DeserializeOwned + Send + Unpin + Job> MysqlStorage<T> {
         &self,
         worker_id: String,
         interval: Duration,
+        buffer_size: usize,
     ) -> impl Stream<Item = Result<Option<JobRequest<T>>, JobStreamError>> {
         let pool = self.pool.clone();
         let mut interval = tokio::time::interval(interval);
@@ -76,43 +80,44 @@ impl<T: DeserializeOwned + Send + Unpin + Job> MysqlStorage<T> {
                 .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
 
                 let job_type = T::NAME;
-                let fetch_query = "SELECT * FROM jobs
-                    WHERE status = 'Pending' AND run_at <= NOW() AND job_type = ? ORDER BY run_at ASC LIMIT 1 FOR UPDATE";
-                let job: Option<SqlJobRequest<T>> = sqlx::query_as(fetch_query)
+                let fetch_query = "SELECT id FROM jobs
+                    WHERE status = 'Pending' AND run_at <= NOW() AND job_type = ? ORDER BY priority DESC, run_at ASC LIMIT ? FOR UPDATE SKIP LOCKED";
+                let job_ids: Vec<(String,)> = sqlx::query_as(fetch_query)
                     .bind(job_type)
-                    .fetch_optional(&mut tx)
+                    .bind(u64::try_from(buffer_size).unwrap_or(u64::MAX))
+                    .fetch_all(&mut tx)
                     .await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-                yield match job.build_job_request() {
-                        None => {
-                            tx.commit()
-                                .await
-                                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-                                None
-                        },
-                        Some(job) => {
-
-                            let job_id = job.id();
-                            let update_query = "UPDATE jobs SET status = 'Running', lock_by = ?, lock_at = NOW() WHERE id = ? AND status = 'Pending' AND lock_by IS NULL;";
-                            sqlx::query(update_query)
-                                .bind(worker_id.clone())
-                                .bind(job_id.clone())
-                                .execute(&mut tx)
-                                .await
-                                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-                            let job: Option<SqlJobRequest<T>> = sqlx::query_as("Select * from jobs where id = ?")
-                                .bind(job_id.clone())
-                                .fetch_optional(&mut tx)
-                                .await
-                                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-
-                            tx.commit()
-                                .await
-                                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-
-                            job.build_job_request()
-                        }
+                let mut jobs: Vec<SqlJobRequest<T>> = Vec::new();
+                if !job_ids.is_empty() {
+                    let ids = vec!["?"; job_ids.len()].join(", ");
+                    let update_query = format!("UPDATE jobs SET status = 'Running', lock_by = ?, lock_at = NOW() WHERE id IN ({ids})");
+                    let mut update = sqlx::query(&update_query).bind(worker_id.clone());
+                    for (id,) in &job_ids {
+                        update = update.bind(id);
                     }
-                    // yield fetch_next(pool.clone(), worker_id.clone(), job.as_job_request()).await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?
+                    update
+                        .execute(&mut tx)
+                        .await
+                        .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
+                    let select_query = format!("SELECT * FROM jobs WHERE id IN ({ids}) ORDER BY priority DESC, run_at ASC");
+                    let mut select = sqlx::query_as(&select_query);
+                    for (id,) in &job_ids {
+                        select = select.bind(id);
+                    }
+                    jobs = select
+                        .fetch_all(&mut tx)
+                        .await
+                        .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
+                }
+                tx.commit()
+                    .await
+                    .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
+                if jobs.is_empty() {
+                    yield None;
+                }
+                for job in jobs {
+                    yield Some(job.into());
+                }
             }
         }
     }
@@ -145,6 +150,225 @@ impl<T: DeserializeOwned + Send + Unpin + Job> MysqlStorage<T> {
     }
 }
 
+/// Makes sure `key` is free for a new job of `job_type`, following `policy`.
+/// Returns the job holding the key when the push should be skipped instead
+async fn acquire_unique_key(
+    tx: &mut Transaction<'_, MySql>,
+    job_type: &str,
+    key: &str,
+    policy: UniquePolicy,
+) -> StorageResult<Option<JobId>> {
+    sqlx::query(
+        "UPDATE jobs SET unique_key = NULL WHERE job_type = ? AND unique_key = ? AND unique_until <= NOW()",
+    )
+    .bind(job_type)
+    .bind(key)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| StorageError::Database(Box::from(e)))?;
+    // Locks the key in the unique index, so racing pushes wait for this transaction
+    let holder: Option<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, status, batch_id FROM jobs WHERE job_type = ? AND unique_slot = ? FOR UPDATE",
+    )
+    .bind(job_type)
+    .bind(key)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let (holder, status, batch_id) = match holder {
+        Some(holder) => holder,
+        None => return Ok(None),
+    };
+    let holder_id = holder
+        .parse()
+        .map_err(|e| StorageError::Database(Box::new(e)))?;
+    let (dependents,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM job_dependencies WHERE parent_id = ?")
+            .bind(&holder)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    match policy {
+        UniquePolicy::Ignore => Ok(Some(holder_id)),
+        // Jobs of a batch are counted by it, and jobs waiting on the holder would never run,
+        // so those are never replaced
+        UniquePolicy::Replace
+            if status != JobState::Running.as_ref() && batch_id.is_none() && dependents == 0 =>
+        {
+            sqlx::query("DELETE FROM jobs WHERE id = ?")
+                .bind(holder)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            Ok(None)
+        }
+        _ => Err(StorageError::Duplicate(holder_id)),
+    }
+}
+
+/// Records that `job_id` depends on `parents` and blocks or kills it accordingly,
+/// returning the state of the job.
+/// Must run after the job was inserted, within the same transaction
+async fn add_dependencies(
+    tx: &mut Transaction<'_, MySql>,
+    job_id: &JobId,
+    parents: &[JobId],
+) -> StorageResult<JobState> {
+    let job_id = job_id.to_string();
+    let mut state = JobState::Pending;
+    for parent in parents {
+        let parent = parent.to_string();
+        sqlx::query("INSERT IGNORE INTO job_dependencies (job_id, parent_id) VALUES (?, ?)")
+            .bind(&job_id)
+            .bind(&parent)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Holding the parent row makes a concurrent ack wait until the dependency is visible
+        let status: Option<(String,)> =
+            sqlx::query_as("SELECT status FROM jobs WHERE id = ? FOR SHARE")
+                .bind(parent)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        match status {
+            None => return Err(StorageError::NotFound),
+            Some((status,)) if status == JobState::Killed.as_ref() => state = JobState::Killed,
+            Some((status,))
+                if status == JobState::Cancelled.as_ref() && state != JobState::Killed =>
+            {
+                state = JobState::Cancelled
+            }
+            Some((status,)) if status != JobState::Done.as_ref() && state == JobState::Pending => {
+                state = JobState::Blocked
+            }
+            _ => {}
+        }
+    }
+    let query = match state {
+        JobState::Pending => return Ok(state),
+        JobState::Killed => "UPDATE jobs SET status = 'Killed', done_at = NOW(), last_error = 'A parent job was killed' WHERE id = ?",
+        JobState::Cancelled => "UPDATE jobs SET status = 'Cancelled', done_at = NOW(), last_error = 'A parent job was cancelled' WHERE id = ?",
+        _ => "UPDATE jobs SET status = 'Blocked' WHERE id = ?",
+    };
+    sqlx::query(query)
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    Ok(state)
+}
+
+/// Gives every job waiting on `job_id`, directly or not, the `status` it finished with since
+/// they can never run, then counts them all as failed in their batches along with `job_id`
+async fn finish_dependents(
+    tx: &mut Transaction<'_, MySql>,
+    job_id: &str,
+    status: &JobState,
+) -> StorageResult<()> {
+    let query = "WITH RECURSIVE descendants(id) AS (
+            SELECT job_id FROM job_dependencies WHERE parent_id = ?
+            UNION SELECT d.job_id FROM job_dependencies d
+                INNER JOIN descendants ON d.parent_id = descendants.id
+        )
+        SELECT id, batch_id FROM jobs
+        WHERE status = 'Blocked' AND id IN (SELECT id FROM descendants) FOR UPDATE";
+    let descendants: Vec<(String, Option<String>)> = sqlx::query_as(query)
+        .bind(job_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    if !descendants.is_empty() {
+        let ids = vec!["?"; descendants.len()].join(", ");
+        let update_query = format!(
+            "UPDATE jobs SET status = ?, done_at = NOW(), last_error = ? WHERE id IN ({ids})"
+        );
+        let mut update = sqlx::query(&update_query)
+            .bind(status.as_ref())
+            .bind(format!(
+                "A parent job was {}",
+                status.as_ref().to_lowercase()
+            ));
+        for (id, _) in &descendants {
+            update = update.bind(id);
+        }
+        update
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    let (batch_id,): (Option<String>,) = sqlx::query_as("SELECT batch_id FROM jobs WHERE id = ?")
+        .bind(job_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let mut failed: HashMap<String, i64> = HashMap::new();
+    for batch_id in std::iter::once(batch_id)
+        .chain(descendants.into_iter().map(|(_, batch_id)| batch_id))
+        .flatten()
+    {
+        *failed.entry(batch_id).or_default() += 1;
+    }
+    for (batch_id, failed) in failed {
+        count_in_batch(tx, &batch_id, 0, failed).await?;
+    }
+    Ok(())
+}
+
+/// Counts finished jobs towards `batch_id`
+async fn count_in_batch(
+    tx: &mut Transaction<'_, MySql>,
+    batch_id: &str,
+    done: i64,
+    failed: i64,
+) -> StorageResult<()> {
+    sqlx::query("UPDATE batches SET done = done + ?, failed = failed + ? WHERE id = ?")
+        .bind(done)
+        .bind(failed)
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    finish_batch(tx, batch_id).await
+}
+
+/// Releases the callback of `batch_id` if it is closed and every job in it has finished.
+/// The callback that does not apply is deleted
+async fn finish_batch(tx: &mut Transaction<'_, MySql>, batch_id: &str) -> StorageResult<()> {
+    let query = "SELECT on_success, on_failure, failed FROM batches
+        WHERE id = ? AND closed AND finished_at IS NULL AND done + failed >= total FOR UPDATE";
+    let batch: Option<(Option<String>, Option<String>, i64)> = sqlx::query_as(query)
+        .bind(batch_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let (callback, discarded) = match batch {
+        Some((on_success, on_failure, 0)) => (on_success, on_failure),
+        Some((on_success, on_failure, _)) => (on_failure, on_success),
+        None => return Ok(()),
+    };
+    sqlx::query("UPDATE batches SET finished_at = NOW() WHERE id = ?")
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    if let Some(callback) = callback {
+        sqlx::query("UPDATE jobs SET status = 'Pending', run_at = NOW() WHERE id = ?")
+            .bind(callback)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    if let Some(discarded) = discarded {
+        sqlx::query("DELETE FROM jobs WHERE id = ?")
+            .bind(discarded)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    Ok(())
+}
+
 #[async_trait::async_trait]
 impl<T> Storage for MysqlStorage<T>
 where
@@ -152,53 +376,73 @@ where
 {
     type Output = T;
 
-    async fn push(&mut self, job: Self::Output) -> StorageResult<()> {
-        let id = Uuid::new_v4();
-        let query =
-            "INSERT INTO jobs VALUES (?, ?, ?, 'Pending', 0, 25, now(), NULL, NULL, NULL, NULL)";
+    async fn push_with(&mut self, job: Self::Output, options: PushOptions) -> StorageResult<JobId> {
+        let id = options.id().copied().unwrap_or_default();
+        let run_at = options.run_at().copied().unwrap_or_else(Utc::now);
+        let query = "INSERT INTO jobs (job, id, job_type, status, attempts, max_attempts, run_at, timeout_ms, priority, unique_key, unique_until, batch_id) VALUES (?, ?, ?, 'Pending', 0, ?, ?, ?, ?, ?, ?, ?)";
         let pool = self.pool.clone();
 
         let job = serde_json::to_string(&job)?;
-        let mut pool = pool
-            .acquire()
+        let mut tx = pool
+            .begin()
             .await
             .map_err(|e| StorageError::Connection(Box::from(e)))?;
         let job_type = T::NAME;
+        if let Some(key) = options.unique_key() {
+            if let Some(holder) =
+                acquire_unique_key(&mut tx, job_type, key, options.unique_policy()).await?
+            {
+                return Ok(holder);
+            }
+        }
+        let unique_until = match options.unique_ttl() {
+            Some(ttl) => Some(
+                Utc::now()
+                    + chrono::Duration::from_std(ttl)
+                        .map_err(|e| StorageError::Database(Box::from(e)))?,
+            ),
+            None => None,
+        };
         sqlx::query(query)
             .bind(job)
             .bind(id.to_string())
             .bind(job_type.to_string())
-            .execute(&mut pool)
+            .bind(options.max_attempts())
+            .bind(run_at)
+            .bind(options.timeout().map(|timeout| timeout.as_millis() as i64))
+            .bind(options.priority())
+            .bind(options.unique_key())
+            .bind(unique_until)
+            .bind(options.batch().map(ToString::to_string))
+            .execute(&mut tx)
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        Ok(())
-    }
-
-    async fn schedule(
-        &mut self,
-        job: Self::Output,
-        on: chrono::DateTime<Utc>,
-    ) -> StorageResult<()> {
-        let query =
-            "INSERT INTO jobs VALUES (?, ?, ?, 'Pending', 0, 25, ?, NULL, NULL, NULL, NULL)";
-        let pool = self.pool.clone();
-        let id = Uuid::new_v4();
-
-        let job = serde_json::to_string(&job)?;
-        let mut pool = pool
-            .acquire()
-            .await
-            .map_err(|e| StorageError::Connection(Box::from(e)))?;
-        let job_type = T::NAME;
-        sqlx::query(query)
-            .bind(job)
-            .bind(id.to_string())
-            .bind(job_type)
-            .bind(on)
-            .execute(&mut pool)
+        let state = match options.parents() {
+            [] => JobState::Pending,
+            parents => add_dependencies(&mut tx, &id, parents).await?,
+        };
+        if let Some(batch_id) = options.batch() {
+            let query = "UPDATE batches SET total = total + 1, failed = failed + ?
+                WHERE id = ? AND job_type = ? AND NOT closed";
+            let added = sqlx::query(query)
+                .bind(i64::from(matches!(
+                    state,
+                    JobState::Killed | JobState::Cancelled
+                )))
+                .bind(batch_id.to_string())
+                .bind(job_type)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+            if added == 0 {
+                return Err(StorageError::NotFound);
+            }
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        Ok(())
+        Ok(id)
     }
 
     async fn fetch_by_id(&self, job_id: String) -> StorageResult<Option<JobRequest<Self::Output>>> {
@@ -226,8 +470,13 @@ where
                 // Idealy jobs are queue via run_at. So this is not necessary
                 Ok(true)
             }
-            // Worker not seen in 5 minutes yet has running jobs
-            StorageWorkerPulse::RenqueueOrpharned { count } => {
+            // Worker not seen within `timeout_worker` yet has running jobs
+            StorageWorkerPulse::RenqueueOrpharned {
+                count,
+                timeout_worker,
+            } => {
+                let timeout_worker = chrono::Duration::from_std(timeout_worker)
+                    .map_err(|e| StorageError::Database(Box::from(e)))?;
                 let job_type = T::NAME;
                 let mut tx = pool
                     .acquire()
@@ -239,7 +488,7 @@ where
                         WHERE status = "Running" AND workers.last_seen < ? AND workers.worker_type = ?
                         ORDER BY lock_at ASC LIMIT ?;"#;
                 sqlx::query(query)
-                    .bind(Utc::now().sub(chrono::Duration::minutes(5)))
+                    .bind(Utc::now().sub(timeout_worker))
                     .bind(job_type)
                     .bind(count)
                     .execute(&mut tx)
@@ -255,15 +504,99 @@ where
         let pool = self.pool.clone();
 
         let mut tx = pool
-            .acquire()
+            .begin()
             .await
             .map_err(|e| StorageError::Connection(Box::from(e)))?;
         let query =
             "UPDATE jobs SET status = 'Killed', done_at = NOW() WHERE id = ? AND lock_by = ?";
-        sqlx::query(query)
+        let killed = sqlx::query(query)
             .bind(job_id.to_owned())
             .bind(worker_id.to_owned())
             .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if killed > 0 {
+            finish_dependents(&mut tx, &job_id, &JobState::Killed).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn cancel(&mut self, job_id: String) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query =
+            "UPDATE jobs SET status = 'Cancelled', done_at = NOW(), lock_by = NULL, lock_at = NULL
+            WHERE id = ? AND status IN ('Pending', 'Retry', 'Failed', 'Blocked')";
+        let cancelled = sqlx::query(query)
+            .bind(&job_id)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if cancelled > 0 {
+            finish_dependents(&mut tx, &job_id, &JobState::Cancelled).await?;
+        } else {
+            // A running job is left to its worker
+            let query =
+                "UPDATE jobs SET cancel_requested = TRUE WHERE id = ? AND status = 'Running'";
+            let flagged = sqlx::query(query)
+                .bind(&job_id)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+            if flagged == 0 {
+                return Err(StorageError::NotFound);
+            }
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_cancelled(&self, worker_id: String) -> StorageResult<Vec<String>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query =
+            "SELECT id FROM jobs WHERE lock_by = ? AND status = 'Running' AND cancel_requested";
+        let ids: Vec<(String,)> = sqlx::query_as(query)
+            .bind(worker_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn abort(&mut self, worker_id: String, job_id: String) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query =
+            "UPDATE jobs SET status = 'Cancelled', done_at = NOW() WHERE id = ? AND lock_by = ?";
+        let aborted = sqlx::query(query)
+            .bind(&job_id)
+            .bind(worker_id)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if aborted > 0 {
+            finish_dependents(&mut tx, &job_id, &JobState::Cancelled).await?;
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         Ok(())
@@ -289,8 +622,176 @@ where
         Ok(())
     }
 
-    fn consume(&mut self, worker_id: String, interval: Duration) -> JobStreamResult<T> {
-        Box::pin(self.stream_jobs(worker_id, interval))
+    async fn open_batch(&mut self, callbacks: BatchCallbacks<T>) -> StorageResult<BatchId> {
+        let batch_id = BatchId::new();
+        let job_type = T::NAME;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        // Callbacks wait as blocked jobs until the batch finishes
+        let mut callback_ids = Vec::new();
+        for callback in [callbacks.on_success(), callbacks.on_failure()] {
+            let callback = match callback {
+                Some(callback) => callback,
+                None => {
+                    callback_ids.push(None);
+                    continue;
+                }
+            };
+            let id = JobId::new().to_string();
+            sqlx::query("INSERT INTO jobs (job, id, job_type, status) VALUES (?, ?, ?, 'Blocked')")
+                .bind(serde_json::to_string(callback)?)
+                .bind(&id)
+                .bind(job_type)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            callback_ids.push(Some(id));
+        }
+        sqlx::query(
+            "INSERT INTO batches (id, job_type, on_success, on_failure) VALUES (?, ?, ?, ?)",
+        )
+        .bind(batch_id.to_string())
+        .bind(job_type)
+        .bind(&callback_ids[0])
+        .bind(&callback_ids[1])
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(batch_id)
+    }
+
+    async fn close_batch(&mut self, batch_id: &BatchId) -> StorageResult<()> {
+        let batch_id = batch_id.to_string();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let closed = sqlx::query("UPDATE batches SET closed = TRUE WHERE id = ? AND job_type = ?")
+            .bind(&batch_id)
+            .bind(T::NAME)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if closed == 0 {
+            return Err(StorageError::NotFound);
+        }
+        // Every job may have finished already
+        finish_batch(&mut tx, &batch_id).await?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn batch_progress(&self, batch_id: &BatchId) -> StorageResult<Option<BatchProgress>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query = "SELECT total, done, failed, closed FROM batches WHERE id = ? AND job_type = ?";
+        let progress: Option<(i64, i64, i64, bool)> = sqlx::query_as(query)
+            .bind(batch_id.to_string())
+            .bind(T::NAME)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(progress.map(|(total, done, failed, closed)| BatchProgress {
+            total,
+            done,
+            failed,
+            closed,
+        }))
+    }
+
+    async fn save_result(
+        &mut self,
+        job_id: String,
+        result: serde_json::Value,
+        retention: Duration,
+    ) -> StorageResult<()> {
+        let now = Utc::now();
+        let expires_at = now
+            + chrono::Duration::from_std(retention)
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Results past their retention are dropped as new ones are saved
+        sqlx::query(
+            "UPDATE jobs SET result = NULL, result_expires_at = NULL WHERE result_expires_at <= ?",
+        )
+        .bind(now)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let saved = sqlx::query("UPDATE jobs SET result = ?, result_expires_at = ? WHERE id = ?")
+            .bind(serde_json::to_string(&result)?)
+            .bind(expires_at)
+            .bind(&job_id)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if saved == 0 {
+            return Err(StorageError::NotFound);
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_result(&self, job_id: String) -> StorageResult<Option<serde_json::Value>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query = "SELECT result FROM jobs WHERE id = ? AND result_expires_at > ?";
+        let result: Option<(serde_json::Value,)> = sqlx::query_as(query)
+            .bind(job_id)
+            .bind(Utc::now())
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(result.map(|(result,)| result))
+    }
+
+    async fn reenqueue_active(&mut self, worker_id: String) -> StorageResult<()> {
+        let pool = self.pool.clone();
+
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+                "UPDATE jobs SET status = 'Pending', done_at = NULL, lock_by = NULL, lock_at = NULL WHERE lock_by = ? AND status = 'Running'";
+        sqlx::query(query)
+            .bind(worker_id)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    fn consume(
+        &mut self,
+        worker_id: String,
+        interval: Duration,
+        buffer_size: usize,
+    ) -> JobStreamResult<T> {
+        Box::pin(self.stream_jobs(worker_id, interval, buffer_size))
     }
     async fn len(&self) -> StorageResult<i64> {
         let pool = self.pool.clone();
@@ -312,20 +813,80 @@ where
         let pool = self.pool.clone();
 
         let mut tx = pool
-            .acquire()
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Lock the blocked children first, so that acks of their other parents run one at a time
+        // and the last one sees every parent done
+        let children: Vec<(String,)> = sqlx::query_as(
+            "SELECT id FROM jobs WHERE status = 'Blocked'
+                AND id IN (SELECT job_id FROM job_dependencies WHERE parent_id = ?)
+                ORDER BY id FOR UPDATE",
+        )
+        .bind(&job_id)
+        .fetch_all(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query = "UPDATE jobs SET status = 'Done', done_at = now() WHERE id = ? AND lock_by = ?";
-        sqlx::query(query)
+        let done = sqlx::query(query)
             .bind(job_id.to_owned())
             .bind(worker_id.to_owned())
             .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if done > 0 {
+            let (batch_id,): (Option<String>,) =
+                sqlx::query_as("SELECT batch_id FROM jobs WHERE id = ?")
+                    .bind(&job_id)
+                    .fetch_one(&mut tx)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::from(e)))?;
+            if let Some(batch_id) = batch_id {
+                count_in_batch(&mut tx, &batch_id, 1, 0).await?;
+            }
+        }
+        if done > 0 && !children.is_empty() {
+            // Unblock the children whose parents are now all done
+            let ids = vec!["?"; children.len()].join(", ");
+            let ready_query = format!(
+                "SELECT d.job_id FROM job_dependencies d INNER JOIN jobs p ON p.id = d.parent_id
+                    WHERE d.job_id IN ({ids}) GROUP BY d.job_id HAVING SUM(p.status <> 'Done') = 0"
+            );
+            let mut ready = sqlx::query_as(&ready_query);
+            for (id,) in &children {
+                ready = ready.bind(id);
+            }
+            let ready: Vec<(String,)> = ready
+                .fetch_all(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            if !ready.is_empty() {
+                let ids = vec!["?"; ready.len()].join(", ");
+                let update_query =
+                    format!("UPDATE jobs SET status = 'Pending' WHERE id IN ({ids})");
+                let mut update = sqlx::query(&update_query);
+                for (id,) in &ready {
+                    update = update.bind(id);
+                }
+                update
+                    .execute(&mut tx)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::from(e)))?;
+            }
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         Ok(())
     }
 
-    async fn reschedule(&mut self, job: &JobRequest<T>, wait: Duration) -> StorageResult<()> {
+    async fn reschedule(
+        &mut self,
+        _worker_id: String,
+        job: &JobRequest<T>,
+        wait: Duration,
+    ) -> StorageResult<()> {
         let pool = self.pool.clone();
         let job_id = job.id();
 
@@ -361,13 +922,18 @@ where
         let lock_by = job.lock_by().clone();
         let lock_at = *job.lock_at();
         let last_error = job.last_error().clone();
+        let last_failure = job
+            .last_failure()
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
 
         let mut tx = pool
             .acquire()
             .await
             .map_err(|e| StorageError::Connection(Box::from(e)))?;
         let query =
-                "UPDATE jobs SET status = ?, attempts = ?, done_at = ?, lock_by = ?, lock_at = ?, last_error = ? WHERE id = ?";
+                "UPDATE jobs SET status = ?, attempts = ?, done_at = ?, lock_by = ?, lock_at = ?, last_error = ?, last_failure = ? WHERE id = ?";
         sqlx::query(query)
             .bind(status.to_owned())
             .bind(attempts)
@@ -375,6 +941,7 @@ where
             .bind(lock_by)
             .bind(lock_at)
             .bind(last_error)
+            .bind(last_failure)
             .bind(job_id.to_owned())
             .execute(&mut tx)
             .await
@@ -382,11 +949,170 @@ where
         Ok(())
     }
 
+    async fn update_progress(
+        &mut self,
+        job_id: String,
+        progress: &JobProgress,
+    ) -> StorageResult<()> {
+        let progress = serde_json::to_string(progress)?;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query = "UPDATE jobs SET progress = ? WHERE id = ?";
+        let updated = sqlx::query(query)
+            .bind(progress)
+            .bind(job_id)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn append_logs(
+        &mut self,
+        job_id: String,
+        lines: &[JobLogLine],
+        max_lines: usize,
+    ) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        for line in lines {
+            sqlx::query(
+                "INSERT INTO job_logs (job_id, level, message, logged_at) VALUES (?, ?, ?, ?)",
+            )
+            .bind(&job_id)
+            .bind(&line.level)
+            .bind(&line.message)
+            .bind(line.logged_at)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        }
+        // Only the last `max_lines` lines of the job are kept
+        let query = "DELETE FROM job_logs WHERE job_id = ? AND id NOT IN (SELECT id FROM (SELECT id FROM job_logs WHERE job_id = ? ORDER BY id DESC LIMIT ?) AS kept)";
+        sqlx::query(query)
+            .bind(&job_id)
+            .bind(&job_id)
+            .bind(max_lines as i64)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
     async fn keep_alive<Service>(&mut self, worker_id: String) -> StorageResult<()> {
         self.keep_alive_at::<Service>(worker_id, Utc::now()).await
     }
 }
 
+/// Puts a killed job back into the queue, see [DeadLetter]
+const REQUEUE_DEAD: &str = "status = 'Pending', attempts = 0, run_at = NOW(), done_at = NULL, lock_by = NULL, lock_at = NULL, unique_key = NULL, unique_until = NULL, batch_id = NULL, cancel_requested = FALSE";
+
+#[async_trait::async_trait]
+impl<T> DeadLetter for MysqlStorage<T>
+where
+    T: Job + Serialize + DeserializeOwned + Send + 'static + Unpin,
+{
+    async fn list_dead(&self, page: i32) -> StorageResult<Vec<JobRequest<T>>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query = "SELECT * FROM jobs WHERE status = 'Killed' AND job_type = ? ORDER BY done_at DESC LIMIT 10 OFFSET ?";
+        let jobs: Vec<SqlJobRequest<T>> = sqlx::query_as(query)
+            .bind(T::NAME)
+            .bind(((page - 1) * 10) as i64)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(jobs.into_iter().map(Into::into).collect())
+    }
+
+    async fn requeue_dead(&mut self, job_ids: &[JobId]) -> StorageResult<u64> {
+        if job_ids.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let ids = vec!["?"; job_ids.len()].join(", ");
+        let query = format!(
+            "UPDATE jobs SET {REQUEUE_DEAD} WHERE status = 'Killed' AND job_type = ? AND id IN ({ids})"
+        );
+        let mut query = sqlx::query(&query).bind(T::NAME);
+        for id in job_ids {
+            query = query.bind(id.to_string());
+        }
+        let requeued = query
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(requeued)
+    }
+
+    async fn requeue_dead_matching(&mut self, filter: &DeadLetterFilter) -> StorageResult<u64> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query = format!(
+            "UPDATE jobs SET {REQUEUE_DEAD} WHERE status = 'Killed' AND job_type = ?
+                AND (? IS NULL OR INSTR(last_error, ?) > 0)
+                AND (? IS NULL OR done_at >= ?)
+                AND (? IS NULL OR done_at < ?)"
+        );
+        let requeued = sqlx::query(&query)
+            .bind(T::NAME)
+            .bind(filter.error_contains())
+            .bind(filter.error_contains())
+            .bind(filter.killed_after())
+            .bind(filter.killed_after())
+            .bind(filter.killed_before())
+            .bind(filter.killed_before())
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(requeued)
+    }
+
+    async fn purge_dead(&mut self, age: Duration) -> StorageResult<u64> {
+        let killed_before = Utc::now()
+            - chrono::Duration::from_std(age).map_err(|e| StorageError::Database(Box::from(e)))?;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query = "DELETE FROM jobs WHERE status = 'Killed' AND job_type = ? AND done_at < ?";
+        let purged = sqlx::query(query)
+            .bind(T::NAME)
+            .bind(killed_before)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(purged)
+    }
+}
+
 #[async_trait::async_trait]
 
 impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for MysqlStorage<J> {
@@ -404,9 +1130,11 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for MysqlS
             COUNT(CASE WHEN status = 'Done' THEN 1 END) AS done,
             COUNT(CASE WHEN status = 'Retry' THEN 1 END) AS retry,
             COUNT(CASE WHEN status = 'Failed' THEN 1 END) AS failed,
-            COUNT(CASE WHEN status = 'Killed' THEN 1 END) AS killed
+            COUNT(CASE WHEN status = 'Killed' THEN 1 END) AS killed,
+            COUNT(CASE WHEN status = 'Blocked' THEN 1 END) AS blocked,
+            COUNT(CASE WHEN status = 'Cancelled' THEN 1 END) AS cancelled
         FROM jobs WHERE job_type = ?";
-        let res: (i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
+        let res: (i64, i64, i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
             .bind(J::NAME)
             .fetch_one(&mut conn)
             .await
@@ -418,6 +1146,8 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for MysqlS
         inner.insert(JobState::Retry, res.3);
         inner.insert(JobState::Failed, res.4);
         inner.insert(JobState::Killed, res.5);
+        inner.insert(JobState::Blocked, res.6);
+        inner.insert(JobState::Cancelled, res.7);
         Ok(Counts { inner })
     }
 
@@ -445,6 +1175,29 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for MysqlS
         Ok(res.into_iter().map(|j| j.into()).collect())
     }
 
+    async fn job_logs(&mut self, job_id: String) -> Result<Vec<JobLogLine>, JobError> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Connection(Box::from(e)))?;
+        let query =
+            "SELECT level, message, logged_at FROM job_logs WHERE job_id = ? ORDER BY id ASC";
+        let lines: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(query)
+            .bind(job_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(lines
+            .into_iter()
+            .map(|(level, message, logged_at)| JobLogLine {
+                level,
+                message,
+                logged_at,
+            })
+            .collect())
+    }
+
     async fn list_workers(&mut self) -> Result<Vec<JobStreamWorker>, JobError> {
         let mut conn = self
             .pool
@@ -475,8 +1228,14 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for MysqlS
 mod tests {
 
     use super::*;
+    use apalis_core::context::JobContext;
+    use apalis_core::job_fn::job_fn;
+    use apalis_core::storage::AckLayer;
+    use apalis_core::worker::WorkerRef;
     use email_service::Email;
     use futures::StreamExt;
+    use sqlx::types::Uuid;
+    use tower::{Layer, Service};
 
     /// migrate DB and return a storage instance.
     async fn setup() -> MysqlStorage<Email> {
@@ -514,7 +1273,8 @@ mod tests {
     where
         S: Storage<Output = T>,
     {
-        let mut stream = storage.consume(worker_id.to_owned(), std::time::Duration::from_secs(10));
+        let mut stream =
+            storage.consume(worker_id.to_owned(), std::time::Duration::from_secs(10), 1);
         stream
             .next()
             .await
@@ -629,6 +1389,82 @@ mod tests {
         cleanup(storage, worker_id).await;
     }
 
+    #[tokio::test]
+    async fn test_ack_layer_stores_job_logs() {
+        let mut storage = setup().await;
+
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, &worker_id).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone())
+            .with_job_logs(2)
+            .layer(job_fn(|_: Email, ctx: JobContext| async move {
+                ctx.logger().info("connecting");
+                ctx.logger().info("sending");
+                ctx.logger().warn("slow mailbox");
+                Ok::<_, JobError>(())
+            }));
+        service.call(job).await.expect("job should succeed");
+
+        let line = JobLogLine {
+            level: "INFO".to_string(),
+            message: "sent".to_string(),
+            logged_at: Utc::now(),
+        };
+        storage
+            .append_logs(job_id.clone(), &[line], 2)
+            .await
+            .expect("failed to append logs");
+
+        let lines: Vec<String> = storage
+            .job_logs(job_id)
+            .await
+            .expect("failed to fetch logs")
+            .into_iter()
+            .map(|l| l.message)
+            .collect();
+        assert_eq!(lines, vec!["slow mailbox".to_string(), "sent".to_string()]);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_job() {
+        let mut storage = setup().await;
+
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, &worker_id).await;
+        let job_id = job.context().id();
+
+        storage
+            .cancel(job_id.clone())
+            .await
+            .expect("failed to cancel job");
+        let cancelled = storage
+            .fetch_cancelled(worker_id.clone())
+            .await
+            .expect("failed to fetch cancelled jobs");
+        assert_eq!(cancelled, vec![job_id.clone()]);
+
+        storage
+            .abort(worker_id.clone(), job_id.clone())
+            .await
+            .expect("failed to abort job");
+
+        let job = get_job(&mut storage, job_id.clone()).await;
+        assert_eq!(*job.context().status(), JobState::Cancelled);
+        assert!(job.context().done_at().is_some());
+
+        cleanup(storage, worker_id).await;
+    }
+
     #[tokio::test]
     async fn test_storage_heartbeat_reenqueuorphaned_pulse_last_seen_6min() {
         let mut storage = setup().await;
@@ -653,7 +1489,10 @@ mod tests {
 
         // heartbeat with ReenqueueOrpharned pulse
         storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .unwrap();
 
@@ -693,7 +1532,10 @@ mod tests {
 
         // heartbeat with ReenqueueOrpharned pulse
         storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .unwrap();
 
@@ -705,4 +1547,52 @@ mod tests {
 
         cleanup(storage, worker_id).await;
     }
+
+    #[tokio::test]
+    async fn test_ack_layer_acknowledges_successful_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, &worker_id).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone()).layer(
+            job_fn(|_: Email, _: JobContext| async { Ok::<_, JobError>(()) }),
+        );
+        service.call(job).await.expect("job should succeed");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Done);
+        assert!(job.context().done_at().is_some());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_retries_failed_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, &worker_id).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone()).layer(
+            job_fn(|_: Email, _: JobContext| async { Err::<(), _>("smtp unavailable") }),
+        );
+        assert!(service.call(job).await.is_err());
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 1);
+        assert_eq!(
+            *job.context().last_error(),
+            Some("Job Failed: smtp unavailable".to_string())
+        );
+
+        cleanup(storage, worker_id).await;
+    }
 }
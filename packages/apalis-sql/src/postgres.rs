@@ -10,20 +10,23 @@
 //!     );
 //! ```
 
+use apalis_core::context::{JobLogLine, JobProgress};
 use apalis_core::error::{JobError, JobStreamError};
-use apalis_core::job::{Counts, Job, JobStreamExt, JobStreamResult, JobStreamWorker};
+use apalis_core::job::{Counts, Job, JobId, JobStreamExt, JobStreamResult, JobStreamWorker};
 use apalis_core::request::{JobRequest, JobState};
 use apalis_core::storage::StorageError;
 use apalis_core::storage::StorageWorkerPulse;
-use apalis_core::storage::{Storage, StorageResult};
+use apalis_core::storage::{
+    BatchCallbacks, BatchId, BatchProgress, DeadLetter, DeadLetterFilter, PushOptions, Storage,
+    StorageResult, UniquePolicy,
+};
 use async_stream::try_stream;
 use chrono::{DateTime, Utc};
 use futures::{FutureExt, Stream};
 use futures_lite::future;
 use serde::{de::DeserializeOwned, Serialize};
 use sqlx::postgres::PgListener;
-use sqlx::types::Uuid;
-use sqlx::{PgPool, Row};
+use sqlx::{PgPool, Postgres, Row, Transaction};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{marker::PhantomData, ops::Add, time::Duration};
@@ -75,6 +78,7 @@ impl<T: DeserializeOwned + Send + Unpin + Job> PostgresStorage<T> {
         &self,
         worker_id: String,
         interval: Duration,
+        buffer_size: usize,
     ) -> impl Stream<Item = Result<Option<JobRequest<T>>, JobStreamError>> {
         let pool = self.pool.clone();
         let mut interval = tokio::time::interval(interval);
@@ -91,13 +95,19 @@ impl<T: DeserializeOwned + Send + Unpin + Job> PostgresStorage<T> {
                 let tx = pool.clone();
                 let mut tx = tx.acquire().await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
                 let job_type = T::NAME;
-                let fetch_query = "Select * FROM apalis.get_job($1, $2) WHERE job IS NOT NULL;";
-                let job: Option<SqlJobRequest<T>> = sqlx::query_as(fetch_query)
+                let fetch_query = "Select * FROM apalis.get_jobs($1, $2, $3);";
+                let jobs: Vec<SqlJobRequest<T>> = sqlx::query_as(fetch_query)
                     .bind(worker_id.clone())
                     .bind(job_type)
-                    .fetch_optional(&mut tx)
+                    .bind(i32::try_from(buffer_size).unwrap_or(i32::MAX))
+                    .fetch_all(&mut tx)
                     .await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-                yield job.build_job_request()
+                if jobs.is_empty() {
+                    yield None;
+                }
+                for job in jobs {
+                    yield Some(job.into());
+                }
             }
         }
     }
@@ -132,6 +142,212 @@ impl<T: DeserializeOwned + Send + Unpin + Job> PostgresStorage<T> {
     }
 }
 
+/// Makes sure `key` is free for a new job of `job_type`, following `policy`.
+/// Returns the job holding the key when the push should be skipped instead
+async fn acquire_unique_key(
+    tx: &mut Transaction<'_, Postgres>,
+    job_type: &str,
+    key: &str,
+    policy: UniquePolicy,
+) -> StorageResult<Option<JobId>> {
+    // Serialize racing pushes of the same key until the transaction ends
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1 || '::' || $2))")
+        .bind(job_type)
+        .bind(key)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    sqlx::query(
+        "UPDATE apalis.jobs SET unique_key = NULL WHERE job_type = $1 AND unique_key = $2 AND unique_until <= now()",
+    )
+    .bind(job_type)
+    .bind(key)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let holder: Option<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, status, batch_id FROM apalis.jobs WHERE job_type = $1 AND unique_key = $2 AND status NOT IN ('Done', 'Killed', 'Cancelled') FOR UPDATE",
+    )
+    .bind(job_type)
+    .bind(key)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let (holder, status, batch_id) = match holder {
+        Some(holder) => holder,
+        None => return Ok(None),
+    };
+    let holder_id = holder
+        .parse()
+        .map_err(|e| StorageError::Database(Box::new(e)))?;
+    let (dependents,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM apalis.job_dependencies WHERE parent_id = $1")
+            .bind(&holder)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    match policy {
+        UniquePolicy::Ignore => Ok(Some(holder_id)),
+        // Jobs of a batch are counted by it, and jobs waiting on the holder would never run,
+        // so those are never replaced
+        UniquePolicy::Replace
+            if status != JobState::Running.as_ref() && batch_id.is_none() && dependents == 0 =>
+        {
+            sqlx::query("DELETE FROM apalis.jobs WHERE id = $1")
+                .bind(holder)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            Ok(None)
+        }
+        _ => Err(StorageError::Duplicate(holder_id)),
+    }
+}
+
+/// Records that `job_id` depends on `parents` and blocks or kills it accordingly,
+/// returning the state of the job.
+/// Must run after the job was inserted, within the same transaction
+async fn add_dependencies(
+    tx: &mut Transaction<'_, Postgres>,
+    job_id: &JobId,
+    parents: &[JobId],
+) -> StorageResult<JobState> {
+    let job_id = job_id.to_string();
+    let mut state = JobState::Pending;
+    for parent in parents {
+        let parent = parent.to_string();
+        sqlx::query(
+            "INSERT INTO apalis.job_dependencies (job_id, parent_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(&job_id)
+        .bind(&parent)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Holding the parent row makes a concurrent ack wait until the dependency is visible
+        let status: Option<(String,)> =
+            sqlx::query_as("SELECT status FROM apalis.jobs WHERE id = $1 FOR SHARE")
+                .bind(parent)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        match status {
+            None => return Err(StorageError::NotFound),
+            Some((status,)) if status == JobState::Killed.as_ref() => state = JobState::Killed,
+            Some((status,))
+                if status == JobState::Cancelled.as_ref() && state != JobState::Killed =>
+            {
+                state = JobState::Cancelled
+            }
+            Some((status,)) if status != JobState::Done.as_ref() && state == JobState::Pending => {
+                state = JobState::Blocked
+            }
+            _ => {}
+        }
+    }
+    let query = match state {
+        JobState::Pending => return Ok(state),
+        JobState::Killed => "UPDATE apalis.jobs SET status = 'Killed', done_at = now(), last_error = 'A parent job was killed' WHERE id = $1",
+        JobState::Cancelled => "UPDATE apalis.jobs SET status = 'Cancelled', done_at = now(), last_error = 'A parent job was cancelled' WHERE id = $1",
+        _ => "UPDATE apalis.jobs SET status = 'Blocked' WHERE id = $1",
+    };
+    sqlx::query(query)
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    Ok(state)
+}
+
+/// Gives every job waiting on `job_id`, directly or not, the `status` it finished with since
+/// they can never run, then counts them all as failed in their batches along with `job_id`
+async fn finish_dependents(
+    tx: &mut Transaction<'_, Postgres>,
+    job_id: &str,
+    batch_id: Option<String>,
+    status: &JobState,
+) -> StorageResult<()> {
+    let query = "WITH RECURSIVE descendants(id) AS (
+            SELECT job_id FROM apalis.job_dependencies WHERE parent_id = $1
+            UNION SELECT d.job_id FROM apalis.job_dependencies d
+                INNER JOIN descendants ON d.parent_id = descendants.id
+        )
+        UPDATE apalis.jobs SET status = $2, done_at = now(), last_error = $3
+        WHERE status = 'Blocked' AND id IN (SELECT id FROM descendants)
+        RETURNING batch_id";
+    let descendants: Vec<(Option<String>,)> = sqlx::query_as(query)
+        .bind(job_id)
+        .bind(status.as_ref())
+        .bind(format!(
+            "A parent job was {}",
+            status.as_ref().to_lowercase()
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let mut failed: HashMap<String, i64> = HashMap::new();
+    for batch_id in std::iter::once(batch_id)
+        .chain(descendants.into_iter().map(|(batch_id,)| batch_id))
+        .flatten()
+    {
+        *failed.entry(batch_id).or_default() += 1;
+    }
+    for (batch_id, failed) in failed {
+        count_in_batch(tx, &batch_id, 0, failed).await?;
+    }
+    Ok(())
+}
+
+/// Counts finished jobs towards `batch_id`
+async fn count_in_batch(
+    tx: &mut Transaction<'_, Postgres>,
+    batch_id: &str,
+    done: i64,
+    failed: i64,
+) -> StorageResult<()> {
+    sqlx::query("UPDATE apalis.batches SET done = done + $2, failed = failed + $3 WHERE id = $1")
+        .bind(batch_id)
+        .bind(done)
+        .bind(failed)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    finish_batch(tx, batch_id).await
+}
+
+/// Releases the callback of `batch_id` if it is closed and every job in it has finished.
+/// The callback that does not apply is deleted
+async fn finish_batch(tx: &mut Transaction<'_, Postgres>, batch_id: &str) -> StorageResult<()> {
+    let query = "UPDATE apalis.batches SET finished_at = now()
+        WHERE id = $1 AND closed AND finished_at IS NULL AND done + failed >= total
+        RETURNING on_success, on_failure, failed";
+    let batch: Option<(Option<String>, Option<String>, i64)> = sqlx::query_as(query)
+        .bind(batch_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let (callback, discarded) = match batch {
+        Some((on_success, on_failure, 0)) => (on_success, on_failure),
+        Some((on_success, on_failure, _)) => (on_failure, on_success),
+        None => return Ok(()),
+    };
+    if let Some(callback) = callback {
+        sqlx::query("UPDATE apalis.jobs SET status = 'Pending', run_at = now() WHERE id = $1")
+            .bind(callback)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    if let Some(discarded) = discarded {
+        sqlx::query("DELETE FROM apalis.jobs WHERE id = $1")
+            .bind(discarded)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    Ok(())
+}
+
 #[async_trait::async_trait]
 impl<T> Storage for PostgresStorage<T>
 where
@@ -146,50 +362,72 @@ where
     /// ```sql
     /// Select apalis.push_job(job_type::text, job::json);
     /// ```
-    async fn push(&mut self, job: Self::Output) -> StorageResult<()> {
-        let id = Uuid::new_v4();
-        let query = "INSERT INTO apalis.jobs VALUES ($1, $2, $3, 'Pending', 0, 25, NOW() , NULL, NULL, NULL, NULL)";
+    async fn push_with(&mut self, job: Self::Output, options: PushOptions) -> StorageResult<JobId> {
+        let id = options.id().copied().unwrap_or_default();
+        let run_at = options.run_at().copied().unwrap_or_else(Utc::now);
+        let query = "INSERT INTO apalis.jobs (job, id, job_type, status, attempts, max_attempts, run_at, timeout_ms, priority, unique_key, unique_until, batch_id) VALUES ($1, $2, $3, 'Pending', 0, $4, $5, $6, $7, $8, $9, $10)";
         let pool = self.pool.clone();
         let job = serde_json::to_value(&job)?;
-        let mut pool = pool
-            .acquire()
+        let mut tx = pool
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let job_type = T::NAME;
+        if let Some(key) = options.unique_key() {
+            if let Some(holder) =
+                acquire_unique_key(&mut tx, job_type, key, options.unique_policy()).await?
+            {
+                return Ok(holder);
+            }
+        }
+        let unique_until = match options.unique_ttl() {
+            Some(ttl) => Some(
+                Utc::now()
+                    + chrono::Duration::from_std(ttl)
+                        .map_err(|e| StorageError::Database(Box::from(e)))?,
+            ),
+            None => None,
+        };
         sqlx::query(query)
             .bind(job)
             .bind(id.to_string())
             .bind(job_type.to_string())
-            .execute(&mut pool)
-            .await
-            .map_err(|e| StorageError::Database(Box::from(e)))?;
-        Ok(())
-    }
-
-    async fn schedule(
-        &mut self,
-        job: Self::Output,
-        on: chrono::DateTime<Utc>,
-    ) -> StorageResult<()> {
-        let query =
-            "INSERT INTO apalis.jobs VALUES ($1, $2, $3, 'Pending', 0, 25, $4, NULL, NULL, NULL, NULL)";
-        let pool = self.pool.clone();
-        let id = Uuid::new_v4();
-        let job = serde_json::to_value(&job)?;
-        let mut pool = pool
-            .acquire()
+            .bind(options.max_attempts())
+            .bind(run_at)
+            .bind(options.timeout().map(|timeout| timeout.as_millis() as i64))
+            .bind(options.priority())
+            .bind(options.unique_key())
+            .bind(unique_until)
+            .bind(options.batch().map(ToString::to_string))
+            .execute(&mut tx)
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        let job_type = T::NAME;
-        sqlx::query(query)
-            .bind(job)
-            .bind(id.to_string())
-            .bind(job_type)
-            .bind(on)
-            .execute(&mut pool)
+        let state = match options.parents() {
+            [] => JobState::Pending,
+            parents => add_dependencies(&mut tx, &id, parents).await?,
+        };
+        if let Some(batch_id) = options.batch() {
+            let query = "UPDATE apalis.batches SET total = total + 1, failed = failed + $3
+                WHERE id = $1 AND job_type = $2 AND NOT closed";
+            let added = sqlx::query(query)
+                .bind(batch_id.to_string())
+                .bind(job_type)
+                .bind(i64::from(matches!(
+                    state,
+                    JobState::Killed | JobState::Cancelled
+                )))
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+            if added == 0 {
+                return Err(StorageError::NotFound);
+            }
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        Ok(())
+        Ok(id)
     }
 
     async fn fetch_by_id(&self, job_id: String) -> StorageResult<Option<JobRequest<Self::Output>>> {
@@ -216,8 +454,13 @@ where
                 // Idealy jobs are queue via run_at. So this is not necessary
                 Ok(true)
             }
-            // Worker not seen in 5 minutes yet has running jobs
-            StorageWorkerPulse::RenqueueOrpharned { count } => {
+            // Worker not seen within `timeout_worker` yet has running jobs
+            StorageWorkerPulse::RenqueueOrpharned {
+                count,
+                timeout_worker,
+            } => {
+                let timeout_worker = chrono::Duration::from_std(timeout_worker)
+                    .map_err(|e| StorageError::Database(Box::from(e)))?;
                 let job_type = T::NAME;
                 let mut tx = pool
                     .acquire()
@@ -227,11 +470,12 @@ where
                             SET status = 'Pending', done_at = NULL, lock_by = NULL, lock_at = NULL, last_error ='Job was abandoned'
                             WHERE id in 
                                 (SELECT jobs.id from apalis.jobs INNER join apalis.workers ON lock_by = workers.id 
-                                    WHERE status= 'Running' AND workers.last_seen < NOW() - INTERVAL '5 minutes'
+                                    WHERE status= 'Running' AND workers.last_seen < $3
                                     AND workers.worker_type = $1 ORDER BY lock_at ASC LIMIT $2);";
                 sqlx::query(query)
                     .bind(job_type)
                     .bind(count)
+                    .bind(Utc::now() - timeout_worker)
                     .execute(&mut tx)
                     .await
                     .map_err(|e| StorageError::Database(Box::from(e)))?;
@@ -245,15 +489,97 @@ where
         let pool = self.pool.clone();
 
         let mut tx = pool
-            .acquire()
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query =
-                "UPDATE apalis.jobs SET status = 'Killed', done_at = now() WHERE id = $1 AND lock_by = $2";
-        sqlx::query(query)
+                "UPDATE apalis.jobs SET status = 'Killed', done_at = now() WHERE id = $1 AND lock_by = $2 RETURNING batch_id";
+        let killed: Option<(Option<String>,)> = sqlx::query_as(query)
             .bind(job_id.to_owned())
             .bind(worker_id.to_owned())
-            .execute(&mut tx)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        if let Some((batch_id,)) = killed {
+            finish_dependents(&mut tx, &job_id, batch_id, &JobState::Killed).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn cancel(&mut self, job_id: String) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "UPDATE apalis.jobs SET status = 'Cancelled', done_at = now(), lock_by = NULL, lock_at = NULL
+            WHERE id = $1 AND status IN ('Pending', 'Retry', 'Failed', 'Blocked')
+            RETURNING batch_id";
+        let cancelled: Option<(Option<String>,)> = sqlx::query_as(query)
+            .bind(&job_id)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        match cancelled {
+            Some((batch_id,)) => {
+                finish_dependents(&mut tx, &job_id, batch_id, &JobState::Cancelled).await?
+            }
+            None => {
+                // A running job is left to its worker
+                let query = "UPDATE apalis.jobs SET cancel_requested = TRUE WHERE id = $1 AND status = 'Running'";
+                let flagged = sqlx::query(query)
+                    .bind(&job_id)
+                    .execute(&mut tx)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::from(e)))?
+                    .rows_affected();
+                if flagged == 0 {
+                    return Err(StorageError::NotFound);
+                }
+            }
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_cancelled(&self, worker_id: String) -> StorageResult<Vec<String>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "SELECT id FROM apalis.jobs WHERE lock_by = $1 AND status = 'Running' AND cancel_requested";
+        let ids: Vec<(String,)> = sqlx::query_as(query)
+            .bind(worker_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn abort(&mut self, worker_id: String, job_id: String) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+                "UPDATE apalis.jobs SET status = 'Cancelled', done_at = now() WHERE id = $1 AND lock_by = $2 RETURNING batch_id";
+        let aborted: Option<(Option<String>,)> = sqlx::query_as(query)
+            .bind(&job_id)
+            .bind(worker_id)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        if let Some((batch_id,)) = aborted {
+            finish_dependents(&mut tx, &job_id, batch_id, &JobState::Cancelled).await?;
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         Ok(())
@@ -279,8 +605,180 @@ where
         Ok(())
     }
 
-    fn consume(&mut self, worker_id: String, interval: Duration) -> JobStreamResult<T> {
-        Box::pin(self.stream_jobs(worker_id, interval))
+    async fn open_batch(&mut self, callbacks: BatchCallbacks<T>) -> StorageResult<BatchId> {
+        let batch_id = BatchId::new();
+        let job_type = T::NAME;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Callbacks wait as blocked jobs until the batch finishes
+        let mut callback_ids = Vec::new();
+        for callback in [callbacks.on_success(), callbacks.on_failure()] {
+            let callback = match callback {
+                Some(callback) => callback,
+                None => {
+                    callback_ids.push(None);
+                    continue;
+                }
+            };
+            let id = JobId::new().to_string();
+            sqlx::query(
+                "INSERT INTO apalis.jobs (job, id, job_type, status) VALUES ($1, $2, $3, 'Blocked')",
+            )
+            .bind(serde_json::to_value(callback)?)
+            .bind(&id)
+            .bind(job_type)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+            callback_ids.push(Some(id));
+        }
+        sqlx::query(
+            "INSERT INTO apalis.batches (id, job_type, on_success, on_failure) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(batch_id.to_string())
+        .bind(job_type)
+        .bind(&callback_ids[0])
+        .bind(&callback_ids[1])
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(batch_id)
+    }
+
+    async fn close_batch(&mut self, batch_id: &BatchId) -> StorageResult<()> {
+        let batch_id = batch_id.to_string();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let closed =
+            sqlx::query("UPDATE apalis.batches SET closed = TRUE WHERE id = $1 AND job_type = $2")
+                .bind(&batch_id)
+                .bind(T::NAME)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+        if closed == 0 {
+            return Err(StorageError::NotFound);
+        }
+        // Every job may have finished already
+        finish_batch(&mut tx, &batch_id).await?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn batch_progress(&self, batch_id: &BatchId) -> StorageResult<Option<BatchProgress>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+            "SELECT total, done, failed, closed FROM apalis.batches WHERE id = $1 AND job_type = $2";
+        let progress: Option<(i64, i64, i64, bool)> = sqlx::query_as(query)
+            .bind(batch_id.to_string())
+            .bind(T::NAME)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(progress.map(|(total, done, failed, closed)| BatchProgress {
+            total,
+            done,
+            failed,
+            closed,
+        }))
+    }
+
+    async fn save_result(
+        &mut self,
+        job_id: String,
+        result: serde_json::Value,
+        retention: Duration,
+    ) -> StorageResult<()> {
+        let now = Utc::now();
+        let expires_at = now
+            + chrono::Duration::from_std(retention)
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Results past their retention are dropped as new ones are saved
+        sqlx::query(
+            "UPDATE apalis.jobs SET result = NULL, result_expires_at = NULL WHERE result_expires_at <= $1",
+        )
+        .bind(now)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let saved =
+            sqlx::query("UPDATE apalis.jobs SET result = $1, result_expires_at = $2 WHERE id = $3")
+                .bind(result)
+                .bind(expires_at)
+                .bind(&job_id)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+        if saved == 0 {
+            return Err(StorageError::NotFound);
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_result(&self, job_id: String) -> StorageResult<Option<serde_json::Value>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "SELECT result FROM apalis.jobs WHERE id = $1 AND result_expires_at > NOW()";
+        let result: Option<(serde_json::Value,)> = sqlx::query_as(query)
+            .bind(job_id)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(result.map(|(result,)| result))
+    }
+
+    async fn reenqueue_active(&mut self, worker_id: String) -> StorageResult<()> {
+        let pool = self.pool.clone();
+
+        let mut tx = pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+                "UPDATE apalis.jobs SET status = 'Pending', done_at = NULL, lock_by = NULL, lock_at = NULL WHERE lock_by = $1 AND status = 'Running'";
+        sqlx::query(query)
+            .bind(worker_id)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    fn consume(
+        &mut self,
+        worker_id: String,
+        interval: Duration,
+        buffer_size: usize,
+    ) -> JobStreamResult<T> {
+        Box::pin(self.stream_jobs(worker_id, interval, buffer_size))
     }
     async fn len(&self) -> StorageResult<i64> {
         let pool = self.pool.clone();
@@ -302,21 +800,57 @@ where
         let pool = self.pool.clone();
 
         let mut tx = pool
-            .acquire()
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Lock the blocked children first, so that acks of their other parents run one at a time
+        // and the last one sees every parent done
+        sqlx::query(
+            "SELECT id FROM apalis.jobs WHERE status = 'Blocked'
+                AND id IN (SELECT job_id FROM apalis.job_dependencies WHERE parent_id = $1)
+                ORDER BY id FOR UPDATE",
+        )
+        .bind(&job_id)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query =
-                "UPDATE apalis.jobs SET status = 'Done', done_at = now() WHERE id = $1 AND lock_by = $2";
-        sqlx::query(query)
+                "UPDATE apalis.jobs SET status = 'Done', done_at = now() WHERE id = $1 AND lock_by = $2 RETURNING batch_id";
+        let done: Option<(Option<String>,)> = sqlx::query_as(query)
             .bind(job_id.to_owned())
             .bind(worker_id.to_owned())
-            .execute(&mut tx)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        if let Some((batch_id,)) = done {
+            if let Some(batch_id) = batch_id {
+                count_in_batch(&mut tx, &batch_id, 1, 0).await?;
+            }
+            // Unblock the children whose parents are now all done
+            let query = "UPDATE apalis.jobs SET status = 'Pending'
+                WHERE status = 'Blocked'
+                    AND id IN (SELECT job_id FROM apalis.job_dependencies WHERE parent_id = $1)
+                    AND NOT EXISTS (SELECT 1 FROM apalis.job_dependencies d
+                        INNER JOIN apalis.jobs p ON p.id = d.parent_id
+                        WHERE d.job_id = apalis.jobs.id AND p.status != 'Done')";
+            sqlx::query(query)
+                .bind(job_id)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         Ok(())
     }
 
-    async fn reschedule(&mut self, job: &JobRequest<T>, wait: Duration) -> StorageResult<()> {
+    async fn reschedule(
+        &mut self,
+        _worker_id: String,
+        job: &JobRequest<T>,
+        wait: Duration,
+    ) -> StorageResult<()> {
         let pool = self.pool.clone();
         let job_id = job.id();
 
@@ -352,13 +886,18 @@ where
         let lock_by = job.lock_by().clone();
         let lock_at = *job.lock_at();
         let last_error = job.last_error().clone();
+        let last_failure = job
+            .last_failure()
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
 
         let mut tx = pool
             .acquire()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query =
-                "UPDATE apalis.jobs SET status = $1, attempts = $2, done_at = $3, lock_by = $4, lock_at = $5, last_error = $6 WHERE id = $7";
+                "UPDATE apalis.jobs SET status = $1, attempts = $2, done_at = $3, lock_by = $4, lock_at = $5, last_error = $6, last_failure = $7 WHERE id = $8";
         sqlx::query(query)
             .bind(status.to_owned())
             .bind(attempts)
@@ -366,6 +905,7 @@ where
             .bind(lock_by)
             .bind(lock_at)
             .bind(last_error)
+            .bind(last_failure)
             .bind(job_id.to_owned())
             .execute(&mut tx)
             .await
@@ -373,11 +913,160 @@ where
         Ok(())
     }
 
+    async fn update_progress(
+        &mut self,
+        job_id: String,
+        progress: &JobProgress,
+    ) -> StorageResult<()> {
+        let progress = serde_json::to_value(progress)?;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "UPDATE apalis.jobs SET progress = $1 WHERE id = $2";
+        let updated = sqlx::query(query)
+            .bind(progress)
+            .bind(job_id)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn append_logs(
+        &mut self,
+        job_id: String,
+        lines: &[JobLogLine],
+        max_lines: usize,
+    ) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        for line in lines {
+            sqlx::query("INSERT INTO apalis.job_logs (job_id, level, message, logged_at) VALUES ($1, $2, $3, $4)")
+                .bind(&job_id)
+                .bind(&line.level)
+                .bind(&line.message)
+                .bind(line.logged_at)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        }
+        // Only the last `max_lines` lines of the job are kept
+        let query = "DELETE FROM apalis.job_logs WHERE job_id = $1 AND id NOT IN (SELECT id FROM apalis.job_logs WHERE job_id = $1 ORDER BY id DESC LIMIT $2)";
+        sqlx::query(query)
+            .bind(&job_id)
+            .bind(max_lines as i64)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
     async fn keep_alive<Service>(&mut self, worker_id: String) -> StorageResult<()> {
         self.keep_alive_at::<Service>(worker_id, Utc::now()).await
     }
 }
 
+/// Puts a killed job back into the queue, see [DeadLetter]
+const REQUEUE_DEAD: &str = "status = 'Pending', attempts = 0, run_at = now(), done_at = NULL, lock_by = NULL, lock_at = NULL, unique_key = NULL, unique_until = NULL, batch_id = NULL, cancel_requested = FALSE";
+
+#[async_trait::async_trait]
+impl<T> DeadLetter for PostgresStorage<T>
+where
+    T: Job + Serialize + DeserializeOwned + Send + 'static + Unpin,
+{
+    async fn list_dead(&self, page: i32) -> StorageResult<Vec<JobRequest<T>>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "SELECT * FROM apalis.jobs WHERE status = 'Killed' AND job_type = $1 ORDER BY done_at DESC LIMIT 10 OFFSET $2";
+        let jobs: Vec<SqlJobRequest<T>> = sqlx::query_as(query)
+            .bind(T::NAME)
+            .bind(((page - 1) * 10) as i64)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(jobs.into_iter().map(Into::into).collect())
+    }
+
+    async fn requeue_dead(&mut self, job_ids: &[JobId]) -> StorageResult<u64> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = format!(
+            "UPDATE apalis.jobs SET {REQUEUE_DEAD} WHERE status = 'Killed' AND job_type = $1 AND id = ANY($2)"
+        );
+        let ids: Vec<String> = job_ids.iter().map(ToString::to_string).collect();
+        let requeued = sqlx::query(&query)
+            .bind(T::NAME)
+            .bind(ids)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(requeued)
+    }
+
+    async fn requeue_dead_matching(&mut self, filter: &DeadLetterFilter) -> StorageResult<u64> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = format!(
+            "UPDATE apalis.jobs SET {REQUEUE_DEAD} WHERE status = 'Killed' AND job_type = $1
+                AND ($2::text IS NULL OR strpos(last_error, $2) > 0)
+                AND ($3::timestamptz IS NULL OR done_at >= $3)
+                AND ($4::timestamptz IS NULL OR done_at < $4)"
+        );
+        let requeued = sqlx::query(&query)
+            .bind(T::NAME)
+            .bind(filter.error_contains())
+            .bind(filter.killed_after())
+            .bind(filter.killed_before())
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(requeued)
+    }
+
+    async fn purge_dead(&mut self, age: Duration) -> StorageResult<u64> {
+        let killed_before = Utc::now()
+            - chrono::Duration::from_std(age).map_err(|e| StorageError::Database(Box::from(e)))?;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+            "DELETE FROM apalis.jobs WHERE status = 'Killed' AND job_type = $1 AND done_at < $2";
+        let purged = sqlx::query(query)
+            .bind(T::NAME)
+            .bind(killed_before)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(purged)
+    }
+}
+
 #[async_trait::async_trait]
 
 impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for PostgresStorage<J> {
@@ -395,9 +1084,11 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for Postgr
                             COUNT(1) FILTER (WHERE status = 'Done') AS done,
                             COUNT(1) FILTER (WHERE status = 'Retry') AS retry, 
                             COUNT(1) FILTER (WHERE status = 'Failed') AS failed, 
-                            COUNT(1) FILTER (WHERE status = 'Killed') AS killed
+                            COUNT(1) FILTER (WHERE status = 'Killed') AS killed,
+                            COUNT(1) FILTER (WHERE status = 'Blocked') AS blocked,
+                            COUNT(1) FILTER (WHERE status = 'Cancelled') AS cancelled
                         FROM apalis.jobs WHERE job_type = $1";
-        let res: (i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
+        let res: (i64, i64, i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
             .bind(J::NAME)
             .fetch_one(&mut conn)
             .await
@@ -409,6 +1100,8 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for Postgr
         inner.insert(JobState::Retry, res.3);
         inner.insert(JobState::Failed, res.4);
         inner.insert(JobState::Killed, res.5);
+        inner.insert(JobState::Blocked, res.6);
+        inner.insert(JobState::Cancelled, res.7);
         Ok(Counts { inner })
     }
 
@@ -436,17 +1129,39 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for Postgr
         Ok(res.into_iter().map(|j| j.into()).collect())
     }
 
-    async fn list_workers(&mut self) -> Result<Vec<JobStreamWorker>, JobError> {
+    async fn job_logs(&mut self, job_id: String) -> Result<Vec<JobLogLine>, JobError> {
         let mut conn = self
             .pool
-            .clone()
             .acquire()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        let fetch_query =
-            "SELECT id, layers, last_seen FROM apalis.workers WHERE worker_type = $1 ORDER BY last_seen DESC LIMIT 20 OFFSET $2";
-        let res: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(fetch_query)
-            .bind(J::NAME)
+        let query = "SELECT level, message, logged_at FROM apalis.job_logs WHERE job_id = $1 ORDER BY id ASC";
+        let lines: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(query)
+            .bind(job_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(lines
+            .into_iter()
+            .map(|(level, message, logged_at)| JobLogLine {
+                level,
+                message,
+                logged_at,
+            })
+            .collect())
+    }
+
+    async fn list_workers(&mut self) -> Result<Vec<JobStreamWorker>, JobError> {
+        let mut conn = self
+            .pool
+            .clone()
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let fetch_query =
+            "SELECT id, layers, last_seen FROM apalis.workers WHERE worker_type = $1 ORDER BY last_seen DESC LIMIT 20 OFFSET $2";
+        let res: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(fetch_query)
+            .bind(J::NAME)
             .bind(0_i64)
             .fetch_all(&mut conn)
             .await
@@ -467,8 +1182,15 @@ mod tests {
     use std::ops::Sub;
 
     use super::*;
+    use apalis_core::context::JobContext;
+    use apalis_core::job_fn::job_fn;
+    use apalis_core::storage::AckLayer;
+    use apalis_core::worker::WorkerRef;
+    use chrono::SubsecRound;
     use email_service::Email;
     use futures::StreamExt;
+    use sqlx::types::Uuid;
+    use tower::{Layer, Service};
 
     /// migrate DB and return a storage instance.
     async fn setup() -> PostgresStorage<Email> {
@@ -485,7 +1207,7 @@ mod tests {
 
     /// rollback DB changes made by tests.
     /// Delete the following rows:
-    ///  - jobs whose state is `Pending` or locked by `worker_id`
+    ///  - jobs whose state is `Pending`, `Blocked` or locked by `worker_id`
     ///  - worker identified by `worker_id`
     ///
     /// You should execute this function in the end of a test
@@ -495,11 +1217,13 @@ mod tests {
             .acquire()
             .await
             .expect("failed to get connection");
-        sqlx::query("Delete from apalis.jobs where lock_by = $1 or status = 'Pending'")
-            .bind(worker_id.clone())
-            .execute(&mut tx)
-            .await
-            .expect("failed to delete jobs");
+        sqlx::query(
+            "Delete from apalis.jobs where lock_by = $1 or status IN ('Pending', 'Blocked', 'Cancelled')",
+        )
+        .bind(worker_id.clone())
+        .execute(&mut tx)
+        .await
+        .expect("failed to delete jobs");
         sqlx::query("Delete from apalis.workers where id = $1")
             .bind(worker_id.clone())
             .execute(&mut tx)
@@ -521,7 +1245,7 @@ mod tests {
     where
         S: Storage<Output = T>,
     {
-        let mut stream = storage.consume(worker_id, std::time::Duration::from_secs(10));
+        let mut stream = storage.consume(worker_id, std::time::Duration::from_secs(10), 1);
         stream
             .next()
             .await
@@ -581,6 +1305,72 @@ mod tests {
         cleanup(storage, worker_id).await;
     }
 
+    #[tokio::test]
+    async fn test_consume_prefers_higher_priority() {
+        let mut storage = setup().await;
+        let now = Utc::now().trunc_subsecs(0);
+        let mut ids = Vec::new();
+        // Pushed from the lowest to the highest expected position
+        for (priority, minutes) in [(0, 3), (5, 1), (5, 2), (10, 0)] {
+            let options = PushOptions::new()
+                .with_priority(priority)
+                .with_run_at(now.sub(chrono::Duration::minutes(minutes)));
+            let id = storage
+                .push_with(example_email(), options)
+                .await
+                .expect("failed to push a job");
+            ids.push(id.to_string());
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut stream = storage.consume(worker_id.clone(), std::time::Duration::from_secs(10), 4);
+        let mut consumed = Vec::new();
+        for _ in 0..4 {
+            let job = stream
+                .next()
+                .await
+                .expect("stream is empty")
+                .expect("failed to poll job")
+                .expect("no job is pending");
+            consumed.push(job.context().id());
+        }
+        ids.reverse();
+        assert_eq!(consumed, ids);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_consume_claims_whole_batch_at_once() {
+        let mut storage = setup().await;
+        for _ in 0..3 {
+            push_email(&mut storage, example_email()).await;
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut stream = storage.consume(worker_id.clone(), std::time::Duration::from_secs(10), 2);
+        let first = stream
+            .next()
+            .await
+            .expect("stream is empty")
+            .expect("failed to poll job")
+            .expect("no job is pending");
+        assert_eq!(*first.context().lock_by(), Some(worker_id.clone()));
+
+        let (claimed,): (i64,) = sqlx::query_as(
+            "SELECT count(*) FROM apalis.jobs WHERE status = 'Running' AND lock_by = $1",
+        )
+        .bind(worker_id.clone())
+        .fetch_one(&storage.pool)
+        .await
+        .expect("failed to count claimed jobs");
+        assert_eq!(claimed, 2);
+
+        cleanup(storage, worker_id).await;
+    }
+
     #[tokio::test]
     async fn test_acknowledge_job() {
         let mut storage = setup().await;
@@ -626,6 +1416,113 @@ mod tests {
         cleanup(storage, worker_id).await;
     }
 
+    #[tokio::test]
+    async fn test_dead_letter_requeues_and_purges_killed_jobs() {
+        let mut storage = setup().await;
+        let worker_id = register_worker(&mut storage).await;
+        let mut ids = Vec::new();
+        for error in ["payment gateway down", "invalid card"] {
+            storage
+                .push_with(example_email(), PushOptions::new().with_max_attempts(1))
+                .await
+                .expect("failed to push a job");
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            ids.push(job.context().id());
+            let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone())
+                .layer(job_fn(move |_: Email, _: JobContext| async move {
+                    Err::<(), _>(error)
+                }));
+            assert!(service.call(job).await.is_err());
+        }
+
+        let dead: Vec<String> = storage
+            .list_dead(1)
+            .await
+            .expect("failed to list dead jobs")
+            .iter()
+            .map(|job| job.context().id())
+            .collect();
+        assert!(ids.iter().all(|id| dead.contains(id)));
+
+        let filter = DeadLetterFilter::new()
+            .with_error_containing("payment gateway down")
+            .with_killed_after(Utc::now() - chrono::Duration::minutes(1));
+        let requeued = storage
+            .requeue_dead_matching(&filter)
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 1);
+        let job = get_job(&mut storage, ids[0].clone()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 0);
+
+        let card: JobId = ids[1].parse().unwrap();
+        let requeued = storage
+            .requeue_dead(&[card])
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 1);
+        let requeued = storage
+            .requeue_dead(&[card])
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 0);
+
+        // Only jobs killed long enough ago are purged
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+        storage
+            .kill(worker_id.clone(), job_id.clone())
+            .await
+            .expect("failed to kill job");
+        storage
+            .purge_dead(Duration::from_secs(3600))
+            .await
+            .expect("failed to purge dead jobs");
+        let mut job = get_job(&mut storage, job_id.clone()).await;
+        job.set_done_at(Some(Utc::now() - chrono::Duration::hours(2)));
+        storage
+            .update_by_id(job_id.clone(), &job)
+            .await
+            .expect("failed to update job");
+        let purged = storage
+            .purge_dead(Duration::from_secs(3600))
+            .await
+            .expect("failed to purge dead jobs");
+        assert!(purged >= 1);
+        assert!(storage
+            .fetch_by_id(job_id)
+            .await
+            .expect("failed to fetch job by id")
+            .is_none());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_reenqueue_active_jobs() {
+        let mut storage = setup().await;
+
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        storage
+            .reenqueue_active(worker_id.clone())
+            .await
+            .expect("failed to reenqueue active jobs");
+
+        let job = get_job(&mut storage, job_id.clone()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert!(job.context().lock_by().is_none());
+        assert!(job.context().lock_at().is_none());
+
+        cleanup(storage, worker_id).await;
+    }
+
     #[tokio::test]
     async fn test_heartbeat_renqueueorphaned_pulse_last_seen_6min() {
         let mut storage = setup().await;
@@ -637,7 +1534,10 @@ mod tests {
 
         let job = consume_one(&mut storage, worker_id.clone()).await;
         let result = storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .expect("failed to heartbeat");
         assert!(result);
@@ -668,7 +1568,10 @@ mod tests {
 
         let job = consume_one(&mut storage, worker_id.clone()).await;
         let result = storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .expect("failed to heartbeat");
         assert!(result);
@@ -681,4 +1584,542 @@ mod tests {
 
         cleanup(storage, worker_id).await;
     }
+
+    #[tokio::test]
+    async fn test_ack_layer_acknowledges_successful_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone()).layer(
+            job_fn(|_: Email, _: JobContext| async { Ok::<_, JobError>(()) }),
+        );
+        service.call(job).await.expect("job should succeed");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Done);
+        assert!(job.context().done_at().is_some());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_saves_job_result() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone())
+            .with_result_retention(Duration::from_secs(60))
+            .layer(job_fn(|_: Email, _: JobContext| async {
+                Ok::<_, JobError>("https://reports/1.pdf".to_string())
+            }));
+        service.call(job).await.expect("job should succeed");
+
+        let result = storage
+            .fetch_result(job_id.clone())
+            .await
+            .expect("failed to fetch result");
+        assert_eq!(result, Some(serde_json::json!("https://reports/1.pdf")));
+
+        // A result past its retention is no longer returned
+        storage
+            .save_result(job_id.clone(), serde_json::json!(null), Duration::ZERO)
+            .await
+            .expect("failed to save result");
+        let result = storage
+            .fetch_result(job_id)
+            .await
+            .expect("failed to fetch result");
+        assert!(result.is_none());
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_writes_job_progress() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone())
+            .with_progress_interval(Duration::from_secs(60))
+            .layer(job_fn(|_: Email, ctx: JobContext| async move {
+                let progress = ctx.progress_reporter();
+                progress.report(10, None).await;
+                progress.report(100, Some("sent".to_string())).await;
+                Ok::<_, JobError>(())
+            }));
+        service.call(job).await.expect("job should succeed");
+
+        let job = get_job(&mut storage, job_id).await;
+        let progress = job
+            .context()
+            .progress()
+            .clone()
+            .expect("progress should be saved");
+        assert_eq!(progress.percent, 100);
+        assert_eq!(progress.message.as_deref(), Some("sent"));
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_stores_job_logs() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone())
+            .with_job_logs(2)
+            .layer(job_fn(|_: Email, ctx: JobContext| async move {
+                ctx.logger().info("connecting");
+                ctx.logger().info("sending");
+                ctx.logger().warn("slow mailbox");
+                Ok::<_, JobError>(())
+            }));
+        service.call(job).await.expect("job should succeed");
+
+        let lines: Vec<(String, String)> = storage
+            .job_logs(job_id)
+            .await
+            .expect("failed to fetch logs")
+            .into_iter()
+            .map(|l| (l.level, l.message))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("INFO".to_string(), "sending".to_string()),
+                ("WARN".to_string(), "slow mailbox".to_string())
+            ]
+        );
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_retries_failed_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone()).layer(
+            job_fn(|_: Email, _: JobContext| async { Err::<(), _>("smtp unavailable") }),
+        );
+        assert!(service.call(job).await.is_err());
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 1);
+        assert_eq!(
+            *job.context().last_error(),
+            Some("Job Failed: smtp unavailable".to_string())
+        );
+        let failure = job
+            .context()
+            .last_failure()
+            .clone()
+            .expect("failure was not recorded");
+        assert_eq!(failure.attempt, 1);
+        assert_eq!(failure.message, "Job Failed: smtp unavailable");
+        assert_eq!(failure.chain, vec!["smtp unavailable".to_string()]);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_push_returns_job_id() {
+        let mut storage = setup().await;
+        let job_id = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+
+        let job = get_job(&mut storage, job_id.to_string()).await;
+        assert_eq!(job.context().id(), job_id.to_string());
+        assert_eq!(job.context().max_attempts(), 25);
+    }
+
+    #[tokio::test]
+    async fn test_push_with_options() {
+        let mut storage = setup().await;
+        let job_id = JobId::new();
+        let run_at = Utc::now().add(chrono::Duration::hours(1)).trunc_subsecs(0);
+        let options = PushOptions::new()
+            .with_id(job_id)
+            .with_max_attempts(3)
+            .with_run_at(run_at)
+            .with_timeout(Duration::from_millis(1500))
+            .with_priority(7);
+        let pushed = storage
+            .push_with(example_email(), options)
+            .await
+            .expect("failed to push a job");
+        assert_eq!(pushed, job_id);
+
+        let job = get_job(&mut storage, job_id.to_string()).await;
+        assert_eq!(job.context().max_attempts(), 3);
+        assert_eq!(*job.context().run_at(), run_at);
+        assert_eq!(job.context().timeout(), Some(Duration::from_millis(1500)));
+        assert_eq!(job.context().priority(), 7);
+
+        cleanup(storage, String::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_push_with_unique_key() {
+        let mut storage = setup().await;
+        let key = Uuid::new_v4().to_string();
+        let unique = |policy| PushOptions::new().with_unique_key(key.clone(), policy);
+
+        let first = storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+        match storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, first),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+        let ignored = storage
+            .push_with(example_email(), unique(UniquePolicy::Ignore))
+            .await
+            .expect("failed to push a job");
+        assert_eq!(ignored, first);
+
+        // The pending job is replaced
+        let replaced = storage
+            .push_with(example_email(), unique(UniquePolicy::Replace))
+            .await
+            .expect("failed to push a job");
+        assert_ne!(replaced, first);
+        assert!(storage
+            .fetch_by_id(first.to_string())
+            .await
+            .expect("failed to fetch job")
+            .is_none());
+
+        // A running job is not
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), replaced.to_string());
+        assert!(matches!(
+            storage
+                .push_with(example_email(), unique(UniquePolicy::Replace))
+                .await,
+            Err(StorageError::Duplicate(_))
+        ));
+
+        // The key is released once the job is done
+        storage
+            .ack(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to ack job");
+        storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_unique_key_is_released_after_ttl() {
+        let mut storage = setup().await;
+        let options = PushOptions::new()
+            .with_unique_key(Uuid::new_v4().to_string(), UniquePolicy::Reject)
+            .with_unique_ttl(std::time::Duration::ZERO);
+
+        storage
+            .push_with(example_email(), options.clone())
+            .await
+            .expect("failed to push a job");
+        storage
+            .push_with(example_email(), options)
+            .await
+            .expect("the expired key should be free");
+
+        cleanup(storage, String::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_child_job_runs_after_parents_are_done() {
+        let mut storage = setup().await;
+        let first = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let second = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(
+                example_email(),
+                PushOptions::new().with_parents([first, second]),
+            )
+            .await
+            .expect("failed to push a job");
+        let job = get_job(&mut storage, child.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Blocked);
+
+        let worker_id = register_worker(&mut storage).await;
+        for parent in [first, second] {
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            assert_eq!(job.context().id(), parent.to_string());
+            storage
+                .ack(worker_id.clone(), job.context().id())
+                .await
+                .expect("failed to ack job");
+        }
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), child.to_string());
+
+        // Parents that are already done do not block
+        let unblocked = storage
+            .push_with(example_email(), PushOptions::new().with_parents([first]))
+            .await
+            .expect("failed to push a job");
+        let job = get_job(&mut storage, unblocked.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_unique_job_with_dependents_is_not_replaced() {
+        let mut storage = setup().await;
+        let key = Uuid::new_v4().to_string();
+        let unique = |policy| PushOptions::new().with_unique_key(key.clone(), policy);
+        let parent = storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+
+        // Replacing the parent would leave its child blocked forever
+        match storage
+            .push_with(example_email(), unique(UniquePolicy::Replace))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, parent),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+        let job = get_job(&mut storage, child.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Blocked);
+        get_job(&mut storage, parent.to_string()).await;
+
+        cleanup(storage, String::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_child_job_is_killed_with_parent() {
+        let mut storage = setup().await;
+        let parent = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+        let grandchild = storage
+            .push_with(example_email(), PushOptions::new().with_parents([child]))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), parent.to_string());
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+
+        for id in [child, grandchild] {
+            let job = get_job(&mut storage, id.to_string()).await;
+            assert_eq!(*job.context().status(), JobState::Killed);
+        }
+        assert!(matches!(
+            storage
+                .push_with(
+                    example_email(),
+                    PushOptions::new().with_parents([JobId::new()])
+                )
+                .await,
+            Err(StorageError::NotFound)
+        ));
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_job_with_children() {
+        let mut storage = setup().await;
+        let worker_id = register_worker(&mut storage).await;
+        let parent = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), parent.to_string());
+
+        storage
+            .cancel(parent.to_string())
+            .await
+            .expect("failed to cancel job");
+        let job = get_job(&mut storage, parent.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Running);
+        let cancelled = storage
+            .fetch_cancelled(worker_id.clone())
+            .await
+            .expect("failed to fetch cancelled jobs");
+        assert_eq!(cancelled, vec![parent.to_string()]);
+
+        storage
+            .abort(worker_id.clone(), parent.to_string())
+            .await
+            .expect("failed to abort job");
+        for id in [parent, child] {
+            let job = get_job(&mut storage, id.to_string()).await;
+            assert_eq!(*job.context().status(), JobState::Cancelled);
+        }
+        assert!(matches!(
+            storage.cancel(child.to_string()).await,
+            Err(StorageError::NotFound)
+        ));
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_pushes_success_callback() {
+        let mut storage = setup().await;
+        let callbacks = BatchCallbacks::new()
+            .with_on_success(example_email())
+            .with_on_failure(example_email());
+        let batch_id = storage
+            .open_batch(callbacks)
+            .await
+            .expect("failed to open batch");
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let id = storage
+                .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+                .await
+                .expect("failed to push a job");
+            ids.push(id.to_string());
+        }
+        storage
+            .close_batch(&batch_id)
+            .await
+            .expect("failed to close batch");
+        assert!(matches!(
+            storage
+                .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+                .await,
+            Err(StorageError::NotFound)
+        ));
+
+        let worker_id = register_worker(&mut storage).await;
+        for done in 1..=2 {
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            assert!(ids.contains(&job.context().id()));
+            storage
+                .ack(worker_id.clone(), job.context().id())
+                .await
+                .expect("failed to ack job");
+            let progress = storage
+                .batch_progress(&batch_id)
+                .await
+                .expect("failed to fetch progress")
+                .expect("no batch found");
+            assert_eq!(progress.total, 2);
+            assert_eq!(progress.done, done);
+            assert_eq!(progress.pending(), 2 - done);
+        }
+
+        // Only the success callback is left
+        let counts = storage.counts().await.expect("failed to count jobs");
+        assert_eq!(counts.inner[&JobState::Blocked], 0);
+        assert_eq!(counts.inner[&JobState::Pending], 1);
+
+        cleanup(storage, worker_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_pushes_failure_callback() {
+        let mut storage = setup().await;
+        let callbacks = BatchCallbacks::new().with_on_success(example_email());
+        let batch_id = storage
+            .open_batch(callbacks)
+            .await
+            .expect("failed to open batch");
+        storage
+            .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+        let progress = storage
+            .batch_progress(&batch_id)
+            .await
+            .expect("failed to fetch progress")
+            .expect("no batch found");
+        assert_eq!(progress.failed, 1);
+        assert!(!progress.is_finished());
+
+        storage
+            .close_batch(&batch_id)
+            .await
+            .expect("failed to close batch");
+        let progress = storage
+            .batch_progress(&batch_id)
+            .await
+            .expect("failed to fetch progress")
+            .expect("no batch found");
+        assert!(progress.is_finished());
+        // There is no failure callback, and the success callback is discarded
+        let counts = storage.counts().await.expect("failed to count jobs");
+        assert_eq!(counts.inner[&JobState::Blocked], 0);
+        assert_eq!(counts.inner[&JobState::Pending], 0);
+
+        cleanup(storage, worker_id).await;
+    }
 }
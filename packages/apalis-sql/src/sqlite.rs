@@ -1,16 +1,20 @@
 use crate::from_row::IntoJobRequest;
+use apalis_core::context::{JobLogLine, JobProgress};
 use apalis_core::error::{JobError, JobStreamError};
-use apalis_core::job::{Counts, Job, JobStreamExt, JobStreamResult, JobStreamWorker};
+use apalis_core::job::{Counts, Job, JobId, JobStreamExt, JobStreamResult, JobStreamWorker};
 use apalis_core::request::{JobRequest, JobState};
 use apalis_core::storage::StorageError;
 use apalis_core::storage::StorageWorkerPulse;
-use apalis_core::storage::{Storage, StorageResult};
+use apalis_core::storage::{
+    BatchCallbacks, BatchId, BatchProgress, DeadLetter, DeadLetterFilter, PushOptions, Storage,
+    StorageResult, UniquePolicy,
+};
 use async_stream::try_stream;
 use chrono::{DateTime, Utc};
 use futures::Stream;
 use serde::{de::DeserializeOwned, Serialize};
-use sqlx::types::Uuid;
-use sqlx::{Pool, Row, Sqlite, SqlitePool};
+use sqlx::{Pool, Row, Sqlite, SqlitePool, Transaction};
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ops::Sub;
@@ -98,65 +102,244 @@ impl<T: Job> SqliteStorage<T> {
     }
 }
 
-async fn fetch_next<T>(
-    pool: Pool<Sqlite>,
-    worker_id: String,
-    job: Option<JobRequest<T>>,
-) -> Result<Option<JobRequest<T>>, JobStreamError>
-where
-    T: Send + Unpin + DeserializeOwned,
-{
-    match job {
-        None => Ok(None),
-        Some(job) => {
-            let mut tx = pool
-                .begin()
-                .await
-                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-            let job_id = job.id();
-            let update_query = "UPDATE Jobs SET status = 'Running', lock_by = ?2, lock_at = ?3 WHERE id = ?1 AND status = 'Pending' AND lock_by IS NULL; Select * from Jobs where id = ?1 AND lock_by = ?2";
-            let job: Option<SqlJobRequest<T>> = sqlx::query_as(update_query)
-                .bind(job_id.clone())
-                .bind(worker_id)
-                .bind(Utc::now().timestamp())
-                .fetch_optional(&mut tx)
-                .await
-                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-            tx.commit()
-                .await
-                .map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-            Ok(job.build_job_request())
-        }
-    }
-}
-
 impl<T: DeserializeOwned + Send + Unpin + Job> SqliteStorage<T> {
     fn stream_jobs(
         &self,
         worker_id: String,
         interval: Duration,
+        buffer_size: usize,
     ) -> impl Stream<Item = Result<Option<JobRequest<T>>, JobStreamError>> {
         let pool = self.pool.clone();
         let mut interval = tokio::time::interval(interval);
         try_stream! {
             loop {
                 interval.tick().await;
-                let tx = pool.clone();
-                let mut tx = tx.acquire().await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
+                let mut conn = pool.acquire().await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
                 let job_type = T::NAME;
-                let fetch_query = "SELECT * FROM Jobs
-                    WHERE rowid = (SELECT min(rowid) FROM Jobs
-                    WHERE status = 'Pending' OR (status = 'Failed' AND attempts < max_attempts) AND run_at < ?1 AND job_type = ?2)";
-                let job: Option<SqlJobRequest<T>> = sqlx::query_as(fetch_query)
+                // Claim the whole batch in a single statement so that no other worker can take any of it
+                let fetch_query = "UPDATE Jobs SET status = 'Running', lock_by = ?2, lock_at = ?1
+                    WHERE id IN (SELECT id FROM Jobs
+                        WHERE status = 'Pending' AND lock_by IS NULL AND run_at <= ?1 AND job_type = ?3
+                        ORDER BY priority DESC, run_at ASC, rowid ASC LIMIT ?4)
+                    RETURNING *";
+                let jobs: Vec<SqlJobRequest<T>> = sqlx::query_as(fetch_query)
                     .bind(Utc::now().timestamp())
+                    .bind(worker_id.clone())
                     .bind(job_type)
-                    .fetch_optional(&mut tx)
+                    .bind(i64::try_from(buffer_size).unwrap_or(i64::MAX))
+                    .fetch_all(&mut conn)
                     .await.map_err(|e| JobStreamError::BrokenPipe(Box::from(e)))?;
-                yield fetch_next(pool.clone(), worker_id.clone(), job.build_job_request()).await?
+                if jobs.is_empty() {
+                    yield None;
+                }
+                // RETURNING does not keep the order of the subquery
+                let mut jobs: Vec<JobRequest<T>> = jobs.into_iter().map(Into::into).collect();
+                jobs.sort_by_key(|job| (Reverse(job.priority()), *job.run_at()));
+                for job in jobs {
+                    yield Some(job);
+                }
+            }
+        }
+    }
+}
+/// Makes sure `key` is free for a new job of `job_type`, following `policy`.
+/// Returns the job holding the key when the push should be skipped instead
+async fn acquire_unique_key(
+    tx: &mut Transaction<'_, Sqlite>,
+    job_type: &str,
+    key: &str,
+    policy: UniquePolicy,
+) -> StorageResult<Option<JobId>> {
+    // Writing first takes the database lock, so racing pushes are serialized
+    sqlx::query(
+        "UPDATE Jobs SET unique_key = NULL WHERE job_type = ?1 AND unique_key = ?2 AND unique_until <= ?3",
+    )
+    .bind(job_type)
+    .bind(key)
+    .bind(Utc::now().timestamp())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let holder: Option<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, status, batch_id FROM Jobs WHERE job_type = ?1 AND unique_key = ?2 AND status NOT IN ('Done', 'Killed', 'Cancelled')",
+    )
+    .bind(job_type)
+    .bind(key)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let (holder, status, batch_id) = match holder {
+        Some(holder) => holder,
+        None => return Ok(None),
+    };
+    let holder_id = holder
+        .parse()
+        .map_err(|e| StorageError::Database(Box::new(e)))?;
+    let (dependents,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM JobDependencies WHERE parent_id = ?1")
+            .bind(&holder)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    match policy {
+        UniquePolicy::Ignore => Ok(Some(holder_id)),
+        // Jobs of a batch are counted by it, and jobs waiting on the holder would never run,
+        // so those are never replaced
+        UniquePolicy::Replace
+            if status != JobState::Running.as_ref() && batch_id.is_none() && dependents == 0 =>
+        {
+            sqlx::query("DELETE FROM Jobs WHERE id = ?1")
+                .bind(holder)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            Ok(None)
+        }
+        _ => Err(StorageError::Duplicate(holder_id)),
+    }
+}
+
+/// Records that `job_id` depends on `parents` and blocks or kills it accordingly,
+/// returning the state of the job.
+/// Must run after the job was inserted, within the same transaction
+async fn add_dependencies(
+    tx: &mut Transaction<'_, Sqlite>,
+    job_id: &JobId,
+    parents: &[JobId],
+) -> StorageResult<JobState> {
+    let job_id = job_id.to_string();
+    let mut state = JobState::Pending;
+    for parent in parents {
+        let parent = parent.to_string();
+        sqlx::query("INSERT OR IGNORE INTO JobDependencies (job_id, parent_id) VALUES (?1, ?2)")
+            .bind(&job_id)
+            .bind(&parent)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let status: Option<(String,)> = sqlx::query_as("SELECT status FROM Jobs WHERE id = ?1")
+            .bind(parent)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        match status {
+            None => return Err(StorageError::NotFound),
+            Some((status,)) if status == JobState::Killed.as_ref() => state = JobState::Killed,
+            Some((status,))
+                if status == JobState::Cancelled.as_ref() && state != JobState::Killed =>
+            {
+                state = JobState::Cancelled
+            }
+            Some((status,)) if status != JobState::Done.as_ref() && state == JobState::Pending => {
+                state = JobState::Blocked
             }
+            _ => {}
         }
     }
+    let query = match state {
+        JobState::Pending => return Ok(state),
+        JobState::Killed => "UPDATE Jobs SET status = 'Killed', done_at = strftime('%s','now'), last_error = 'A parent job was killed' WHERE id = ?1",
+        JobState::Cancelled => "UPDATE Jobs SET status = 'Cancelled', done_at = strftime('%s','now'), last_error = 'A parent job was cancelled' WHERE id = ?1",
+        _ => "UPDATE Jobs SET status = 'Blocked' WHERE id = ?1",
+    };
+    sqlx::query(query)
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    Ok(state)
+}
+
+/// Gives every job waiting on `job_id`, directly or not, the `status` it finished with since
+/// they can never run, then counts them all as failed in their batches along with `job_id`
+async fn finish_dependents(
+    tx: &mut Transaction<'_, Sqlite>,
+    job_id: &str,
+    batch_id: Option<String>,
+    status: &JobState,
+) -> StorageResult<()> {
+    let query = "WITH RECURSIVE Descendants(id) AS (
+            SELECT job_id FROM JobDependencies WHERE parent_id = ?1
+            UNION SELECT JobDependencies.job_id FROM JobDependencies
+                INNER JOIN Descendants ON JobDependencies.parent_id = Descendants.id
+        )
+        UPDATE Jobs SET status = ?2, done_at = strftime('%s','now'), last_error = ?3
+        WHERE status = 'Blocked' AND id IN Descendants
+        RETURNING batch_id";
+    let descendants: Vec<(Option<String>,)> = sqlx::query_as(query)
+        .bind(job_id)
+        .bind(status.as_ref())
+        .bind(format!(
+            "A parent job was {}",
+            status.as_ref().to_lowercase()
+        ))
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let mut failed: HashMap<String, i64> = HashMap::new();
+    for batch_id in std::iter::once(batch_id)
+        .chain(descendants.into_iter().map(|(batch_id,)| batch_id))
+        .flatten()
+    {
+        *failed.entry(batch_id).or_default() += 1;
+    }
+    for (batch_id, failed) in failed {
+        count_in_batch(tx, &batch_id, 0, failed).await?;
+    }
+    Ok(())
+}
+
+/// Counts finished jobs towards `batch_id`
+async fn count_in_batch(
+    tx: &mut Transaction<'_, Sqlite>,
+    batch_id: &str,
+    done: i64,
+    failed: i64,
+) -> StorageResult<()> {
+    sqlx::query("UPDATE Batches SET done = done + ?2, failed = failed + ?3 WHERE id = ?1")
+        .bind(batch_id)
+        .bind(done)
+        .bind(failed)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    finish_batch(tx, batch_id).await
+}
+
+/// Releases the callback of `batch_id` if it is closed and every job in it has finished.
+/// The callback that does not apply is deleted
+async fn finish_batch(tx: &mut Transaction<'_, Sqlite>, batch_id: &str) -> StorageResult<()> {
+    let query = "UPDATE Batches SET finished_at = strftime('%s','now')
+        WHERE id = ?1 AND closed = 1 AND finished_at IS NULL AND done + failed >= total
+        RETURNING on_success, on_failure, failed";
+    let batch: Option<(Option<String>, Option<String>, i64)> = sqlx::query_as(query)
+        .bind(batch_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+    let (callback, discarded) = match batch {
+        Some((on_success, on_failure, 0)) => (on_success, on_failure),
+        Some((on_success, on_failure, _)) => (on_failure, on_success),
+        None => return Ok(()),
+    };
+    if let Some(callback) = callback {
+        sqlx::query("UPDATE Jobs SET status = 'Pending', run_at = ?2 WHERE id = ?1")
+            .bind(callback)
+            .bind(Utc::now().timestamp())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    if let Some(discarded) = discarded {
+        sqlx::query("DELETE FROM Jobs WHERE id = ?1")
+            .bind(discarded)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+    }
+    Ok(())
 }
+
 #[async_trait::async_trait]
 impl<T> Storage for SqliteStorage<T>
 where
@@ -164,52 +347,68 @@ where
 {
     type Output = T;
 
-    async fn push(&mut self, job: Self::Output) -> StorageResult<()> {
-        let id = Uuid::new_v4();
-        let query = "INSERT INTO Jobs VALUES (?1, ?2, ?3, 'Pending', 0, 25, strftime('%s','now'), NULL, NULL, NULL, NULL)";
+    async fn push_with(&mut self, job: Self::Output, options: PushOptions) -> StorageResult<JobId> {
+        let id = options.id().copied().unwrap_or_default();
+        let run_at = options.run_at().copied().unwrap_or_else(Utc::now);
+        let query = "INSERT INTO Jobs (job, id, job_type, status, attempts, max_attempts, run_at, timeout_ms, priority, unique_key, unique_until, batch_id) VALUES (?1, ?2, ?3, 'Pending', 0, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
         let pool = self.pool.clone();
 
         let job = serde_json::to_string(&job)?;
-        let mut pool = pool
-            .acquire()
+        let mut tx = pool
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let job_type = T::NAME;
+        if let Some(key) = options.unique_key() {
+            if let Some(holder) =
+                acquire_unique_key(&mut tx, job_type, key, options.unique_policy()).await?
+            {
+                return Ok(holder);
+            }
+        }
+        let unique_until = options
+            .unique_ttl()
+            .map(|ttl| Utc::now().timestamp() + ttl.as_secs() as i64);
         sqlx::query(query)
             .bind(job)
             .bind(id.to_string())
             .bind(job_type.to_string())
-            .execute(&mut pool)
-            .await
-            .map_err(|e| StorageError::Database(Box::from(e)))?;
-        Ok(())
-    }
-
-    async fn schedule(
-        &mut self,
-        job: Self::Output,
-        on: chrono::DateTime<Utc>,
-    ) -> StorageResult<()> {
-        let query =
-            "INSERT INTO Jobs VALUES (?1, ?2, ?3, 'Pending', 0, 25, ?4, NULL, NULL, NULL, NULL)";
-        let pool = self.pool.clone();
-        let id = Uuid::new_v4();
-
-        let job = serde_json::to_string(&job)?;
-        let mut pool = pool
-            .acquire()
+            .bind(options.max_attempts())
+            .bind(run_at.timestamp())
+            .bind(options.timeout().map(|timeout| timeout.as_millis() as i64))
+            .bind(options.priority())
+            .bind(options.unique_key())
+            .bind(unique_until)
+            .bind(options.batch().map(ToString::to_string))
+            .execute(&mut tx)
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        let job_type = T::NAME;
-        sqlx::query(query)
-            .bind(job)
-            .bind(id.to_string())
-            .bind(job_type)
-            .bind(on.timestamp())
-            .execute(&mut pool)
+        let state = match options.parents() {
+            [] => JobState::Pending,
+            parents => add_dependencies(&mut tx, &id, parents).await?,
+        };
+        if let Some(batch_id) = options.batch() {
+            let query = "UPDATE Batches SET total = total + 1, failed = failed + ?3
+                WHERE id = ?1 AND job_type = ?2 AND closed = 0";
+            let added = sqlx::query(query)
+                .bind(batch_id.to_string())
+                .bind(job_type)
+                .bind(i64::from(matches!(
+                    state,
+                    JobState::Killed | JobState::Cancelled
+                )))
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+            if added == 0 {
+                return Err(StorageError::NotFound);
+            }
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
-        Ok(())
+        Ok(id)
     }
 
     async fn fetch_by_id(&self, job_id: String) -> StorageResult<Option<JobRequest<Self::Output>>> {
@@ -245,17 +444,24 @@ where
                             WHERE id in 
                                 (SELECT Jobs.id from Jobs 
                                     WHERE status= "Failed" AND Jobs.attempts < Jobs.max_attempts
+                                     AND job_type = ?1 AND run_at <= ?3
                                      ORDER BY lock_at ASC LIMIT ?2);"#;
                 sqlx::query(query)
                     .bind(job_type)
                     .bind(count)
+                    .bind(Utc::now().timestamp())
                     .execute(&mut tx)
                     .await
                     .map_err(|e| StorageError::Database(Box::from(e)))?;
                 Ok(true)
             }
-            // Worker not seen in 5 minutes yet has running jobs
-            StorageWorkerPulse::RenqueueOrpharned { count } => {
+            // Worker not seen within `timeout_worker` yet has running jobs
+            StorageWorkerPulse::RenqueueOrpharned {
+                count,
+                timeout_worker,
+            } => {
+                let timeout_worker = chrono::Duration::from_std(timeout_worker)
+                    .map_err(|e| StorageError::Database(Box::from(e)))?;
                 let job_type = T::NAME;
                 let mut tx = pool
                     .acquire()
@@ -268,7 +474,7 @@ where
                                     WHERE status= "Running" AND workers.last_seen < ?1
                                     AND Workers.worker_type = ?2 ORDER BY lock_at ASC LIMIT ?3);"#;
                 sqlx::query(query)
-                    .bind(Utc::now().sub(chrono::Duration::minutes(5)).timestamp())
+                    .bind(Utc::now().sub(timeout_worker).timestamp())
                     .bind(job_type)
                     .bind(count)
                     .execute(&mut tx)
@@ -284,15 +490,98 @@ where
         let pool = self.pool.clone();
 
         let mut tx = pool
-            .acquire()
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query =
-                "UPDATE Jobs SET status = 'Killed', done_at = strftime('%s','now') WHERE id = ?1 AND lock_by = ?2";
-        sqlx::query(query)
+                "UPDATE Jobs SET status = 'Killed', done_at = strftime('%s','now') WHERE id = ?1 AND lock_by = ?2 RETURNING batch_id";
+        let killed: Option<(Option<String>,)> = sqlx::query_as(query)
             .bind(job_id.to_owned())
             .bind(worker_id.to_owned())
-            .execute(&mut tx)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        if let Some((batch_id,)) = killed {
+            finish_dependents(&mut tx, &job_id, batch_id, &JobState::Killed).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn cancel(&mut self, job_id: String) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "UPDATE Jobs SET status = 'Cancelled', done_at = strftime('%s','now'), lock_by = NULL, lock_at = NULL
+            WHERE id = ?1 AND status IN ('Pending', 'Retry', 'Failed', 'Blocked')
+            RETURNING batch_id";
+        let cancelled: Option<(Option<String>,)> = sqlx::query_as(query)
+            .bind(&job_id)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        match cancelled {
+            Some((batch_id,)) => {
+                finish_dependents(&mut tx, &job_id, batch_id, &JobState::Cancelled).await?
+            }
+            None => {
+                // A running job is left to its worker
+                let query =
+                    "UPDATE Jobs SET cancel_requested = 1 WHERE id = ?1 AND status = 'Running'";
+                let flagged = sqlx::query(query)
+                    .bind(&job_id)
+                    .execute(&mut tx)
+                    .await
+                    .map_err(|e| StorageError::Database(Box::from(e)))?
+                    .rows_affected();
+                if flagged == 0 {
+                    return Err(StorageError::NotFound);
+                }
+            }
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_cancelled(&self, worker_id: String) -> StorageResult<Vec<String>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "SELECT id FROM Jobs WHERE lock_by = ?1 AND status = 'Running' AND cancel_requested = 1";
+        let ids: Vec<(String,)> = sqlx::query_as(query)
+            .bind(worker_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn abort(&mut self, worker_id: String, job_id: String) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+                "UPDATE Jobs SET status = 'Cancelled', done_at = strftime('%s','now') WHERE id = ?1 AND lock_by = ?2 RETURNING batch_id";
+        let aborted: Option<(Option<String>,)> = sqlx::query_as(query)
+            .bind(&job_id)
+            .bind(worker_id)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        if let Some((batch_id,)) = aborted {
+            finish_dependents(&mut tx, &job_id, batch_id, &JobState::Cancelled).await?;
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         Ok(())
@@ -318,8 +607,179 @@ where
         Ok(())
     }
 
-    fn consume(&mut self, worker_id: String, interval: Duration) -> JobStreamResult<T> {
-        Box::pin(self.stream_jobs(worker_id, interval))
+    async fn reenqueue_active(&mut self, worker_id: String) -> StorageResult<()> {
+        let pool = self.pool.clone();
+
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+                "UPDATE Jobs SET status = 'Pending', done_at = NULL, lock_by = NULL, lock_at = NULL WHERE lock_by = ?1 AND status = 'Running'";
+        sqlx::query(query)
+            .bind(worker_id)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn open_batch(&mut self, callbacks: BatchCallbacks<T>) -> StorageResult<BatchId> {
+        let batch_id = BatchId::new();
+        let job_type = T::NAME;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Callbacks wait as blocked jobs until the batch finishes
+        let mut callback_ids = Vec::new();
+        for callback in [callbacks.on_success(), callbacks.on_failure()] {
+            let callback = match callback {
+                Some(callback) => callback,
+                None => {
+                    callback_ids.push(None);
+                    continue;
+                }
+            };
+            let id = JobId::new().to_string();
+            sqlx::query("INSERT INTO Jobs (job, id, job_type, status, run_at) VALUES (?1, ?2, ?3, 'Blocked', ?4)")
+                .bind(serde_json::to_string(callback)?)
+                .bind(&id)
+                .bind(job_type)
+                .bind(Utc::now().timestamp())
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+            callback_ids.push(Some(id));
+        }
+        sqlx::query(
+            "INSERT INTO Batches (id, job_type, on_success, on_failure) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(batch_id.to_string())
+        .bind(job_type)
+        .bind(&callback_ids[0])
+        .bind(&callback_ids[1])
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(batch_id)
+    }
+
+    async fn close_batch(&mut self, batch_id: &BatchId) -> StorageResult<()> {
+        let batch_id = batch_id.to_string();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let closed = sqlx::query("UPDATE Batches SET closed = 1 WHERE id = ?1 AND job_type = ?2")
+            .bind(&batch_id)
+            .bind(T::NAME)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if closed == 0 {
+            return Err(StorageError::NotFound);
+        }
+        // Every job may have finished already
+        finish_batch(&mut tx, &batch_id).await?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn batch_progress(&self, batch_id: &BatchId) -> StorageResult<Option<BatchProgress>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+            "SELECT total, done, failed, closed FROM Batches WHERE id = ?1 AND job_type = ?2";
+        let progress: Option<(i64, i64, i64, bool)> = sqlx::query_as(query)
+            .bind(batch_id.to_string())
+            .bind(T::NAME)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(progress.map(|(total, done, failed, closed)| BatchProgress {
+            total,
+            done,
+            failed,
+            closed,
+        }))
+    }
+
+    async fn save_result(
+        &mut self,
+        job_id: String,
+        result: serde_json::Value,
+        retention: Duration,
+    ) -> StorageResult<()> {
+        let now = Utc::now();
+        let expires_at = now
+            + chrono::Duration::from_std(retention)
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        // Results past their retention are dropped as new ones are saved
+        sqlx::query(
+            "UPDATE Jobs SET result = NULL, result_expires_at = NULL WHERE result_expires_at <= ?1",
+        )
+        .bind(now.timestamp())
+        .execute(&mut tx)
+        .await
+        .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let saved =
+            sqlx::query("UPDATE Jobs SET result = ?1, result_expires_at = ?2 WHERE id = ?3")
+                .bind(serde_json::to_string(&result)?)
+                .bind(expires_at.timestamp())
+                .bind(&job_id)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?
+                .rows_affected();
+        if saved == 0 {
+            return Err(StorageError::NotFound);
+        }
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
+    async fn fetch_result(&self, job_id: String) -> StorageResult<Option<serde_json::Value>> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "SELECT result FROM Jobs WHERE id = ?1 AND result_expires_at > ?2";
+        let result: Option<(serde_json::Value,)> = sqlx::query_as(query)
+            .bind(job_id)
+            .bind(Utc::now().timestamp())
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(result.map(|(result,)| result))
+    }
+
+    fn consume(
+        &mut self,
+        worker_id: String,
+        interval: Duration,
+        buffer_size: usize,
+    ) -> JobStreamResult<T> {
+        Box::pin(self.stream_jobs(worker_id, interval, buffer_size))
     }
     async fn len(&self) -> StorageResult<i64> {
         let pool = self.pool.clone();
@@ -341,21 +801,46 @@ where
         let pool = self.pool.clone();
 
         let mut tx = pool
-            .acquire()
+            .begin()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query =
-                "UPDATE Jobs SET status = 'Done', done_at = strftime('%s','now') WHERE id = ?1 AND lock_by = ?2";
-        sqlx::query(query)
+                "UPDATE Jobs SET status = 'Done', done_at = strftime('%s','now') WHERE id = ?1 AND lock_by = ?2 RETURNING batch_id";
+        let done: Option<(Option<String>,)> = sqlx::query_as(query)
             .bind(job_id.to_owned())
             .bind(worker_id.to_owned())
-            .execute(&mut tx)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        if let Some((batch_id,)) = done {
+            if let Some(batch_id) = batch_id {
+                count_in_batch(&mut tx, &batch_id, 1, 0).await?;
+            }
+            // Unblock the children whose parents are now all done
+            let query = "UPDATE Jobs SET status = 'Pending'
+                WHERE status = 'Blocked'
+                    AND id IN (SELECT job_id FROM JobDependencies WHERE parent_id = ?1)
+                    AND NOT EXISTS (SELECT 1 FROM JobDependencies
+                        INNER JOIN Jobs AS Parents ON Parents.id = JobDependencies.parent_id
+                        WHERE JobDependencies.job_id = Jobs.id AND Parents.status != 'Done')";
+            sqlx::query(query)
+                .bind(job_id)
+                .execute(&mut tx)
+                .await
+                .map_err(|e| StorageError::Database(Box::from(e)))?;
+        }
+        tx.commit()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         Ok(())
     }
 
-    async fn reschedule(&mut self, job: &JobRequest<T>, wait: Duration) -> StorageResult<()> {
+    async fn reschedule(
+        &mut self,
+        _worker_id: String,
+        job: &JobRequest<T>,
+        wait: Duration,
+    ) -> StorageResult<()> {
         let pool = self.pool.clone();
         let job_id = job.id();
 
@@ -391,13 +876,18 @@ where
         let lock_by = job.lock_by().clone();
         let lock_at = (*job.lock_at()).map(|v| v.timestamp());
         let last_error = job.last_error().clone();
+        let last_failure = job
+            .last_failure()
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
 
         let mut tx = pool
             .acquire()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
         let query =
-                "UPDATE Jobs SET status = ?1, attempts = ?2, done_at = ?3, lock_by = ?4, lock_at = ?5, last_error = ?6 WHERE id = ?7";
+                "UPDATE Jobs SET status = ?1, attempts = ?2, done_at = ?3, lock_by = ?4, lock_at = ?5, last_error = ?6, last_failure = ?7 WHERE id = ?8";
         sqlx::query(query)
             .bind(status.to_owned())
             .bind(attempts)
@@ -405,6 +895,7 @@ where
             .bind(lock_by)
             .bind(lock_at)
             .bind(last_error)
+            .bind(last_failure)
             .bind(job_id.to_owned())
             .execute(&mut tx)
             .await
@@ -412,6 +903,68 @@ where
         Ok(())
     }
 
+    async fn update_progress(
+        &mut self,
+        job_id: String,
+        progress: &JobProgress,
+    ) -> StorageResult<()> {
+        let progress = serde_json::to_string(progress)?;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "UPDATE Jobs SET progress = ?1 WHERE id = ?2";
+        let updated = sqlx::query(query)
+            .bind(progress)
+            .bind(job_id)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        if updated == 0 {
+            return Err(StorageError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn append_logs(
+        &mut self,
+        job_id: String,
+        lines: &[JobLogLine],
+        max_lines: usize,
+    ) -> StorageResult<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        for line in lines {
+            sqlx::query(
+                "INSERT INTO JobLogs (job_id, level, message, logged_at) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .bind(&job_id)
+            .bind(&line.level)
+            .bind(&line.message)
+            .bind(line.logged_at.timestamp())
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        }
+        // Only the last `max_lines` lines of the job are kept
+        let query = "DELETE FROM JobLogs WHERE job_id = ?1 AND id NOT IN (SELECT id FROM JobLogs WHERE job_id = ?1 ORDER BY id DESC LIMIT ?2)";
+        sqlx::query(query)
+            .bind(&job_id)
+            .bind(max_lines as i64)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        tx.commit()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(())
+    }
+
     async fn keep_alive<Service>(&mut self, worker_id: String) -> StorageResult<()> {
         let mut tx = self
             .pool
@@ -435,26 +988,121 @@ where
     }
 }
 
-#[async_trait::async_trait]
+/// Puts a killed job back into the queue, see [DeadLetter]
+const REQUEUE_DEAD: &str = "status = 'Pending', attempts = 0, run_at = strftime('%s','now'), done_at = NULL, lock_by = NULL, lock_at = NULL, unique_key = NULL, unique_until = NULL, batch_id = NULL, cancel_requested = 0";
 
-impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for SqliteStorage<J> {
-    async fn counts(&mut self) -> Result<Counts, JobError> {
+#[async_trait::async_trait]
+impl<T> DeadLetter for SqliteStorage<T>
+where
+    T: Job + Serialize + DeserializeOwned + Send + 'static + Unpin,
+{
+    async fn list_dead(&self, page: i32) -> StorageResult<Vec<JobRequest<T>>> {
         let mut conn = self
             .pool
-            .clone()
             .acquire()
             .await
             .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "SELECT * FROM Jobs WHERE status = 'Killed' AND job_type = ?1 ORDER BY done_at DESC LIMIT 10 OFFSET ?2";
+        let jobs: Vec<SqlJobRequest<T>> = sqlx::query_as(query)
+            .bind(T::NAME)
+            .bind((page - 1) * 10)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(jobs.into_iter().map(Into::into).collect())
+    }
 
-        let fetch_query = "SELECT
-                            COUNT(1) FILTER (WHERE status = 'Pending') AS pending, 
-                            COUNT(1) FILTER (WHERE status = 'Running') AS running,
-                            COUNT(1) FILTER (WHERE status = 'Done') AS done,
-                            COUNT(1) FILTER (WHERE status = 'Retry') AS retry, 
-                            COUNT(1) FILTER (WHERE status = 'Failed') AS failed, 
-                            COUNT(1) FILTER (WHERE status = 'Killed') AS killed
+    async fn requeue_dead(&mut self, job_ids: &[JobId]) -> StorageResult<u64> {
+        if job_ids.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let ids = vec!["?"; job_ids.len()].join(", ");
+        let query = format!(
+            "UPDATE Jobs SET {REQUEUE_DEAD} WHERE status = 'Killed' AND job_type = ? AND id IN ({ids})"
+        );
+        let mut query = sqlx::query(&query).bind(T::NAME);
+        for id in job_ids {
+            query = query.bind(id.to_string());
+        }
+        let requeued = query
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(requeued)
+    }
+
+    async fn requeue_dead_matching(&mut self, filter: &DeadLetterFilter) -> StorageResult<u64> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = format!(
+            "UPDATE Jobs SET {REQUEUE_DEAD} WHERE status = 'Killed' AND job_type = ?1
+                AND (?2 IS NULL OR instr(last_error, ?2) > 0)
+                AND (?3 IS NULL OR done_at >= ?3)
+                AND (?4 IS NULL OR done_at < ?4)"
+        );
+        let requeued = sqlx::query(&query)
+            .bind(T::NAME)
+            .bind(filter.error_contains())
+            .bind(filter.killed_after().map(DateTime::timestamp))
+            .bind(filter.killed_before().map(DateTime::timestamp))
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(requeued)
+    }
+
+    async fn purge_dead(&mut self, age: Duration) -> StorageResult<u64> {
+        let killed_before = Utc::now()
+            - chrono::Duration::from_std(age).map_err(|e| StorageError::Database(Box::from(e)))?;
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query = "DELETE FROM Jobs WHERE status = 'Killed' AND job_type = ?1 AND done_at < ?2";
+        let purged = sqlx::query(query)
+            .bind(T::NAME)
+            .bind(killed_before.timestamp())
+            .execute(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?
+            .rows_affected();
+        Ok(purged)
+    }
+}
+
+#[async_trait::async_trait]
+
+impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for SqliteStorage<J> {
+    async fn counts(&mut self) -> Result<Counts, JobError> {
+        let mut conn = self
+            .pool
+            .clone()
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+
+        let fetch_query = "SELECT
+                            COUNT(1) FILTER (WHERE status = 'Pending') AS pending, 
+                            COUNT(1) FILTER (WHERE status = 'Running') AS running,
+                            COUNT(1) FILTER (WHERE status = 'Done') AS done,
+                            COUNT(1) FILTER (WHERE status = 'Retry') AS retry, 
+                            COUNT(1) FILTER (WHERE status = 'Failed') AS failed, 
+                            COUNT(1) FILTER (WHERE status = 'Killed') AS killed,
+                            COUNT(1) FILTER (WHERE status = 'Blocked') AS blocked,
+                            COUNT(1) FILTER (WHERE status = 'Cancelled') AS cancelled
                         FROM Jobs WHERE job_type = ?";
-        let res: (i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
+        let res: (i64, i64, i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
             .bind(J::NAME)
             .fetch_one(&mut conn)
             .await
@@ -466,6 +1114,8 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for Sqlite
         inner.insert(JobState::Retry, res.3);
         inner.insert(JobState::Failed, res.4);
         inner.insert(JobState::Killed, res.5);
+        inner.insert(JobState::Blocked, res.6);
+        inner.insert(JobState::Cancelled, res.7);
         Ok(Counts { inner })
     }
 
@@ -493,6 +1143,29 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for Sqlite
         Ok(res.into_iter().map(|j| j.into()).collect())
     }
 
+    async fn job_logs(&mut self, job_id: String) -> Result<Vec<JobLogLine>, JobError> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        let query =
+            "SELECT level, message, logged_at FROM JobLogs WHERE job_id = ?1 ORDER BY id ASC";
+        let lines: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(query)
+            .bind(job_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| StorageError::Database(Box::from(e)))?;
+        Ok(lines
+            .into_iter()
+            .map(|(level, message, logged_at)| JobLogLine {
+                level,
+                message,
+                logged_at,
+            })
+            .collect())
+    }
+
     async fn list_workers(&mut self) -> Result<Vec<JobStreamWorker>, JobError> {
         let mut conn = self
             .pool
@@ -523,9 +1196,28 @@ impl<J: 'static + Job + Serialize + DeserializeOwned> JobStreamExt<J> for Sqlite
 mod tests {
 
     use super::*;
+    use apalis_core::builder::{WorkerBuilder, WorkerFactoryFn};
+    use apalis_core::context::JobContext;
+    use apalis_core::job::Backoff;
+    use apalis_core::job_fn::job_fn;
+    use apalis_core::monitor::Monitor;
+    use apalis_core::response::JobResult;
+    use apalis_core::storage::builder::{StorageWorkerConfig, WithStorage};
+    use apalis_core::storage::AckLayer;
+    use apalis_core::utils::timer::TokioTimer;
+    use apalis_core::worker::{timeout::JobTimeout, WorkerEvent, WorkerListener, WorkerRef};
+    use chrono::SubsecRound;
     use email_service::Email;
     use futures::StreamExt;
+    use serde::Deserialize;
+    use sqlx::types::Uuid;
     use std::ops::Sub;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use tempfile::TempDir;
+    use tower::{Layer, Service};
 
     /// migrate DB and return a storage instance.
     async fn setup() -> SqliteStorage<Email> {
@@ -539,6 +1231,19 @@ mod tests {
         storage
     }
 
+    /// migrate a DB in a file, shared by the connections of a worker, and return a storage
+    /// instance along with the directory of the file, which is removed once dropped.
+    async fn setup_file() -> (TempDir, SqliteStorage<Email>) {
+        let dir = TempDir::new().expect("failed to create a temporary directory");
+        let path = dir.path().join("apalis.db");
+        let storage =
+            SqliteStorage::<Email>::connect(format!("sqlite://{}?mode=rwc", path.display()))
+                .await
+                .expect("failed to connect DB server");
+        storage.setup().await.expect("failed to migrate DB");
+        (dir, storage)
+    }
+
     #[tokio::test]
     async fn test_inmemory_sqlite_worker() {
         let mut sqlite = SqliteStorage::<Email>::connect("sqlite::memory:")
@@ -560,6 +1265,16 @@ mod tests {
 
     struct DummyService {}
 
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Report {
+        id: u32,
+    }
+
+    impl Job for Report {
+        const NAME: &'static str = "apalis::Report";
+        const BACKOFF: Backoff = Backoff::fixed(Duration::from_secs(60));
+    }
+
     fn example_email() -> Email {
         Email {
             subject: "Test Subject".to_string(),
@@ -572,7 +1287,7 @@ mod tests {
     where
         S: Storage<Output = T>,
     {
-        let mut stream = storage.consume(worker_id, std::time::Duration::from_secs(10));
+        let mut stream = storage.consume(worker_id, std::time::Duration::from_secs(10), 1);
         stream
             .next()
             .await
@@ -671,6 +1386,105 @@ mod tests {
         assert!(job.context().done_at().is_some());
     }
 
+    #[tokio::test]
+    async fn test_dead_letter_requeues_and_purges_killed_jobs() {
+        let mut storage = setup().await;
+        let worker_id = register_worker(&mut storage).await;
+        let mut ids = Vec::new();
+        for error in ["payment gateway down", "invalid card"] {
+            storage
+                .push_with(example_email(), PushOptions::new().with_max_attempts(1))
+                .await
+                .expect("failed to push a job");
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            ids.push(job.context().id());
+            let mut service = AckLayer::new(WorkerRef::new(worker_id.clone()), storage.clone())
+                .layer(job_fn(move |_: Email, _: JobContext| async move {
+                    Err::<(), _>(error)
+                }));
+            assert!(service.call(job).await.is_err());
+        }
+
+        let dead = storage
+            .list_dead(1)
+            .await
+            .expect("failed to list dead jobs");
+        assert_eq!(dead.len(), 2);
+        assert!(dead.iter().all(|job| job.context().last_error().is_some()));
+
+        let filter = DeadLetterFilter::new().with_error_containing("gateway");
+        let requeued = storage
+            .requeue_dead_matching(&filter)
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 1);
+        let job = get_job(&mut storage, ids[0].clone()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 0);
+
+        let card: JobId = ids[1].parse().unwrap();
+        let requeued = storage
+            .requeue_dead(&[card])
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 1);
+        let requeued = storage
+            .requeue_dead(&[card])
+            .await
+            .expect("failed to requeue dead jobs");
+        assert_eq!(requeued, 0);
+
+        // Only jobs killed long enough ago are purged
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+        let purged = storage
+            .purge_dead(Duration::from_secs(3600))
+            .await
+            .expect("failed to purge dead jobs");
+        assert_eq!(purged, 0);
+        let mut job = get_job(&mut storage, job.context().id()).await;
+        job.set_done_at(Some(Utc::now() - chrono::Duration::hours(2)));
+        storage
+            .update_by_id(job.context().id(), &job)
+            .await
+            .expect("failed to update job");
+        let purged = storage
+            .purge_dead(Duration::from_secs(3600))
+            .await
+            .expect("failed to purge dead jobs");
+        assert_eq!(purged, 1);
+        assert!(storage
+            .list_dead(1)
+            .await
+            .expect("failed to list dead jobs")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reenqueue_active_jobs() {
+        let mut storage = setup().await;
+
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        storage
+            .reenqueue_active(worker_id.clone())
+            .await
+            .expect("failed to reenqueue active jobs");
+
+        let job = get_job(&mut storage, job_id.clone()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert!(job.context().lock_by().is_none());
+        assert!(job.context().lock_at().is_none());
+    }
+
     #[tokio::test]
     async fn test_heartbeat_renqueueorphaned_pulse_last_seen_6min() {
         let mut storage = setup().await;
@@ -682,7 +1496,10 @@ mod tests {
 
         let job = consume_one(&mut storage, worker_id.clone()).await;
         let result = storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .expect("failed to heartbeat");
         assert!(result);
@@ -711,7 +1528,10 @@ mod tests {
 
         let job = consume_one(&mut storage, worker_id.clone()).await;
         let result = storage
-            .heartbeat(StorageWorkerPulse::RenqueueOrpharned { count: 5 })
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(300),
+            })
             .await
             .expect("failed to heartbeat");
         assert!(result);
@@ -722,4 +1542,1125 @@ mod tests {
         assert_eq!(*job.context().status(), JobState::Running);
         assert_eq!(*job.context().lock_by(), Some(worker_id.clone()));
     }
+
+    #[tokio::test]
+    async fn test_heartbeat_renqueueorphaned_pulse_custom_timeout() {
+        let mut storage = setup().await;
+
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id =
+            register_worker_at(&mut storage, Utc::now().sub(chrono::Duration::minutes(2))).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .heartbeat(StorageWorkerPulse::RenqueueOrpharned {
+                count: 5,
+                timeout_worker: Duration::from_secs(60),
+            })
+            .await
+            .expect("failed to heartbeat");
+
+        let job_id = job.context().id();
+        let job = get_job(&mut storage, job_id.clone()).await;
+
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert!(job.context().lock_by().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_consume_claims_whole_batch_at_once() {
+        let mut storage = setup().await;
+        for _ in 0..3 {
+            push_email(&mut storage, example_email()).await;
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut stream = storage.consume(worker_id.clone(), Duration::from_secs(10), 2);
+        stream
+            .next()
+            .await
+            .expect("stream is empty")
+            .expect("failed to poll job")
+            .expect("no job is pending");
+
+        let (claimed,): (i64,) =
+            sqlx::query_as("SELECT count(*) FROM Jobs WHERE status = 'Running' AND lock_by = ?1")
+                .bind(worker_id)
+                .fetch_one(&storage.pool)
+                .await
+                .expect("failed to count claimed jobs");
+        assert_eq!(claimed, 2);
+    }
+
+    #[tokio::test]
+    async fn test_consume_prefers_higher_priority() {
+        let mut storage = setup().await;
+        let now = Utc::now().trunc_subsecs(0);
+        let mut ids = Vec::new();
+        // Pushed from the lowest to the highest expected position
+        for (priority, minutes) in [(0, 3), (5, 1), (5, 2), (10, 0)] {
+            let options = PushOptions::new()
+                .with_priority(priority)
+                .with_run_at(now.sub(chrono::Duration::minutes(minutes)));
+            let id = storage
+                .push_with(example_email(), options)
+                .await
+                .expect("failed to push a job");
+            ids.push(id.to_string());
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut stream = storage.consume(worker_id.clone(), Duration::from_secs(10), 4);
+        let mut consumed = Vec::new();
+        for _ in 0..4 {
+            let job = stream
+                .next()
+                .await
+                .expect("stream is empty")
+                .expect("failed to poll job")
+                .expect("no job is pending");
+            consumed.push(job.context().id());
+        }
+        ids.reverse();
+        assert_eq!(consumed, ids);
+    }
+
+    #[tokio::test]
+    async fn test_consume_fetches_up_to_buffer_size() {
+        let mut storage = setup().await;
+        for _ in 0..3 {
+            push_email(&mut storage, example_email()).await;
+        }
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut stream = storage.consume(worker_id.clone(), Duration::from_secs(10), 2);
+        let mut jobs = Vec::new();
+        for _ in 0..2 {
+            let job = stream
+                .next()
+                .await
+                .expect("stream is empty")
+                .expect("failed to poll job")
+                .expect("no job is pending");
+            jobs.push(job);
+        }
+
+        for job in &jobs {
+            assert_eq!(*job.context().status(), JobState::Running);
+            assert_eq!(*job.context().lock_by(), Some(worker_id.clone()));
+        }
+        assert_ne!(jobs[0].context().id(), jobs[1].context().id());
+
+        let last = consume_one(&mut storage, worker_id.clone()).await;
+        assert!(jobs
+            .iter()
+            .all(|job| job.context().id() != last.context().id()));
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_acknowledges_successful_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone()).layer(job_fn(
+            |_: Email, _: JobContext| async { Ok::<_, JobError>(()) },
+        ));
+        service.call(job).await.expect("job should succeed");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Done);
+        assert!(job.context().done_at().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_saves_job_result() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone())
+            .with_result_retention(Duration::from_secs(60))
+            .layer(job_fn(|_: Email, _: JobContext| async {
+                Ok::<_, JobError>("https://reports/1.pdf".to_string())
+            }));
+        service.call(job).await.expect("job should succeed");
+
+        let result = storage
+            .fetch_result(job_id.clone())
+            .await
+            .expect("failed to fetch result");
+        assert_eq!(result, Some(serde_json::json!("https://reports/1.pdf")));
+
+        // A result past its retention is no longer returned
+        storage
+            .save_result(job_id.clone(), serde_json::json!(null), Duration::ZERO)
+            .await
+            .expect("failed to save result");
+        let result = storage
+            .fetch_result(job_id)
+            .await
+            .expect("failed to fetch result");
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_writes_job_progress() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let store = storage.clone();
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone())
+            .with_progress_interval(Duration::from_secs(60))
+            .layer(job_fn(move |_: Email, ctx: JobContext| {
+                let store = store.clone();
+                async move {
+                    let progress = ctx.progress_reporter();
+                    progress.report(10, None).await;
+                    progress.report(50, Some("halfway".to_string())).await;
+
+                    // Only the first report was written, the second waits for the interval
+                    let job = store.fetch_by_id(ctx.id()).await.unwrap().unwrap();
+                    assert_eq!(job.context().progress().as_ref().unwrap().percent, 10);
+                    Ok::<_, JobError>(())
+                }
+            }));
+        service.call(job).await.expect("job should succeed");
+
+        // The last report is written once the job finishes
+        let job = get_job(&mut storage, job_id).await;
+        let progress = job
+            .context()
+            .progress()
+            .clone()
+            .expect("progress should be saved");
+        assert_eq!(progress.percent, 50);
+        assert_eq!(progress.message.as_deref(), Some("halfway"));
+
+        let res = storage
+            .update_progress("unknown".to_string(), &JobProgress::new(10, None))
+            .await;
+        assert!(matches!(res, Err(StorageError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_stores_job_logs() {
+        let mut storage = setup().await;
+        storage
+            .push_with(example_email(), PushOptions::new().with_max_attempts(1))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone())
+            .with_job_logs(2)
+            .layer(job_fn(|_: Email, ctx: JobContext| async move {
+                ctx.logger().info("connecting");
+                ctx.logger().info("sending");
+                ctx.logger().error("mailbox full");
+                Err::<(), _>("mailbox full")
+            }));
+        assert!(service.call(job).await.is_err());
+
+        let logs = |lines: Vec<JobLogLine>| -> Vec<(String, String)> {
+            lines.into_iter().map(|l| (l.level, l.message)).collect()
+        };
+        let lines = storage.job_logs(job_id.clone()).await.unwrap();
+        assert_eq!(
+            logs(lines),
+            vec![
+                ("INFO".to_string(), "sending".to_string()),
+                ("ERROR".to_string(), "mailbox full".to_string())
+            ]
+        );
+
+        // Appending keeps the last lines of the job
+        let line = JobLogLine {
+            level: "WARN".to_string(),
+            message: "requeued".to_string(),
+            logged_at: Utc::now(),
+        };
+        storage
+            .append_logs(job_id.clone(), &[line], 2)
+            .await
+            .expect("failed to append logs");
+        let lines = storage.job_logs(job_id.clone()).await.unwrap();
+        assert_eq!(
+            logs(lines),
+            vec![
+                ("ERROR".to_string(), "mailbox full".to_string()),
+                ("WARN".to_string(), "requeued".to_string())
+            ]
+        );
+
+        // The logs are deleted along with the job
+        let mut job = get_job(&mut storage, job_id.clone()).await;
+        assert_eq!(*job.context().status(), JobState::Killed);
+        job.set_done_at(Some(Utc::now() - chrono::Duration::hours(2)));
+        storage
+            .update_by_id(job_id.clone(), &job)
+            .await
+            .expect("failed to update job");
+        storage
+            .purge_dead(Duration::from_secs(3600))
+            .await
+            .expect("failed to purge dead jobs");
+        assert!(storage.job_logs(job_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_retries_failed_job() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone()).layer(job_fn(
+            |_: Email, _: JobContext| async { Err::<(), _>("smtp unavailable") },
+        ));
+        assert!(service.call(job).await.is_err());
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 1);
+        assert!(job.context().lock_by().is_none());
+        assert_eq!(
+            *job.context().last_error(),
+            Some("Job Failed: smtp unavailable".to_string())
+        );
+        let failure = job
+            .context()
+            .last_failure()
+            .clone()
+            .expect("failure was not recorded");
+        assert_eq!(failure.attempt, 1);
+        assert_eq!(failure.message, "Job Failed: smtp unavailable");
+        assert_eq!(failure.chain, vec!["smtp unavailable".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_reschedules_failed_job_after_backoff() {
+        let mut storage = SqliteStorage::<Report>::connect("sqlite::memory:")
+            .await
+            .expect("failed to connect DB server");
+        storage.setup().await.expect("failed to migrate DB");
+        storage
+            .push(Report { id: 1 })
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = Uuid::new_v4().to_string();
+        storage
+            .keep_alive::<DummyService>(worker_id.clone())
+            .await
+            .expect("failed to register worker");
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone()).layer(job_fn(
+            |_: Report, _: JobContext| async { Err::<(), _>("renderer unavailable") },
+        ));
+        assert!(service.call(job).await.is_err());
+
+        let job = storage
+            .fetch_by_id(job_id)
+            .await
+            .expect("failed to fetch job by id")
+            .expect("no job found by id");
+        assert_eq!(*job.context().status(), JobState::Failed);
+        assert_eq!(job.context().attempts(), 1);
+        assert_eq!(
+            *job.context().last_error(),
+            Some("Job Failed: renderer unavailable".to_string())
+        );
+        assert!(*job.context().run_at() > Utc::now() + chrono::Duration::seconds(50));
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_fails_job_that_times_out() {
+        let mut storage = setup().await;
+        let options = PushOptions::new().with_timeout(Duration::from_millis(50));
+        storage
+            .push_with(example_email(), options)
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let handler = job_fn(|_: Email, _: JobContext| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<_, JobError>(())
+        });
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone())
+            .layer(JobTimeout::new(handler, TokioTimer));
+        let res = tokio::time::timeout(Duration::from_secs(1), service.call(job))
+            .await
+            .expect("job timeout was not enforced");
+        assert!(matches!(res, Err(JobError::TimedOut(_))));
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert_eq!(job.context().attempts(), 1);
+        assert_eq!(
+            *job.context().last_error(),
+            Some("Job timed out after 50ms".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_kills_job_after_max_attempts() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let mut job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+        let max_attempts = job.max_attempts();
+        job.set_attempts(max_attempts - 1);
+        storage
+            .update_by_id(job_id.clone(), &job)
+            .await
+            .expect("failed to update job");
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone()).layer(job_fn(
+            |_: Email, _: JobContext| async { Err::<(), _>("smtp unavailable") },
+        ));
+        assert!(service.call(job).await.is_err());
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Killed);
+        assert_eq!(job.context().attempts(), job.context().max_attempts());
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_kills_job_on_kill_result() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone())
+            .layer(job_fn(|_: Email, _: JobContext| async { JobResult::Kill }));
+        service.call(job).await.expect("job should complete");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Killed);
+    }
+
+    #[tokio::test]
+    async fn test_ack_layer_reschedules_job_on_reschedule_result() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        let mut service = AckLayer::new(WorkerRef::new(worker_id), storage.clone()).layer(job_fn(
+            |_: Email, _: JobContext| async { JobResult::Reschedule(Duration::from_secs(600)) },
+        ));
+        service.call(job).await.expect("job should complete");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert!(job.context().lock_by().is_none());
+        assert!(*job.context().run_at() > Utc::now().add(chrono::Duration::minutes(9)));
+    }
+
+    #[tokio::test]
+    async fn test_push_returns_job_id() {
+        let mut storage = setup().await;
+        let job_id = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+
+        let job = get_job(&mut storage, job_id.to_string()).await;
+        assert_eq!(job.context().id(), job_id.to_string());
+        assert_eq!(job.context().max_attempts(), 25);
+    }
+
+    #[tokio::test]
+    async fn test_push_with_options() {
+        let mut storage = setup().await;
+        let job_id = JobId::new();
+        let run_at = Utc::now().add(chrono::Duration::hours(1)).trunc_subsecs(0);
+        let options = PushOptions::new()
+            .with_id(job_id)
+            .with_max_attempts(3)
+            .with_run_at(run_at)
+            .with_timeout(Duration::from_millis(1500))
+            .with_priority(7);
+        let pushed = storage
+            .push_with(example_email(), options)
+            .await
+            .expect("failed to push a job");
+        assert_eq!(pushed, job_id);
+
+        let job = get_job(&mut storage, job_id.to_string()).await;
+        assert_eq!(job.context().max_attempts(), 3);
+        assert_eq!(*job.context().run_at(), run_at);
+        assert_eq!(job.context().timeout(), Some(Duration::from_millis(1500)));
+        assert_eq!(job.context().priority(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_push_with_unique_key() {
+        let mut storage = setup().await;
+        let key = Uuid::new_v4().to_string();
+        let unique = |policy| PushOptions::new().with_unique_key(key.clone(), policy);
+
+        let first = storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+        match storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, first),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+        let ignored = storage
+            .push_with(example_email(), unique(UniquePolicy::Ignore))
+            .await
+            .expect("failed to push a job");
+        assert_eq!(ignored, first);
+
+        // The pending job is replaced
+        let replaced = storage
+            .push_with(example_email(), unique(UniquePolicy::Replace))
+            .await
+            .expect("failed to push a job");
+        assert_ne!(replaced, first);
+        assert!(storage
+            .fetch_by_id(first.to_string())
+            .await
+            .expect("failed to fetch job")
+            .is_none());
+
+        // A running job is not
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), replaced.to_string());
+        assert!(matches!(
+            storage
+                .push_with(example_email(), unique(UniquePolicy::Replace))
+                .await,
+            Err(StorageError::Duplicate(_))
+        ));
+
+        // The key is released once the job is done
+        storage
+            .ack(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to ack job");
+        storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+    }
+
+    #[tokio::test]
+    async fn test_unique_key_is_released_after_ttl() {
+        let mut storage = setup().await;
+        let options = PushOptions::new()
+            .with_unique_key(Uuid::new_v4().to_string(), UniquePolicy::Reject)
+            .with_unique_ttl(Duration::ZERO);
+
+        storage
+            .push_with(example_email(), options.clone())
+            .await
+            .expect("failed to push a job");
+        storage
+            .push_with(example_email(), options)
+            .await
+            .expect("the expired key should be free");
+    }
+
+    #[tokio::test]
+    async fn test_child_job_runs_after_parents_are_done() {
+        let mut storage = setup().await;
+        let first = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let second = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(
+                example_email(),
+                PushOptions::new().with_parents([first, second]),
+            )
+            .await
+            .expect("failed to push a job");
+        let job = get_job(&mut storage, child.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Blocked);
+
+        let worker_id = register_worker(&mut storage).await;
+        for parent in [first, second] {
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            assert_eq!(job.context().id(), parent.to_string());
+            storage
+                .ack(worker_id.clone(), job.context().id())
+                .await
+                .expect("failed to ack job");
+        }
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        assert_eq!(job.context().id(), child.to_string());
+
+        // Parents that are already done do not block
+        let unblocked = storage
+            .push_with(example_email(), PushOptions::new().with_parents([first]))
+            .await
+            .expect("failed to push a job");
+        let job = get_job(&mut storage, unblocked.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_unique_job_with_dependents_is_not_replaced() {
+        let mut storage = setup().await;
+        let key = Uuid::new_v4().to_string();
+        let unique = |policy| PushOptions::new().with_unique_key(key.clone(), policy);
+        let parent = storage
+            .push_with(example_email(), unique(UniquePolicy::Reject))
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+
+        // Replacing the parent would leave its child blocked forever
+        match storage
+            .push_with(example_email(), unique(UniquePolicy::Replace))
+            .await
+        {
+            Err(StorageError::Duplicate(holder)) => assert_eq!(holder, parent),
+            res => panic!("expected a duplicate, got {res:?}"),
+        }
+        let job = get_job(&mut storage, child.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Blocked);
+        get_job(&mut storage, parent.to_string()).await;
+    }
+
+    #[tokio::test]
+    async fn test_child_job_is_killed_with_parent() {
+        let mut storage = setup().await;
+        let parent = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+        let grandchild = storage
+            .push_with(example_email(), PushOptions::new().with_parents([child]))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+
+        for id in [child, grandchild] {
+            let job = get_job(&mut storage, id.to_string()).await;
+            assert_eq!(*job.context().status(), JobState::Killed);
+        }
+        let late = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+        let job = get_job(&mut storage, late.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Killed);
+        assert!(matches!(
+            storage
+                .push_with(
+                    example_email(),
+                    PushOptions::new().with_parents([JobId::new()])
+                )
+                .await,
+            Err(StorageError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_job_with_children() {
+        let mut storage = setup().await;
+        let parent = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+        let child = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+
+        storage
+            .cancel(parent.to_string())
+            .await
+            .expect("failed to cancel job");
+        for id in [parent, child] {
+            let job = get_job(&mut storage, id.to_string()).await;
+            assert_eq!(*job.context().status(), JobState::Cancelled);
+        }
+        let child = get_job(&mut storage, child.to_string()).await;
+        assert_eq!(
+            child.context().last_error().as_deref(),
+            Some("A parent job was cancelled")
+        );
+        let late = storage
+            .push_with(example_email(), PushOptions::new().with_parents([parent]))
+            .await
+            .expect("failed to push a job");
+        let job = get_job(&mut storage, late.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Cancelled);
+
+        assert!(matches!(
+            storage.cancel(parent.to_string()).await,
+            Err(StorageError::NotFound)
+        ));
+        let worker_id = register_worker(&mut storage).await;
+        let mut stream = storage.consume(worker_id, Duration::from_millis(10), 1);
+        assert!(stream.next().await.unwrap().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_job_flags_it() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+
+        storage
+            .cancel(job_id.clone())
+            .await
+            .expect("failed to cancel job");
+        let job = get_job(&mut storage, job_id.clone()).await;
+        assert_eq!(*job.context().status(), JobState::Running);
+        let cancelled = storage
+            .fetch_cancelled(worker_id.clone())
+            .await
+            .expect("failed to fetch cancelled jobs");
+        assert_eq!(cancelled, vec![job_id.clone()]);
+
+        storage
+            .abort(worker_id, job_id.clone())
+            .await
+            .expect("failed to abort job");
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_batch_pushes_success_callback() {
+        let mut storage = setup().await;
+        let callbacks = BatchCallbacks::new()
+            .with_on_success(example_email())
+            .with_on_failure(example_email());
+        let batch_id = storage
+            .open_batch(callbacks)
+            .await
+            .expect("failed to open batch");
+        for _ in 0..2 {
+            storage
+                .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+                .await
+                .expect("failed to push a job");
+        }
+        storage
+            .close_batch(&batch_id)
+            .await
+            .expect("failed to close batch");
+        assert!(matches!(
+            storage
+                .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+                .await,
+            Err(StorageError::NotFound)
+        ));
+
+        let worker_id = register_worker(&mut storage).await;
+        for done in 1..=2 {
+            let job = consume_one(&mut storage, worker_id.clone()).await;
+            storage
+                .ack(worker_id.clone(), job.context().id())
+                .await
+                .expect("failed to ack job");
+            let progress = storage
+                .batch_progress(&batch_id)
+                .await
+                .expect("failed to fetch progress")
+                .expect("no batch found");
+            assert_eq!(progress.total, 2);
+            assert_eq!(progress.done, done);
+            assert_eq!(progress.pending(), 2 - done);
+        }
+
+        // Only the success callback is left
+        assert_eq!(storage.len().await.expect("failed to count jobs"), 1);
+        let counts = storage.counts().await.expect("failed to count jobs");
+        assert_eq!(counts.inner[&JobState::Blocked], 0);
+        assert_eq!(counts.inner[&JobState::Done], 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_pushes_failure_callback() {
+        let mut storage = setup().await;
+        let callbacks = BatchCallbacks::new().with_on_success(example_email());
+        let batch_id = storage
+            .open_batch(callbacks)
+            .await
+            .expect("failed to open batch");
+        storage
+            .push_with(example_email(), PushOptions::new().with_batch(batch_id))
+            .await
+            .expect("failed to push a job");
+
+        let worker_id = register_worker(&mut storage).await;
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        storage
+            .kill(worker_id.clone(), job.context().id())
+            .await
+            .expect("failed to kill job");
+        let progress = storage
+            .batch_progress(&batch_id)
+            .await
+            .expect("failed to fetch progress")
+            .expect("no batch found");
+        assert_eq!(progress.failed, 1);
+        assert!(!progress.is_finished());
+
+        storage
+            .close_batch(&batch_id)
+            .await
+            .expect("failed to close batch");
+        let progress = storage
+            .batch_progress(&batch_id)
+            .await
+            .expect("failed to fetch progress")
+            .expect("no batch found");
+        assert!(progress.is_finished());
+        // There is no failure callback, and the success callback is discarded
+        let counts = storage.counts().await.expect("failed to count jobs");
+        assert_eq!(counts.inner[&JobState::Blocked], 0);
+        assert_eq!(counts.inner[&JobState::Pending], 0);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_enqueue_scheduled_skips_future_jobs() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+        storage
+            .reschedule(worker_id.clone(), &job, Duration::from_secs(600))
+            .await
+            .expect("failed to reschedule job");
+
+        storage
+            .heartbeat(StorageWorkerPulse::EnqueueScheduled { count: 10 })
+            .await
+            .expect("failed to heartbeat");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_worker_heartbeat_enqueues_scheduled_jobs() {
+        let (_dir, mut storage) = setup_file().await;
+        push_email(&mut storage, example_email()).await;
+
+        let worker_id = register_worker(&mut storage).await;
+
+        let job = consume_one(&mut storage, worker_id.clone()).await;
+        let job_id = job.context().id();
+        storage
+            .reschedule(worker_id.clone(), &job, Duration::ZERO)
+            .await
+            .expect("failed to reschedule job");
+
+        let config = StorageWorkerConfig::new()
+            .with_enqueue_scheduled(Duration::from_millis(10), 10)
+            .without_reenqueue_orphaned();
+        let worker = WorkerBuilder::new("sqlite-heartbeat")
+            .with_storage_config(storage.clone(), config)
+            .build_fn(|_: Email, _: JobContext| async { Ok::<_, JobError>(()) });
+        Monitor::new()
+            .register(worker)
+            .run_with_signal(async {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                Ok(())
+            })
+            .await
+            .expect("failed to run monitor");
+
+        let job = get_job(&mut storage, job_id).await;
+        assert_eq!(*job.context().status(), JobState::Done);
+    }
+
+    #[tokio::test]
+    async fn test_worker_reenqueues_inflight_jobs_on_shutdown_timeout() {
+        let (_dir, mut storage) = setup_file().await;
+        let job_id = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+
+        let started = Arc::new(AtomicBool::new(false));
+        let job_started = started.clone();
+        let worker = WorkerBuilder::new("sqlite-shutdown")
+            .with_storage(storage.clone())
+            .build_fn(move |_: Email, _: JobContext| {
+                let started = job_started.clone();
+                async move {
+                    started.store(true, Ordering::SeqCst);
+                    futures::future::pending::<()>().await;
+                    Ok::<_, JobError>(())
+                }
+            });
+        let res = Monitor::new()
+            .register(worker)
+            .shutdown_timeout(Duration::from_millis(100))
+            .run_with_signal(async move {
+                // Only shut down once the worker has started running the job
+                while !started.load(Ordering::SeqCst) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                Ok(())
+            })
+            .await;
+        assert_eq!(
+            res.expect_err("shutdown should time out").kind(),
+            std::io::ErrorKind::TimedOut
+        );
+
+        let job = get_job(&mut storage, job_id.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Pending);
+        assert!(job.context().lock_by().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_worker_cancels_running_job_on_keep_alive() {
+        let (_dir, mut storage) = setup_file().await;
+        let job_id = storage
+            .push(example_email())
+            .await
+            .expect("failed to push a job");
+
+        let config = StorageWorkerConfig::new().with_keep_alive(Duration::from_millis(50));
+        let worker = WorkerBuilder::new("sqlite-cancel")
+            .with_storage_config(storage.clone(), config)
+            .build_fn(|_: Email, ctx: JobContext| async move {
+                ctx.cancellation_token().cancelled().await;
+                Err::<(), _>(JobError::Cancelled)
+            });
+        let mut check = storage.clone();
+        let _ = Monitor::new()
+            .register(worker)
+            .shutdown_timeout(Duration::from_millis(100))
+            .run_with_signal(async move {
+                let id = job_id.to_string();
+                while *get_job(&mut check, id.clone()).await.context().status() != JobState::Running
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                check
+                    .cancel(id.clone())
+                    .await
+                    .expect("failed to cancel job");
+                while *get_job(&mut check, id.clone()).await.context().status()
+                    != JobState::Cancelled
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                Ok(())
+            })
+            .await;
+
+        let job = get_job(&mut storage, job_id.to_string()).await;
+        assert_eq!(*job.context().status(), JobState::Cancelled);
+        assert_eq!(job.context().attempts(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_worker_concurrency_limits_claimed_jobs() {
+        let (_dir, mut storage) = setup_file().await;
+        for _ in 0..3 {
+            push_email(&mut storage, example_email()).await;
+        }
+
+        let worker = WorkerBuilder::new("sqlite-concurrency")
+            .with_storage(storage.clone())
+            .concurrency(2)
+            .build_fn(|_: Email, _: JobContext| async {
+                futures::future::pending::<()>().await;
+                Ok::<_, JobError>(())
+            });
+        let mut check = storage.clone();
+        let claimed = Arc::new(AtomicUsize::new(0));
+        let seen = claimed.clone();
+        let _ = Monitor::new()
+            .register(worker)
+            .shutdown_timeout(Duration::from_millis(100))
+            .run_with_signal(async move {
+                let mut running = 0;
+                while running < 2 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    running = check
+                        .list_jobs(&JobState::Running, 1)
+                        .await
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+                        .len();
+                }
+                // Give the worker a chance to claim more jobs than it should
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                let jobs = check
+                    .list_jobs(&JobState::Running, 1)
+                    .await
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+                seen.store(jobs.len(), Ordering::SeqCst);
+                Ok(())
+            })
+            .await;
+        assert_eq!(claimed.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_worker_keep_alive_registers_worker() {
+        let storage = setup().await;
+
+        let config = StorageWorkerConfig::new().with_keep_alive(Duration::from_millis(50));
+        let worker = WorkerBuilder::new("sqlite-keep-alive")
+            .with_storage_config(storage.clone(), config)
+            .build_fn(|_: Email, _: JobContext| async { Ok::<_, JobError>(()) });
+        Monitor::new()
+            .register(worker)
+            .run_with_signal(async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok(())
+            })
+            .await
+            .expect("failed to run monitor");
+
+        let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM Workers WHERE id = ?1")
+            .bind("sqlite-keep-alive")
+            .fetch_one(&storage.pool)
+            .await
+            .expect("failed to count workers");
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_paused_worker_keeps_sending_keep_alives() {
+        let mut storage = setup().await;
+        push_email(&mut storage, example_email()).await;
+
+        let config = StorageWorkerConfig::new().with_keep_alive(Duration::from_millis(50));
+        let worker = WorkerBuilder::new("sqlite-paused")
+            .with_storage_config(storage.clone(), config)
+            .build_fn(|_: Email, _: JobContext| async { Ok::<_, JobError>(()) });
+        let monitor = Monitor::new().register(worker);
+        assert!(monitor.handle().pause("sqlite-paused"));
+        monitor
+            .run_with_signal(async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok(())
+            })
+            .await
+            .expect("failed to run monitor");
+
+        let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM Workers WHERE id = ?1")
+            .bind("sqlite-paused")
+            .fetch_one(&storage.pool)
+            .await
+            .expect("failed to count workers");
+        assert_eq!(count, 1);
+        let pending = storage
+            .list_jobs(&JobState::Pending, 1)
+            .await
+            .expect("failed to list jobs");
+        assert_eq!(pending.len(), 1);
+    }
+
+    struct EventCollector(Arc<Mutex<Vec<String>>>);
+
+    impl WorkerListener for EventCollector {
+        fn on_event(&self, worker_id: &str, event: &WorkerEvent) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{worker_id}: {event:?}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_keep_alive_failures_are_emitted() {
+        let storage = setup().await;
+        storage.pool.close().await;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let config = StorageWorkerConfig::new().with_keep_alive(Duration::from_millis(50));
+        let worker = WorkerBuilder::new("sqlite-keep-alive")
+            .with_storage_config(storage, config)
+            .listener(EventCollector(events.clone()))
+            .build_fn(|_: Email, _: JobContext| async { Ok::<_, JobError>(()) });
+        Monitor::new()
+            .register(worker)
+            .run_with_signal(async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                Ok(())
+            })
+            .await
+            .expect("failed to run monitor");
+
+        let events = events.lock().unwrap();
+        assert!(events
+            .iter()
+            .any(|e| e.starts_with("sqlite-keep-alive: Error(\"Failed to send keep-alive")));
+    }
 }
@@ -83,6 +83,9 @@
 #[cfg(feature = "redis")]
 #[cfg_attr(docsrs, doc(cfg(feature = "redis")))]
 pub mod redis {
+    #[cfg(feature = "redis-pubsub")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis-pubsub")))]
+    pub use apalis_redis::listener::{RedisPubSubListener, WorkerEventMessage};
     pub use apalis_redis::RedisStorage;
 }
 
@@ -130,7 +133,6 @@ pub mod cron {
 ///         .with_storage(storage.clone())
 ///         .build(send_email);
 /// }
-
 /// ```
 ///
 /// [`Layer`]: https://docs.rs/tower/latest/tower/trait.Layer.html
@@ -172,16 +174,20 @@ pub mod prelude {
         builder::WorkerFactoryFn,
         context::JobContext,
         error::JobError,
-        executor::Executor,
-        job::{Counts, Job, JobFuture, JobStreamExt},
+        executor::{Executor, TokioExecutor},
+        job::{Backoff, Counts, Job, JobFuture, JobId, JobStreamExt},
         job_fn::job_fn,
-        monitor::Monitor,
+        monitor::{Monitor, MonitorHandle, RestartPolicy, RestartStrategy},
         request::JobRequest,
         request::JobState,
-        response::IntoResponse,
-        storage::builder::WithStorage,
-        storage::Storage,
+        response::{IntoResponse, JobResult},
+        storage::builder::{StorageWorkerConfig, WithStorage},
         storage::StorageWorkerPulse,
+        storage::{
+            BatchCallbacks, BatchId, DeadLetter, DeadLetterFilter, PushOptions, Storage,
+            UniquePolicy,
+        },
         utils::*,
+        worker::WorkerContext,
     };
 }